@@ -0,0 +1,71 @@
+use cascading_tic_tac_toe::{
+    build_book_from_self_play, build_book_from_solver, EvalWeights, BOOK_PATH, WEIGHTS_PATH,
+};
+
+const USAGE: &str = "usage: build_book [--source solver|self-play] [--target N] [--plies N] \
+[--rounds K] [--games N] [--depth N] [--node-limit N] [--output PATH]";
+
+/// Where the book's moves come from
+enum Source {
+    Solver,
+    SelfPlay,
+}
+
+/// Headless entry point for building the opening book the computer plays from, e.g.
+/// `cargo run --release --bin build_book -- --source self-play --target 3 --plies 6`
+fn main() {
+    let mut source = Source::SelfPlay;
+    let mut target = 3;
+    let mut plies = 6;
+    let mut rounds = 1;
+    let mut games = 300;
+    let mut depth = 6;
+    let mut node_limit = 200_000;
+    let mut output = BOOK_PATH.to_string();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| exit_with_usage());
+        match flag.as_str() {
+            "--target" => target = parse(&value),
+            "--plies" => plies = parse(&value),
+            "--rounds" => rounds = parse(&value),
+            "--games" => games = parse(&value),
+            "--depth" => depth = parse(&value),
+            "--node-limit" => node_limit = parse(&value),
+            "--output" => output = value,
+            "--source" => {
+                source = match value.as_str() {
+                    "solver" => Source::Solver,
+                    "self-play" => Source::SelfPlay,
+                    _ => exit_with_usage(),
+                }
+            }
+            _ => exit_with_usage(),
+        }
+    }
+
+    let book = match source {
+        Source::Solver => build_book_from_solver(target, rounds, plies),
+        Source::SelfPlay => {
+            let weights = EvalWeights::load_or_default(WEIGHTS_PATH);
+            build_book_from_self_play(target, plies, games, depth, node_limit, &weights)
+        }
+    };
+    if let Err(error) = book.save(&output) {
+        eprintln!("could not write {}: {}", output, error);
+        std::process::exit(1);
+    }
+    println!("wrote {} positions to {}", book.len(), output);
+}
+
+/// Parse a numeric command line value
+fn parse<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| exit_with_usage())
+}
+
+/// Print the usage string and stop
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
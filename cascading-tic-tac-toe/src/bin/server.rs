@@ -0,0 +1,64 @@
+use std::net::TcpListener;
+use std::time::Duration;
+
+use cascading_tic_tac_toe::{serve, ServerConfig, DEFAULT_PORT};
+
+const USAGE: &str =
+    "usage: server [--port N] [--target N] [--timer SECONDS] [--grace SECONDS] [--games DIRECTORY] [--ratings FILE]";
+
+/// Headless entry point for the dedicated game server, e.g.
+/// `cargo run --release --bin server -- --port 7878 --target 3`
+/// Players connect to its lobby from the Play Online menu. The target and
+/// timer are the settings of quick matches, grace is how long a game waits
+/// for a player who lost their connection. Correspondence games are kept
+/// in the games directory, `correspondence` unless given, and player
+/// accounts and ratings in the ratings file, `ratings.txt` unless given.
+fn main() {
+    let mut port = DEFAULT_PORT;
+    let mut config = ServerConfig {
+        games_directory: Some("correspondence".into()),
+        ratings_path: Some("ratings.txt".into()),
+        ..ServerConfig::default()
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| exit_with_usage());
+        match flag.as_str() {
+            "--port" => port = parse(&value),
+            "--target" => config.quick_settings.target = parse(&value),
+            "--timer" => config.quick_settings.timer = parse(&value),
+            "--grace" => config.grace_period = Duration::from_secs(parse(&value)),
+            "--games" => config.games_directory = Some(value.into()),
+            "--ratings" => config.ratings_path = Some(value.into()),
+            _ => exit_with_usage(),
+        }
+    }
+
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("could not listen on port {}: {}", port, error);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "listening on port {}, quick matches are played to {} with {} seconds on the clock",
+        port, config.quick_settings.target, config.quick_settings.timer
+    );
+    if let Err(error) = serve(listener, config) {
+        eprintln!("server stopped: {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Parse a numeric command line value
+fn parse<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| exit_with_usage())
+}
+
+/// Print the usage string and stop
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
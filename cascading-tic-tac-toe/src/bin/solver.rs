@@ -0,0 +1,42 @@
+use cascading_tic_tac_toe::{solve, SearchMode, SolverConfig};
+
+const USAGE: &str =
+    "usage: solver [--target N] [--rounds K] [--mode exhaustive|pn] [--node-limit N]";
+
+/// Headless entry point for the solver
+/// Prints the value of the game and of each distinct first move, e.g.
+/// `cargo run --release --bin solver -- --target 2 --rounds 1`
+fn main() {
+    let mut config = SolverConfig::new(1, 0, SearchMode::Exhaustive);
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| exit_with_usage());
+        match flag.as_str() {
+            "--target" => config.target = parse(&value),
+            "--rounds" => config.max_rounds = parse(&value),
+            "--node-limit" => config.node_limit = parse(&value),
+            "--mode" => {
+                config.mode = match value.as_str() {
+                    "exhaustive" => SearchMode::Exhaustive,
+                    "pn" => SearchMode::ProofNumber,
+                    _ => exit_with_usage(),
+                }
+            }
+            _ => exit_with_usage(),
+        }
+    }
+
+    print!("{}", solve(config));
+}
+
+/// Parse a numeric command line value
+fn parse<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| exit_with_usage())
+}
+
+/// Print the usage string and stop
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
@@ -0,0 +1,56 @@
+use cascading_tic_tac_toe::{tune, EvalWeights, TuningConfig, WEIGHTS_PATH};
+
+const USAGE: &str = "usage: tune_eval [--generations N] [--games N] [--depth N] [--node-limit N] \
+[--target N] [--max-plies N] [--step N] [--seed N] [--output PATH]";
+
+/// Headless entry point for tuning the bot's evaluation weights by self-play, e.g.
+/// `cargo run --release --bin tune_eval -- --generations 100 --seed 1`
+/// Runs on one thread, so the same arguments always give the same weights.
+fn main() {
+    let mut config = TuningConfig::default();
+    let mut output = WEIGHTS_PATH.to_string();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| exit_with_usage());
+        match flag.as_str() {
+            "--generations" => config.generations = parse(&value),
+            "--games" => config.games = parse(&value),
+            "--depth" => config.depth = parse(&value),
+            "--node-limit" => config.node_limit = parse(&value),
+            "--target" => config.target = parse(&value),
+            "--max-plies" => config.max_plies = parse(&value),
+            "--step" => config.step = parse(&value),
+            "--seed" => config.seed = parse(&value),
+            "--output" => output = value,
+            _ => exit_with_usage(),
+        }
+    }
+
+    let weights = tune(
+        &EvalWeights::default(),
+        &config,
+        |generation, candidate, result, champion| {
+            println!(
+                "generation {}: {:?} scored {:+} against {:?}",
+                generation, candidate.0, result, champion.0
+            );
+        },
+    );
+    if let Err(error) = weights.save(&output) {
+        eprintln!("could not write {}: {}", output, error);
+        std::process::exit(1);
+    }
+    println!("wrote {:?} to {}", weights.0, output);
+}
+
+/// Parse a numeric command line value
+fn parse<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| exit_with_usage())
+}
+
+/// Print the usage string and stop
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
@@ -1,20 +1,31 @@
 use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
 use bevy_kira_audio::prelude::*;
 use std::borrow::BorrowMut;
-use std::collections::HashMap;
 
 use crate::theme::theme::UiTheme;
 use crate::ui_components::bundles::{button_bundle, text_bundle};
 use crate::utils::modify_text::modify_text;
 use crate::{
-    CellState, CellState::Filled, GameScreenTag, GameState, GridCell, PlayerTag, PlayerTurn,
-    RoundInit, StateWrapper,
+    BoardGrid, BoardView, CellState, CellState::Filled, GameScreenTag, GameState, GridCell,
+    LocalPlayers, MoveLog, PlayerTag, PlayerTurn, RoundInit, StateWrapper, CELL_SIZE,
 };
 
-/// Event triggered when a cell is clicked
+/// Cells the board moves by for each press of an arrow key
+const SCROLL_STEP: i32 = 3;
+
+/// Component for the root node of the board, holding the view it was laid out for
+#[derive(Component)]
+pub struct BoardRoot {
+    pub view: BoardView,
+}
+
+/// Event triggered when a cell is clicked, or played by the computer.
+/// Cells out of view can be played, so they are given by coordinate.
 #[derive(Event)]
 pub struct CellClickedEvent {
-    entity: Entity,
+    pub cell: (u32, u32),
+    pub book_move: bool, // Move taken from the opening book
 }
 
 /// System for handling board cell interaction events (Pressed, Hovered, None)
@@ -23,26 +34,27 @@ pub fn board_cell_interaction_system(
     player_turn: ResMut<State<PlayerTurn>>,
     mut send_cell_clicked: EventWriter<CellClickedEvent>,
     mut buttons: Query<
-        (
-            &Interaction,
-            &mut BackgroundColor,
-            &GridCell,
-            Entity,
-            &Children,
-        ),
+        (&Interaction, &mut BackgroundColor, &GridCell, &Children),
         (Changed<Interaction>, With<Button>),
     >,
     mut cell_text_query: Query<&mut Text>,
     game_state: ResMut<State<GameState>>,
+    local_players: Res<LocalPlayers>,
 ) {
-    for (interaction, mut color, cell, entity, children) in buttons.iter_mut() {
-        if cell.state != CellState::Valid || game_state.clone() != GameState::GameOngoing {
+    for (interaction, mut color, cell, children) in buttons.iter_mut() {
+        if cell.state != CellState::Valid
+            || game_state.clone() != GameState::GameOngoing
+            || !local_players.controls(player_turn.get())
+        {
             return;
         }
 
         match *interaction {
             Interaction::Pressed => {
-                send_cell_clicked.send(CellClickedEvent { entity });
+                send_cell_clicked.send(CellClickedEvent {
+                    cell: cell.cell_coord,
+                    book_move: false,
+                });
                 *color = theme.button;
             }
             Interaction::Hovered => {
@@ -73,16 +85,20 @@ pub fn board_cell_interaction_system(
 }
 
 /// System for handling cell click (Pressed) events
-/// Play audio, update cell state and text, update player turn
+/// Play audio, update the board and the cell's button if it is in view,
+/// update player turn
+#[allow(clippy::too_many_arguments)]
 pub fn on_cell_clicked(
     theme: Res<UiTheme>,
     mut events: EventReader<CellClickedEvent>,
+    mut grid: ResMut<BoardGrid>,
     mut cell_query: Query<(&mut GridCell, &Children)>,
     mut cell_text_query: Query<&mut Text>,
     player_turn_state: ResMut<State<PlayerTurn>>,
     player_turn_next_state: ResMut<NextState<PlayerTurn>>,
     asset_server: Res<AssetServer>,
     audio: Res<Audio>,
+    mut move_log: ResMut<MoveLog>,
 ) {
     let mut state = StateWrapper {
         current: player_turn_state.clone(),
@@ -91,29 +107,53 @@ pub fn on_cell_clicked(
 
     let movement_sound = asset_server.load("sounds/Crush8-Bit.ogg");
 
+    // The turn only changes next frame, so a second move this frame, like a
+    // click racing a timeout move, would be played by the same side
+    let mut played = false;
     for event in events.read() {
-        let (mut cell, children) = cell_query
-            .get_mut(event.entity)
-            .expect("on_cell_clicked: Cell not found.");
+        if played {
+            println!(
+                "on_cell_clicked: ({},{}) dropped, a move was already played this frame",
+                event.cell.0, event.cell.1
+            );
+            continue;
+        }
+        if grid.get(event.cell) != CellState::Valid {
+            println!(
+                "on_cell_clicked: ({},{}) cannot be played",
+                event.cell.0, event.cell.1
+            );
+            continue;
+        }
 
         audio.play(movement_sound.clone());
-        update_cell_state(&mut cell, &player_turn_state.get());
-        update_cell_text(
-            &theme,
-            &mut cell_text_query,
-            children,
-            &player_turn_state.get(),
-        );
+        move_log.record(player_turn_state.get(), event.cell, event.book_move);
+        let tag = player_tag(player_turn_state.get());
+        grid.fill(event.cell, tag.clone());
+        // Cells out of view are drawn from the grid when scrolled to
+        if let Some((mut cell, children)) = cell_query
+            .iter_mut()
+            .find(|(cell, _)| cell.cell_coord == event.cell)
+        {
+            cell.state = CellState::Filled(tag);
+            update_cell_text(
+                &theme,
+                &mut cell_text_query,
+                children,
+                player_turn_state.get(),
+            );
+        }
         update_player_turn(&mut state);
+        played = true;
     }
 }
 
-/// Updates the state of the clicked cell based on the current player turn
-fn update_cell_state(cell: &mut Mut<GridCell>, player_turn: &PlayerTurn) {
-    cell.state = match player_turn {
-        PlayerTurn::X => CellState::Filled(PlayerTag::X),
-        PlayerTurn::O => CellState::Filled(PlayerTag::O),
-    };
+/// The mark of the player whose turn it is
+fn player_tag(player_turn: &PlayerTurn) -> PlayerTag {
+    match player_turn {
+        PlayerTurn::X => PlayerTag::X,
+        PlayerTurn::O => PlayerTag::O,
+    }
 }
 
 /// Updates the text of the clicked cell based on the current player turn
@@ -137,7 +177,7 @@ fn update_cell_text(
 }
 
 /// Updates the player turn state to the next player
-fn update_player_turn(state: &mut StateWrapper<PlayerTurn>) {
+pub fn update_player_turn(state: &mut StateWrapper<PlayerTurn>) {
     let next_state = match state.current {
         PlayerTurn::X => PlayerTurn::O,
         PlayerTurn::O => PlayerTurn::X,
@@ -202,10 +242,10 @@ pub fn square_border(theme: &Res<UiTheme>) -> NodeBundle {
     // Define the style for a square border node
     NodeBundle {
         style: Style {
-            // Set the width to 50 pixels
-            width: Val::Px(40.0),
-            // Set the height to 50 pixels
-            height: Val::Px(40.0),
+            // Set the width to 40 pixels
+            width: Val::Px(CELL_SIZE),
+            // Set the height to 40 pixels
+            height: Val::Px(CELL_SIZE),
             // Add a border with 2 pixels width
             border: UiRect::all(Val::Px(2.0)),
             ..Default::default()
@@ -216,6 +256,20 @@ pub fn square_border(theme: &Res<UiTheme>) -> NodeBundle {
     }
 }
 
+/// Define a block standing in for a run of invalid cells in a row
+pub fn spacer_block(theme: &Res<UiTheme>, cells: u32) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            width: Val::Px(CELL_SIZE * cells as f32),
+            height: Val::Px(CELL_SIZE),
+            ..Default::default()
+        },
+        // Drawn as background, invalid cells cannot be played
+        background_color: theme.root,
+        ..Default::default()
+    }
+}
+
 /// Creates a NodeBundle for the in-game menu buttons
 pub fn menu_background(theme: &Res<UiTheme>) -> NodeBundle {
     // Define the style for the menu background node
@@ -242,8 +296,10 @@ pub fn menu_background(theme: &Res<UiTheme>) -> NodeBundle {
 }
 
 /// Algorithm to determine which cells on gameboard are invalid based
-/// on the number of rounds, n, played so far
-fn generate_invalid_cells(n: u32, list: &mut Vec<(u32,u32)>) {
+/// on the number of rounds, n, played so far. The board itself is laid out
+/// from the BoardGrid, this is the reference its cell ids are tested against.
+#[cfg(test)]
+pub(crate) fn generate_invalid_cells(n: u32, list: &mut Vec<(u32,u32)>) {
     // let cols = n + 3;
     for current_n in 1..=n {
         let mut y;
@@ -260,175 +316,112 @@ fn generate_invalid_cells(n: u32, list: &mut Vec<(u32,u32)>) {
     }
 }
 
-/// Algorithm to generate the gameboard based on the number of rounds, n,
-/// and determine which cells are valid, invalid, or already filled from
-/// a previous round
+/// System to grow the board to the current round and bring its newest
+/// sub-board into view. A round count below the board's means the board was
+/// started over, as for a tiebreak round.
+pub fn fit_board(
+    round_init: Res<RoundInit>,
+    mut grid: ResMut<BoardGrid>,
+    mut view: ResMut<BoardView>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+) {
+    if round_init.round_count < grid.round_count() {
+        *grid = BoardGrid::new(round_init.round_count);
+    } else {
+        grid.grow_to(round_init.round_count);
+    }
+    if let Ok(window) = windows.get_single() {
+        *view = BoardView::centred(&grid, Vec2::new(window.width(), window.height()));
+    }
+}
+
+/// System to scroll the board with the arrow keys
+pub fn scroll_board(
+    keys: Res<ButtonInput<KeyCode>>,
+    grid: Res<BoardGrid>,
+    mut view: ResMut<BoardView>,
+) {
+    let step = |back: KeyCode, forward: KeyCode| {
+        (keys.just_pressed(forward) as i32 - keys.just_pressed(back) as i32) * SCROLL_STEP
+    };
+    let rows = step(KeyCode::ArrowUp, KeyCode::ArrowDown);
+    let columns = step(KeyCode::ArrowLeft, KeyCode::ArrowRight);
+    if rows != 0 || columns != 0 {
+        view.scroll(rows, columns, &grid);
+    }
+}
+
+/// Run condition for laying the board out again once it has been scrolled
+pub fn board_view_moved(view: Res<BoardView>, boards: Query<&BoardRoot>) -> bool {
+    boards.iter().any(|board| board.view != *view)
+}
+
+/// Lay out the part of the board in view. Only playable cells get a button,
+/// the invalid cells either side of them are merged into one block per row,
+/// so the number of entities stays the same however many rounds are played.
 pub fn setup_board(
     mut commands: Commands,
     theme: Res<UiTheme>,
     asset_server: Res<AssetServer>,
-    round_init: Res<RoundInit>,
-    cell_query: Query<&GridCell>,
+    grid: Res<BoardGrid>,
+    view: Res<BoardView>,
 ) {
-    let n = round_init.round_count;
+    let columns = view.columns_in_view(&grid);
+
     // Spawn the root node with children
     commands
-        .spawn((root(&theme), GameScreenTag))
+        .spawn((root(&theme), BoardRoot { view: *view }, GameScreenTag))
         .with_children(|parent| {
             // Spawn the main border node with children
             parent.spawn(main_border(&theme)).with_children(|parent| {
                 // Loop through rows
-                for row_index in (0..2 * n + 3).rev() {
+                for row_index in view.rows_in_view(&grid).rev() {
+                    // The playable cells of the row that are in view
+                    let playable = grid.playable_columns(row_index);
+                    let start = playable.start.clamp(columns.start, columns.end);
+                    let end = playable.end.clamp(start, columns.end);
+
                     // Spawn the square row node with children
                     parent.spawn(square_row()).with_children(|parent| {
-                        // Loop through columns
-                        for column_index in 0..n + 3 {
-                            // Calculate the cell ID
+                        if start > columns.start {
+                            parent.spawn(spacer_block(&theme, start - columns.start));
+                        }
+                        for column_index in start..end {
                             let cell_coord = (row_index, column_index);
+                            let state = grid.get(cell_coord);
+                            let text = match &state {
+                                Filled(tag) => tag.to_string(),
+                                _ => String::new(),
+                            };
 
-                            if n == 0 {
-                                parent.spawn(square_border(&theme)).with_children(|parent| {
-                                    // Spawn the button node with children
-                                    parent
-                                        .spawn(button_bundle(
-                                            (
-                                                Val::Percent(100.0),
-                                                Val::Percent(100.0),
-                                                None,
-                                                JustifyContent::Center,
-                                                AlignItems::Center,
-                                            ),
-                                            theme.button,
-                                        ))
-                                        .with_children(|parent| {
-                                            // Spawn the button text node
-                                            parent.spawn(text_bundle(
-                                                "",
-                                                &asset_server,
-                                                (30.0, theme.button_text),
-                                            ));
-                                        })
-                                        // Insert the GridCell component
-                                        .insert(GridCell {
-                                            cell_coord,
-                                            state: CellState::Valid,
-                                        });
-                                });
-                            } else {
-                                let mut invalid_cells = Vec::new();
-                                generate_invalid_cells(n, &mut invalid_cells);
-
-                                let mut filled_cells = HashMap::new();
-                                for cell in cell_query.iter() {
-                                    if cell.state == Filled(PlayerTag::X)
-                                        || cell.state == Filled(PlayerTag::O)
-                                    {
-                                        filled_cells.insert(cell.cell_coord, cell.state.clone());
-                                    }
-                                }
-
-                                if invalid_cells.contains(&cell_coord) {
-                                    // Spawn the square border node with children
-                                    parent.spawn(square_border(&theme)).with_children(|parent| {
-                                        // Spawn the button node with children
-                                        parent
-                                            .spawn(button_bundle(
-                                                (
-                                                    Val::Percent(100.0),
-                                                    Val::Percent(100.0),
-                                                    None,
-                                                    JustifyContent::Center,
-                                                    AlignItems::Center,
-                                                ),
-                                                theme.button,
-                                            ))
-                                            .with_children(|parent| {
-                                                // Spawn the button text node
-                                                parent.spawn(text_bundle(
-                                                    "-",
-                                                    &asset_server,
-                                                    (30.0, theme.button_text),
-                                                ));
-                                            })
-                                            // Insert the GridCell component
-                                            .insert(GridCell {
-                                                cell_coord,
-                                                state: CellState::Invalid,
-                                            });
-                                    });
-                                } else if filled_cells.contains_key(&cell_coord) {
-                                    if let Some(cell_state) = filled_cells.get(&cell_coord) {
-                                        // cell_state is a reference to the CellState value
-                                        let retrieved_state = cell_state.clone();
-                                        // Spawn the square border node with children
-                                        parent.spawn(square_border(&theme)).with_children(
-                                            |parent| {
-                                                // Spawn the button node with children
-                                                match cell_state {
-                                                    Filled(tag) => {
-                                                        parent
-                                                            .spawn(button_bundle(
-                                                                (
-                                                                    Val::Percent(100.0),
-                                                                    Val::Percent(100.0),
-                                                                    None,
-                                                                    JustifyContent::Center,
-                                                                    AlignItems::Center,
-                                                                ),
-                                                                theme.button,
-                                                            ))
-                                                            .with_children(|parent| {
-                                                                // Spawn the button text node
-                                                                parent.spawn(text_bundle(
-                                                                    &tag.to_string(),
-                                                                    &asset_server,
-                                                                    (30.0, theme.button_text),
-                                                                ));
-                                                            })
-                                                            // Insert the GridCell component
-                                                            .insert(GridCell {
-                                                                cell_coord,
-                                                                state: retrieved_state,
-                                                            });
-                                                    }
-                                                    _ => println!("Not a filled cell"),
-                                                }
-                                            },
-                                        );
-                                    } else {
-                                        println!("({},{}) not found in HashMap", cell_coord.0, cell_coord.1);
-                                    }
-                                } else {
-                                    // Spawn the square border node with children
-                                    parent.spawn(square_border(&theme)).with_children(|parent| {
-                                        // Spawn the button node with children
-                                        parent
-                                            .spawn(button_bundle(
-                                                (
-                                                    Val::Percent(100.0),
-                                                    Val::Percent(100.0),
-                                                    None,
-                                                    JustifyContent::Center,
-                                                    AlignItems::Center,
-                                                ),
-                                                theme.button,
-                                            ))
-                                            .with_children(|parent| {
-                                                // Spawn the button text node
-                                                parent.spawn(text_bundle(
-                                                    "",
-                                                    &asset_server,
-                                                    (30.0, theme.button_text),
-                                                ));
-                                            })
-                                            // Insert the GridCell component
-                                            .insert(GridCell {
-                                                cell_coord,
-                                                state: CellState::Valid,
-                                            });
-                                    });
-                                }
-                            }
+                            // Spawn the square border node with children
+                            parent.spawn(square_border(&theme)).with_children(|parent| {
+                                // Spawn the button node with children
+                                parent
+                                    .spawn(button_bundle(
+                                        (
+                                            Val::Percent(100.0),
+                                            Val::Percent(100.0),
+                                            None,
+                                            JustifyContent::Center,
+                                            AlignItems::Center,
+                                        ),
+                                        theme.button,
+                                    ))
+                                    .with_children(|parent| {
+                                        // Spawn the button text node
+                                        parent.spawn(text_bundle(
+                                            &text,
+                                            &asset_server,
+                                            (30.0, theme.button_text),
+                                        ));
+                                    })
+                                    // Insert the GridCell component
+                                    .insert(GridCell { cell_coord, state });
+                            });
+                        }
+                        if columns.end > end {
+                            parent.spawn(spacer_block(&theme, columns.end - end));
                         }
                     });
                 }
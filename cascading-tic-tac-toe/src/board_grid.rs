@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use bevy::math::Vec2;
+use bevy::prelude::Resource;
+
+use crate::{cell_coord, cell_count, sub_board_cell, CellState, PlayerTag};
+
+/// Marks of the cells one sub-board adds to the board, row by row
+type SubBoard = [Option<PlayerTag>; 9];
+
+/// Resource holding the state of the board outside the ECS, so only the cells
+/// on screen need entities. Marks are kept by sub-board and place in it, and
+/// only sub-boards with a mark take room, so the store does not grow with
+/// the board but with the moves played on it.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct BoardGrid {
+    round_count: u32,
+    sub_boards: HashMap<u32, SubBoard>,
+    filled: usize,
+}
+
+/// The empty first sub-board
+impl Default for BoardGrid {
+    fn default() -> Self {
+        BoardGrid::new(0)
+    }
+}
+
+impl BoardGrid {
+    /// An empty board after n rounds
+    pub fn new(round_count: u32) -> Self {
+        BoardGrid {
+            round_count,
+            sub_boards: HashMap::new(),
+            filled: 0,
+        }
+    }
+
+    pub fn round_count(&self) -> u32 {
+        self.round_count
+    }
+
+    /// Rows of the grid the board is laid out on
+    pub fn rows(&self) -> u32 {
+        self.round_count.saturating_mul(2).saturating_add(3)
+    }
+
+    /// Columns of the grid the board is laid out on
+    pub fn columns(&self) -> u32 {
+        self.round_count.saturating_add(3)
+    }
+
+    /// Sub-board and place in it of a cell on the board
+    fn locate(&self, cell: (u32, u32)) -> Option<(u32, usize)> {
+        if cell.0 >= self.rows() || cell.1 >= self.columns() {
+            return None;
+        }
+        sub_board_cell(cell)
+    }
+
+    /// Mark in a cell, None if it is empty or not on the board
+    pub fn mark(&self, cell: (u32, u32)) -> Option<&PlayerTag> {
+        let (sub_board, place) = self.locate(cell)?;
+        self.sub_boards.get(&sub_board)?[place].as_ref()
+    }
+
+    /// State of a cell, Invalid for spacer cells and cells off the board
+    pub fn get(&self, cell: (u32, u32)) -> CellState {
+        match self.locate(cell) {
+            None => CellState::Invalid,
+            Some(_) => match self.mark(cell) {
+                Some(tag) => CellState::Filled(tag.clone()),
+                None => CellState::Valid,
+            },
+        }
+    }
+
+    /// Mark a cell for a player, cells off the board are left alone
+    pub fn fill(&mut self, cell: (u32, u32), tag: PlayerTag) {
+        let Some((sub_board, place)) = self.locate(cell) else {
+            return;
+        };
+        let mark = &mut self.sub_boards.entry(sub_board).or_default()[place];
+        if mark.is_none() {
+            self.filled += 1;
+        }
+        *mark = Some(tag);
+    }
+
+    /// Add the empty sub-boards of the rounds up to round_count. Marks are
+    /// kept by the sub-board that added their cell, so they stay where they are.
+    pub fn grow_to(&mut self, round_count: u32) {
+        self.round_count = self.round_count.max(round_count);
+    }
+
+    /// Every filled cell with its owner, in order of cell id
+    pub fn filled_cells(&self) -> impl Iterator<Item = ((u32, u32), &PlayerTag)> {
+        let mut sub_boards: Vec<_> = self.sub_boards.iter().collect();
+        sub_boards.sort_by_key(|&(&sub_board, _)| sub_board);
+        sub_boards.into_iter().flat_map(|(&k, marks)| {
+            marks.iter().enumerate().filter_map(move |(place, mark)| {
+                let cell = (2 * k + place as u32 / 3, k + place as u32 % 3);
+                Some((cell, mark.as_ref()?))
+            })
+        })
+    }
+
+    /// Coordinates of every cell that can still be played, in order of cell id
+    pub fn valid_cells(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (0..cell_count(self.round_count))
+            .map(cell_coord)
+            .filter(|&cell| self.mark(cell).is_none())
+    }
+
+    /// Number of cells that can still be played
+    pub fn empty_cells(&self) -> usize {
+        cell_count(self.round_count) - self.filled
+    }
+
+    /// Whether no cell is left to play, which ends the round
+    pub fn is_full(&self) -> bool {
+        self.empty_cells() == 0
+    }
+
+    /// Columns of a row that hold playable cells. They always form one run,
+    /// the rest of the row is spacer cells.
+    pub fn playable_columns(&self, row: u32) -> Range<u32> {
+        if row >= self.rows() {
+            return 0..0;
+        }
+        // Row 2k + 1 is the middle of sub-board k, row 2k is where
+        // sub-board k overlaps sub-board k - 1
+        let k = row / 2;
+        if row % 2 == 1 {
+            k..k + 3
+        } else if k == 0 {
+            0..3
+        } else if k > self.round_count {
+            // Bottom row of the last sub-board
+            k - 1..k + 2
+        } else {
+            k - 1..k + 3
+        }
+    }
+}
+
+/// Side of a board cell on screen, in pixels
+pub const CELL_SIZE: f32 = 40.0;
+
+/// Cells realised past each edge of the window, so the board never runs
+/// short while it is being scrolled
+const VIEW_MARGIN: u32 = 2;
+
+/// Resource holding the part of the board realised as entities: the first
+/// row and column in view and how many rows and columns fit in the window
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardView {
+    pub row: u32,
+    pub col: u32,
+    pub rows: u32,
+    pub columns: u32,
+}
+
+/// The top left of a board in the smallest window
+impl Default for BoardView {
+    fn default() -> Self {
+        BoardView {
+            row: 0,
+            col: 0,
+            rows: 15,
+            columns: 20,
+        }
+    }
+}
+
+impl BoardView {
+    /// View of a window of the given size, centred on the newest sub-board
+    pub fn centred(grid: &BoardGrid, window: Vec2) -> Self {
+        let n = grid.round_count();
+        let rows = (window.y / CELL_SIZE) as u32 + 2 * VIEW_MARGIN;
+        let columns = (window.x / CELL_SIZE) as u32 + 2 * VIEW_MARGIN;
+        BoardView {
+            row: (2 * n + 1)
+                .saturating_sub(rows / 2)
+                .min(grid.rows().saturating_sub(rows)),
+            col: (n + 1)
+                .saturating_sub(columns / 2)
+                .min(grid.columns().saturating_sub(columns)),
+            rows,
+            columns,
+        }
+    }
+
+    /// Rows of the board in view
+    pub fn rows_in_view(&self, grid: &BoardGrid) -> Range<u32> {
+        self.row.min(grid.rows())..(self.row + self.rows).min(grid.rows())
+    }
+
+    /// Columns of the board in view
+    pub fn columns_in_view(&self, grid: &BoardGrid) -> Range<u32> {
+        self.col.min(grid.columns())..(self.col + self.columns).min(grid.columns())
+    }
+
+    /// Move the view by whole cells without leaving the board
+    pub fn scroll(&mut self, rows: i32, columns: i32, grid: &BoardGrid) {
+        let last_row = grid.rows().saturating_sub(self.rows);
+        let last_col = grid.columns().saturating_sub(self.columns);
+        self.row = self.row.saturating_add_signed(rows).min(last_row);
+        self.col = self.col.saturating_add_signed(columns).min(last_col);
+    }
+}
+
+/// Unit tests for the board grid
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    /// The playable run of every row holds exactly the cells with an id
+    #[test_case(0)]
+    #[test_case(1)]
+    #[test_case(5)]
+    fn test_playable_columns(n: u32) {
+        let grid = BoardGrid::new(n);
+        for row in 0..grid.rows() {
+            let playable = grid.playable_columns(row);
+            for col in 0..grid.columns() {
+                assert_eq!(
+                    playable.contains(&col),
+                    grid.get((row, col)) == CellState::Valid,
+                    "cell ({}, {}) after {} rounds",
+                    row,
+                    col,
+                    n
+                );
+            }
+        }
+    }
+
+    /// Filled cells keep their place when the board grows
+    #[test]
+    fn test_grow_keeps_cells() {
+        let mut grid = BoardGrid::default();
+        grid.fill((2, 2), PlayerTag::X);
+        grid.grow_to(3);
+        assert_eq!(grid.get((2, 2)), CellState::Filled(PlayerTag::X));
+        assert_eq!(grid.get((8, 5)), CellState::Valid);
+        assert_eq!(grid.get((8, 0)), CellState::Invalid);
+        assert_eq!(grid.valid_cells().count(), cell_count(3) - 1);
+        assert!(!grid.is_full());
+    }
+
+    /// The view stays on the board however far it is scrolled
+    #[test]
+    fn test_view_stays_on_board() {
+        let grid = BoardGrid::new(500);
+        let mut view = BoardView::centred(&grid, Vec2::new(800.0, 600.0));
+        assert!(view.rows_in_view(&grid).contains(&1001));
+        assert!(view.columns_in_view(&grid).contains(&501));
+        assert_eq!(view.rows_in_view(&grid).len(), view.rows as usize);
+
+        view.scroll(-5000, -5000, &grid);
+        assert_eq!((view.row, view.col), (0, 0));
+        view.scroll(5000, 5000, &grid);
+        assert_eq!(view.rows_in_view(&grid).end, grid.rows());
+        assert_eq!(view.columns_in_view(&grid).end, grid.columns());
+    }
+
+    /// Every cell is stored in a place of its own and read back at its coordinate
+    #[test]
+    fn test_sub_board_places() {
+        let mut grid = BoardGrid::new(5);
+        for id in 0..cell_count(5) {
+            grid.fill(cell_coord(id), PlayerTag::O);
+        }
+        let filled: Vec<_> = grid.filled_cells().map(|(cell, _)| cell).collect();
+        assert_eq!(
+            filled,
+            (0..cell_count(5)).map(cell_coord).collect::<Vec<_>>()
+        );
+        assert!(grid.is_full());
+    }
+
+    /// Far sub-boards are found without room for the ones before them
+    #[test]
+    fn test_huge_coordinates() {
+        let n = 1_000_000_000;
+        let mut grid = BoardGrid::new(n);
+        let cell = (2 * n + 1, n + 1);
+        grid.fill(cell, PlayerTag::X);
+        assert_eq!(grid.get(cell), CellState::Filled(PlayerTag::X));
+        assert_eq!(grid.get((2 * n + 2, n + 2)), CellState::Valid);
+        assert_eq!(grid.get((2 * n + 3, n + 2)), CellState::Invalid);
+        assert_eq!(
+            grid.filled_cells().collect::<Vec<_>>(),
+            vec![(cell, &PlayerTag::X)]
+        );
+        assert_eq!(grid.empty_cells(), cell_count(n) - 1);
+    }
+}
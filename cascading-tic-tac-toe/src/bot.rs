@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+
+use crate::{
+    evaluate, BoardGrid, Bound, CellClickedEvent, EvalWeights, GameState, OpeningBook, PlayerTurn,
+    PlayingState, Position, RoundInit, TableEntry, TranspositionTable, WEIGHTS_PATH,
+};
+
+/// Score of a won game, far above anything the evaluation returns
+const WIN: i32 = 1_000_000;
+
+/// A resource holding the settings of the computer opponent
+#[derive(Resource)]
+pub struct Bot {
+    pub player: PlayerTurn, // Player controlled by the computer
+    pub depth: u32,         // Deepest iteration of the search
+    pub node_limit: u64,    // Nodes per move, the last finished depth is used past this
+    pub book_rounds: u32,   // The opening book is consulted before this round
+    pub delay: f32,         // Seconds the computer waits before moving
+}
+
+/// Default constructor for Bot, playing O
+impl Default for Bot {
+    fn default() -> Self {
+        Bot {
+            player: PlayerTurn::O,
+            depth: 6,
+            node_limit: 50_000,
+            book_rounds: 2,
+            delay: 0.5,
+        }
+    }
+}
+
+/// Move chosen by the computer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotMove {
+    pub cell: (u32, u32),
+    pub book_move: bool,
+}
+
+/// Struct for the computer opponent
+pub struct BotPlugin;
+
+/// Plugin that plays the computer's turns in games against the computer
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bot>()
+            .insert_resource(EvalWeights::load_or_default(WEIGHTS_PATH))
+            .init_resource::<TranspositionTable>()
+            .add_systems(
+                Update,
+                bot_move_system
+                    .run_if(in_state(PlayingState::VsComputer))
+                    .run_if(in_state(GameState::GameOngoing)),
+            );
+    }
+}
+
+/// System that plays the computer's turn once its delay has passed
+#[allow(clippy::too_many_arguments)]
+fn bot_move_system(
+    bot: Res<Bot>,
+    book: Res<OpeningBook>,
+    weights: Res<EvalWeights>,
+    mut table: ResMut<TranspositionTable>,
+    round_init: Res<RoundInit>,
+    player_turn: Res<State<PlayerTurn>>,
+    grid: Res<BoardGrid>,
+    mut send_cell_clicked: EventWriter<CellClickedEvent>,
+    time: Res<Time>,
+    mut waited: Local<f32>,
+) {
+    if *player_turn.get() != bot.player {
+        *waited = 0.0;
+        return;
+    }
+    *waited += time.delta_seconds();
+    if *waited < bot.delay {
+        return;
+    }
+    *waited = 0.0;
+
+    let position = Position::from_board(&grid, &round_init, player_turn.get());
+    if let Some(chosen) = choose_move(
+        &position,
+        round_init.target,
+        &bot,
+        &book,
+        &weights,
+        &mut table,
+    ) {
+        send_cell_clicked.send(CellClickedEvent {
+            cell: chosen.cell,
+            book_move: chosen.book_move,
+        });
+    }
+}
+
+/// Pick a move for the player to move: a book move in the early rounds,
+/// otherwise the best move found by the search
+pub fn choose_move(
+    position: &Position,
+    target: u32,
+    bot: &Bot,
+    book: &OpeningBook,
+    weights: &EvalWeights,
+    table: &mut TranspositionTable,
+) -> Option<BotMove> {
+    if position.round_count() < bot.book_rounds && book.target == target {
+        if let Some(cell) = book.get(position) {
+            return Some(BotMove {
+                cell,
+                book_move: true,
+            });
+        }
+    }
+    let ranked = rank_moves(position, target, bot.depth, bot.node_limit, weights, table);
+    ranked.first().map(|&(cell, _)| BotMove {
+        cell,
+        book_move: false,
+    })
+}
+
+/// Every move with its search score for the player to move, best first.
+/// Deepens one ply at a time and keeps the last depth finished within the node limit.
+pub fn rank_moves(
+    position: &Position,
+    target: u32,
+    depth: u32,
+    node_limit: u64,
+    weights: &EvalWeights,
+    table: &mut TranspositionTable,
+) -> Vec<((u32, u32), i32)> {
+    table.new_search();
+    let mut search = Search {
+        table,
+        weights,
+        target,
+        nodes: 0,
+        node_limit,
+    };
+
+    let mut ranked: Vec<((u32, u32), i32)> =
+        position.moves().into_iter().map(|cell| (cell, 0)).collect();
+    for current_depth in 1..=depth.max(1) {
+        let mut scored = Vec::new();
+        for &(cell, _) in ranked.iter() {
+            let child = position.play(cell);
+            match search.negamax(&child, current_depth - 1, -2 * WIN, 2 * WIN) {
+                Some(score) => scored.push((cell, -score)),
+                None => break,
+            }
+        }
+        if scored.len() < ranked.len() {
+            break;
+        }
+        // Stable sort, so equal moves keep the order of the previous depth
+        scored.sort_by_key(|&(_, score)| -score);
+        ranked = scored;
+    }
+    ranked
+}
+
+/// State of a single bot search
+struct Search<'a> {
+    table: &'a mut TranspositionTable,
+    weights: &'a EvalWeights,
+    target: u32,
+    nodes: u64,
+    node_limit: u64,
+}
+
+impl Search<'_> {
+    /// Alpha-beta negamax from the point of view of the player to move,
+    /// None once the node limit is used up
+    fn negamax(
+        &mut self,
+        position: &Position,
+        depth: u32,
+        mut alpha: i32,
+        mut beta: i32,
+    ) -> Option<i32> {
+        self.nodes += 1;
+        if self.nodes > self.node_limit && depth > 0 {
+            return None;
+        }
+        let sign = if *position.turn() == PlayerTurn::X {
+            1
+        } else {
+            -1
+        };
+        // Prefer quicker wins and slower losses
+        if position.o_score() >= self.target {
+            return Some(sign * -(WIN + depth as i32));
+        }
+        if position.x_score() >= self.target {
+            return Some(sign * (WIN + depth as i32));
+        }
+        if depth == 0 {
+            return Some(sign * evaluate(position, self.weights));
+        }
+
+        let alpha_before = alpha;
+        let mut best_move = None;
+        if let Some(entry) = self.table.probe(position.hash()) {
+            best_move = entry.best_move;
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return Some(entry.score),
+                    Bound::Lower => alpha = alpha.max(entry.score),
+                    Bound::Upper => beta = beta.min(entry.score),
+                }
+                if alpha >= beta {
+                    return Some(entry.score);
+                }
+            }
+        }
+
+        // Try the best move from an earlier search first
+        let mut moves = position.moves();
+        if let Some(index) = best_move.and_then(|cell| moves.iter().position(|&m| m == cell)) {
+            moves.swap(0, index);
+        }
+
+        let mut best = -WIN - 1 - depth as i32;
+        for cell in moves {
+            let score = -self.negamax(&position.play(cell), depth - 1, -beta, -alpha)?;
+            if score > best {
+                best = score;
+                best_move = Some(cell);
+            }
+            alpha = alpha.max(best);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= alpha_before {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.store(TableEntry::new(
+            position.hash(),
+            depth,
+            best,
+            bound,
+            best_move,
+        ));
+        Some(best)
+    }
+}
@@ -0,0 +1,419 @@
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+
+use crate::theme::theme::UiTheme;
+use crate::{
+    button_game, button_text_game, clean_chat, ButtonActions, Emote, GameScreenTag, GameState,
+    LocalPlayers, Message, OnlineSession, PlayingState, MAX_CHAT_LENGTH, TEXT_COLOR,
+};
+
+/// Chat lines kept in the history
+const CHAT_HISTORY: usize = 50;
+
+/// Chat lines shown in the panel
+const SHOWN_LINES: usize = 8;
+
+/// Seconds a toast stays on screen
+const TOAST_SECONDS: f32 = 3.0;
+
+/// Something said in chat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatContent {
+    Text(String),
+    Emote(Emote),
+}
+
+impl ChatContent {
+    /// Text shown for the content
+    pub fn label(&self) -> &str {
+        match self {
+            ChatContent::Text(text) => text,
+            ChatContent::Emote(emote) => emote.label(),
+        }
+    }
+}
+
+/// Event sent when the opponent says something
+#[derive(Event)]
+pub struct ChatReceivedEvent(pub ChatContent);
+
+/// One line of the chat history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatLine {
+    pub from_opponent: bool,
+    pub content: ChatContent,
+}
+
+/// A resource holding the chat of the online game
+#[derive(Resource, Default)]
+pub struct Chat {
+    pub lines: Vec<ChatLine>,
+    pub draft: String,   // Being typed, sent with Enter
+    pub open: bool,      // The panel is shown
+    pub muted: bool,     // The opponent's chat and emotes are hidden
+    toasts: Vec<String>, // Not yet on screen
+}
+
+impl Chat {
+    /// Add a line to the history, dropping the oldest past CHAT_HISTORY.
+    /// Emotes are toasted, and so is text while the panel is closed.
+    pub fn push(&mut self, from_opponent: bool, content: ChatContent) {
+        if matches!(content, ChatContent::Emote(_)) || !self.open {
+            self.toasts
+                .push(format!("{}: {}", speaker(from_opponent), content.label()));
+        }
+        self.lines.push(ChatLine {
+            from_opponent,
+            content,
+        });
+        if self.lines.len() > CHAT_HISTORY {
+            self.lines.remove(0);
+        }
+    }
+
+    /// Text of the panel: the latest lines, then the draft
+    pub fn label(&self) -> String {
+        let first = self.lines.len().saturating_sub(SHOWN_LINES);
+        let mut label = String::new();
+        for line in &self.lines[first..] {
+            label.push_str(&format!(
+                "{}: {}\n",
+                speaker(line.from_opponent),
+                line.content.label()
+            ));
+        }
+        label.push_str(&format!("> {}_", self.draft));
+        label
+    }
+}
+
+/// Name shown before a chat line
+fn speaker(from_opponent: bool) -> &'static str {
+    if from_opponent {
+        "Opponent"
+    } else {
+        "You"
+    }
+}
+
+/// Actions of the chat buttons on the game screen
+#[derive(Component)]
+pub enum ChatButtonAction {
+    Toggle, // Open or close the panel
+    Mute,   // Hide or show the opponent's chat
+    Emote(Emote),
+}
+
+/// Tag component for the chat panel and buttons
+#[derive(Component)]
+pub struct ChatControls;
+
+/// Tag component for the chat panel text
+#[derive(Component)]
+pub struct ChatPanelText;
+
+/// Tag component for the text of the mute button
+#[derive(Component)]
+pub struct MuteButtonText;
+
+/// Tag component for the node holding the toasts
+#[derive(Component)]
+pub struct ToastList;
+
+/// Component for a short notice of an emote or a chat line, removed once its timer finishes
+#[derive(Component)]
+pub struct Toast(Timer);
+
+/// Struct for chatting in online games
+pub struct ChatPlugin;
+
+/// Plugin for the chat panel, quick emotes and their toasts. Spectators
+/// control neither player and get no chat.
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ChatReceivedEvent>()
+            .init_resource::<Chat>()
+            .add_systems(
+                Update,
+                (
+                    setup_chat,
+                    chat_buttons,
+                    chat_input,
+                    receive_chat,
+                    update_chat_panel,
+                    show_toasts,
+                    expire_toasts,
+                )
+                    .run_if(in_state(PlayingState::Online))
+                    .run_if(resource_exists::<OnlineSession>),
+            )
+            .add_systems(OnExit(PlayingState::Online), reset_chat);
+    }
+}
+
+/// System that spawns the chat panel, buttons and toast list on the game
+/// screen, which is rebuilt every round
+fn setup_chat(
+    mut commands: Commands,
+    theme: Res<UiTheme>,
+    asset_server: Res<AssetServer>,
+    local_players: Res<LocalPlayers>,
+    game_state: Res<State<GameState>>,
+    controls: Query<(), With<ChatControls>>,
+) {
+    let on_screen = matches!(
+        game_state.get(),
+        GameState::GameOngoing | GameState::Won(_) | GameState::Draw
+    );
+    if !controls.is_empty() || !on_screen || local_players.0.is_empty() {
+        return;
+    }
+    let text_style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+        font_size: 20.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(50.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::FlexStart,
+                    ..default()
+                },
+                ..default()
+            },
+            ChatControls,
+            GameScreenTag,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle {
+                    text: Text::from_section("", text_style.clone()),
+                    style: Style {
+                        display: Display::None,
+                        max_width: Val::Px(400.0),
+                        margin: UiRect::bottom(Val::Px(5.0)),
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..default()
+                },
+                ChatPanelText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(5.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn((button_game(&theme), ChatButtonAction::Toggle))
+                        .with_children(|parent| {
+                            parent.spawn(button_text_game(&asset_server, &theme, "Chat"));
+                        });
+                    parent
+                        .spawn((button_game(&theme), ChatButtonAction::Mute))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                button_text_game(&asset_server, &theme, "Mute"),
+                                MuteButtonText,
+                            ));
+                        });
+                    for emote in Emote::ALL {
+                        parent
+                            .spawn((button_game(&theme), ChatButtonAction::Emote(emote)))
+                            .with_children(|parent| {
+                                parent.spawn(button_text_game(
+                                    &asset_server,
+                                    &theme,
+                                    emote.label(),
+                                ));
+                            });
+                    }
+                });
+        });
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Px(80.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        },
+        ToastList,
+        GameScreenTag,
+    ));
+}
+
+/// System to handle the chat and emote buttons
+fn chat_buttons(
+    interaction_query: ButtonActions<ChatButtonAction>,
+    mut chat: ResMut<Chat>,
+    mut session: ResMut<OnlineSession>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            ChatButtonAction::Toggle => chat.open = !chat.open,
+            ChatButtonAction::Mute => chat.muted = !chat.muted,
+            ChatButtonAction::Emote(emote) => {
+                if let Err(error) = session.send(&Message::Emote(*emote)) {
+                    println!("Could not send emote: {}", error);
+                }
+                chat.push(false, ChatContent::Emote(*emote));
+            }
+        }
+    }
+}
+
+/// System to type in the open chat panel, Enter sends and backspace deletes
+fn chat_input(
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut chat: ResMut<Chat>,
+    mut session: ResMut<OnlineSession>,
+) {
+    if !chat.open {
+        characters.clear();
+        return;
+    }
+    for event in characters.read() {
+        for character in event.char.chars() {
+            if !character.is_control() && chat.draft.chars().count() < MAX_CHAT_LENGTH {
+                chat.draft.push(character);
+            }
+        }
+    }
+    if keys.just_pressed(KeyCode::Backspace) {
+        chat.draft.pop();
+    }
+    if keys.just_pressed(KeyCode::Enter) {
+        let draft = std::mem::take(&mut chat.draft);
+        if let Some(text) = clean_chat(&draft) {
+            if let Err(error) = session.send(&Message::Chat { text: text.clone() }) {
+                println!("Could not send chat: {}", error);
+            }
+            chat.push(false, ChatContent::Text(text));
+        }
+    }
+}
+
+/// System that adds what the opponent says to the chat, unless they are muted
+fn receive_chat(mut events: EventReader<ChatReceivedEvent>, mut chat: ResMut<Chat>) {
+    for event in events.read() {
+        if !chat.muted {
+            chat.push(true, event.0.clone());
+        }
+    }
+}
+
+/// System to show the chat history and draft, and the state of the mute button
+fn update_chat_panel(
+    chat: Res<Chat>,
+    mut panels: Query<(Ref<ChatPanelText>, &mut Text, &mut Style), Without<MuteButtonText>>,
+    mut mute_texts: Query<&mut Text, With<MuteButtonText>>,
+) {
+    for (tag, mut text, mut style) in panels.iter_mut() {
+        // Also filled when the panel has just been spawned
+        if !chat.is_changed() && !tag.is_added() {
+            continue;
+        }
+        text.sections[0].value = chat.label();
+        style.display = if chat.open {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        for mut mute_text in mute_texts.iter_mut() {
+            mute_text.sections[0].value = if chat.muted { "Unmute" } else { "Mute" }.to_string();
+        }
+    }
+}
+
+/// System that puts new toasts on screen once the toast list is there
+fn show_toasts(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut chat: ResMut<Chat>,
+    lists: Query<Entity, With<ToastList>>,
+) {
+    let Ok(list) = lists.get_single() else {
+        return;
+    };
+    if chat.toasts.is_empty() {
+        return;
+    }
+    let toasts = std::mem::take(&mut chat.toasts);
+    commands.entity(list).with_children(|parent| {
+        for toast in toasts {
+            parent.spawn((
+                TextBundle {
+                    text: Text::from_section(
+                        toast,
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 30.0,
+                            color: TEXT_COLOR,
+                        },
+                    ),
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..default()
+                },
+                Toast(Timer::from_seconds(TOAST_SECONDS, TimerMode::Once)),
+            ));
+        }
+    });
+}
+
+/// System that removes toasts whose time is up
+fn expire_toasts(mut commands: Commands, mut toasts: Query<(Entity, &mut Toast)>, time: Res<Time>) {
+    for (entity, mut toast) in toasts.iter_mut() {
+        if toast.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// System that clears the chat when the online game is left
+fn reset_chat(mut chat: ResMut<Chat>) {
+    *chat = Chat::default();
+}
+
+/// Unit tests for the chat history
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The history keeps the latest lines, text is only toasted while the panel is closed
+    #[test]
+    fn test_chat_history() {
+        let mut chat = Chat::default();
+        for index in 0..CHAT_HISTORY + 2 {
+            chat.push(true, ChatContent::Text(index.to_string()));
+        }
+        assert_eq!(chat.lines.len(), CHAT_HISTORY);
+        assert_eq!(chat.lines[0].content, ChatContent::Text("2".to_string()));
+
+        chat.toasts.clear();
+        chat.open = true;
+        chat.push(false, ChatContent::Text("hi".to_string()));
+        chat.push(false, ChatContent::Emote(Emote::Oops));
+        assert_eq!(chat.toasts, vec!["You: Oops".to_string()]);
+        assert!(chat.label().ends_with("You: hi\nYou: Oops\n> _"));
+    }
+}
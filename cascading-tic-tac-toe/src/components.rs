@@ -1,9 +1,15 @@
 use crate::CellState;
 use bevy::ecs::component::Component;
 use bevy::math::Vec2;
-use bevy::prelude::{Entity, NextState, ResMut, Resource, States};
+use bevy::prelude::{
+    Button, Changed, Entity, Interaction, NextState, Query, ResMut, Resource, States, With,
+};
 
-/// Represents a grid cell containing a TicTacToeCell
+/// Buttons tagged with the action `T` whose interaction changed this frame
+pub type ButtonActions<'w, 's, T> =
+    Query<'w, 's, (&'static Interaction, &'static T), (Changed<Interaction>, With<Button>)>;
+
+/// Represents a grid cell in view, the whole board is kept in the BoardGrid
 #[derive(Component, Clone)]
 pub struct GridCell {
     // Max board 4,294,967,295 x 4,294,967,295
@@ -35,6 +41,22 @@ pub struct CameraMenu;
 #[derive(Component)]
 pub struct OnMainMenuScreen;
 
+/// Tag component used to tag entities added on the new game menu screen
+#[derive(Component)]
+pub struct OnNewGameMenuScreen;
+
+/// Tag component used to tag entities added on the target score menu screen
+#[derive(Component)]
+pub struct OnTargetMenuScreen;
+
+/// Tag component used to tag entities added on the timed game menu screen
+#[derive(Component)]
+pub struct OnTimerMenuScreen;
+
+/// Tag component used to tag entities added on the pause menu screen
+#[derive(Component)]
+pub struct OnPauseMenuScreen;
+
 /// Tag component used to tag entities added on the settings menu screen
 #[derive(Component)]
 pub struct OnSettingsMenuScreen;
@@ -47,6 +69,22 @@ pub struct OnDisplaySettingsMenuScreen;
 #[derive(Component)]
 pub struct OnSoundSettingsMenuScreen;
 
+/// Tag component used to tag entities added on the online menu screen
+#[derive(Component)]
+pub struct OnOnlineMenuScreen;
+
+/// Tag component used to tag entities added on the lobby menu screen
+#[derive(Component)]
+pub struct OnLobbyMenuScreen;
+
+/// Tag component used to tag entities added on the correspondence games menu screen
+#[derive(Component)]
+pub struct OnCorrespondenceMenuScreen;
+
+/// Tag component used to tag entities added on the leaderboard menu screen
+#[derive(Component)]
+pub struct OnLeaderboardMenuScreen;
+
 /// Tag component used to mark which setting is currently selected
 #[derive(Component)]
 pub struct SelectedOption;
@@ -55,11 +93,15 @@ pub struct SelectedOption;
 #[derive(Component)]
 pub enum MenuButtonAction {
     Play,
+    RoundOptions, // Go on from the new game menu to the options of its mode
+    StartGame,
+    PlayOnline,
     Settings,
     SettingsDisplay,
     SettingsSound,
     BackToMainMenu,
     BackToSettings,
+    BackToNewGame,
     Quit,
 }
 
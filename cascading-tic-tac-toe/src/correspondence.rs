@@ -0,0 +1,414 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    CorrespondenceInfo, GameRecord, GameSnapshot, MoveError, PlayerTag, PlayerTurn, RoomSettings,
+    ServerGame,
+};
+
+/// First line of a saved correspondence game
+const GAME_HEADER: &str = "cascading-tic-tac-toe correspondence game";
+
+/// Version of the saved game format
+const GAME_VERSION: u32 = 1;
+
+/// Longest time a challenge may give for each move, in days
+pub const MAX_DEADLINE_DAYS: u32 = 30;
+
+/// Seconds since the Unix epoch, the clock correspondence games are kept with
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// A game played over days, each player moving whenever they like. It is
+/// saved after every change and rebuilt from its moves when loaded.
+pub struct CorrespondenceGame {
+    pub id: u32,
+    pub x: String,                // Player who made the challenge
+    pub o: Option<String>,        // Player who accepted it, None while it is open
+    pub deadline: Option<u32>,    // Seconds each move may take, None for no limit
+    pub updated: u64,             // Unix time of the last move, or of the challenge
+    resigned: Option<PlayerTurn>, // Also set when a player misses the deadline
+    game: ServerGame,
+}
+
+impl CorrespondenceGame {
+    /// New challenge by `x`
+    pub fn new(id: u32, x: &str, target: u32, deadline: Option<u32>, now: u64) -> Self {
+        CorrespondenceGame {
+            id,
+            x: x.to_string(),
+            o: None,
+            deadline,
+            updated: now,
+            resigned: None,
+            game: ServerGame::new(target),
+        }
+    }
+
+    /// Seat of `player` in the game, X if they play both sides
+    pub fn seat(&self, player: &str) -> Option<PlayerTurn> {
+        if self.x == player {
+            Some(PlayerTurn::X)
+        } else if self.o.as_deref() == Some(player) {
+            Some(PlayerTurn::O)
+        } else {
+            None
+        }
+    }
+
+    /// Player to move
+    pub fn turn(&self) -> &PlayerTurn {
+        self.game.position().turn()
+    }
+
+    /// Winner of the game, by score, resignation or a missed deadline
+    pub fn winner(&self) -> Option<PlayerTag> {
+        self.game.winner()
+    }
+
+    /// Unix time the next move is due by, once both players are seated
+    pub fn due(&self) -> Option<u64> {
+        if self.o.is_none() || self.winner().is_some() {
+            return None;
+        }
+        self.deadline
+            .map(|deadline| self.updated + u64::from(deadline))
+    }
+
+    /// Forfeit the game of the player to move if their deadline has passed.
+    /// Returns whether it was forfeited.
+    pub fn enforce_deadline(&mut self, now: u64) -> bool {
+        match self.due() {
+            Some(due) if now > due => {
+                let player = self.turn().clone();
+                self.resign(&player, due);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Play a move for `player` if the rules allow it, once both players are seated
+    pub fn play(
+        &mut self,
+        player: &PlayerTurn,
+        cell: (u32, u32),
+        now: u64,
+    ) -> Result<(), MoveError> {
+        if self.o.is_none() {
+            return Err(MoveError::NoOpponent);
+        }
+        self.game.play(player, cell)?;
+        self.updated = now;
+        Ok(())
+    }
+
+    /// End the game in the opponent's favour
+    pub fn resign(&mut self, player: &PlayerTurn, now: u64) {
+        if self.winner().is_none() {
+            self.game.resign(player);
+            self.resigned = Some(player.clone());
+            self.updated = now;
+        }
+    }
+
+    /// Summary sent to the players
+    pub fn snapshot(&self) -> GameSnapshot {
+        self.game.snapshot()
+    }
+
+    /// Everything a player opening the game needs to rebuild it, the clock
+    /// counting from the last move
+    pub fn record(&self, now: u64) -> GameRecord {
+        GameRecord {
+            moves: self.game.record().moves,
+            elapsed: now.saturating_sub(self.updated).min(u32::MAX.into()) as u32,
+        }
+    }
+
+    /// Settings the game is played with, the clock showing the deadline if there is one
+    pub fn settings(&self) -> RoomSettings {
+        let defaults = RoomSettings::default();
+        RoomSettings {
+            target: self.game.target(),
+            timer: self.deadline.unwrap_or(defaults.timer),
+            ..defaults
+        }
+    }
+
+    /// How the game is listed to its players
+    pub fn info(&self) -> CorrespondenceInfo {
+        CorrespondenceInfo {
+            id: self.id,
+            x: self.x.clone(),
+            o: self.o.clone(),
+            turn: self.turn().clone(),
+            winner: self.winner(),
+            due: self.due(),
+        }
+    }
+
+    /// Write the game in the saved game format
+    pub fn to_text(&self) -> String {
+        let moves: Vec<String> = self
+            .game
+            .record()
+            .moves
+            .iter()
+            .map(|cell| format!("{},{}", cell.0, cell.1))
+            .collect();
+        format!(
+            "{}\nversion {}\nid {}\nx {}\no {}\ntarget {}\ndeadline {}\nupdated {}\nresigned {}\nmoves {}\n",
+            GAME_HEADER,
+            GAME_VERSION,
+            self.id,
+            self.x,
+            self.o.as_deref().unwrap_or("-"),
+            self.game.target(),
+            self.deadline.map_or("-".to_string(), |deadline| deadline.to_string()),
+            self.updated,
+            self.resigned
+                .as_ref()
+                .map_or("-".to_string(), |player| format!("{:?}", player)),
+            moves.join(" ")
+        )
+    }
+
+    /// Parse a saved game, replaying its moves to rebuild the board
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut lines = text.lines();
+
+        if lines.next() != Some(GAME_HEADER) {
+            return Err(invalid("not a correspondence game".to_string()));
+        }
+        let mut value = |name: &str| -> io::Result<String> {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .map(|value| value.trim().to_string())
+                .ok_or_else(|| invalid(format!("missing {}", name)))
+        };
+        let number = |text: String| -> io::Result<u64> {
+            text.parse()
+                .map_err(|_| invalid(format!("bad number: {}", text)))
+        };
+        let version = number(value("version")?)?;
+        if version != u64::from(GAME_VERSION) {
+            return Err(invalid(format!(
+                "game version {} is not supported, expected {}",
+                version, GAME_VERSION
+            )));
+        }
+        let id = number(value("id")?)? as u32;
+        let x = value("x")?;
+        let o = Some(value("o")?).filter(|o| o != "-");
+        let target = number(value("target")?)? as u32;
+        let deadline = match value("deadline")?.as_str() {
+            "-" => None,
+            deadline => Some(number(deadline.to_string())? as u32),
+        };
+        let updated = number(value("updated")?)?;
+        let resigned = match value("resigned")?.as_str() {
+            "-" => None,
+            "X" => Some(PlayerTurn::X),
+            "O" => Some(PlayerTurn::O),
+            player => return Err(invalid(format!("bad player: {}", player))),
+        };
+        let moves = value("moves")?;
+
+        let mut game = CorrespondenceGame {
+            id,
+            x,
+            o,
+            deadline,
+            updated,
+            resigned: None,
+            game: ServerGame::new(target),
+        };
+        for cell in moves.split_whitespace() {
+            let parsed = cell
+                .split_once(',')
+                .and_then(|(row, col)| row.parse().ok().zip(col.parse().ok()))
+                .ok_or_else(|| invalid(format!("bad move: {}", cell)))?;
+            let player = game.turn().clone();
+            game.game
+                .play(&player, parsed)
+                .map_err(|error| invalid(format!("bad move {}: {}", cell, error)))?;
+        }
+        if let Some(player) = resigned {
+            game.resign(&player, updated);
+        }
+        Ok(game)
+    }
+}
+
+/// Directory the correspondence games of a server are kept in, one file per
+/// game. It may be shared, every write replaces a whole file at once.
+pub struct CorrespondenceStore {
+    directory: PathBuf,
+}
+
+impl CorrespondenceStore {
+    /// Keep games in `directory`, creating it if needed
+    pub fn open(directory: impl Into<PathBuf>) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(CorrespondenceStore { directory })
+    }
+
+    fn path(&self, id: u32) -> PathBuf {
+        self.directory.join(format!("game_{}.txt", id))
+    }
+
+    /// Read a game, forfeiting it first if a deadline has passed
+    pub fn load(&self, id: u32, now: u64) -> io::Result<CorrespondenceGame> {
+        let mut game = CorrespondenceGame::from_text(&fs::read_to_string(self.path(id))?)?;
+        if game.enforce_deadline(now) {
+            self.save(&game)?;
+        }
+        Ok(game)
+    }
+
+    /// Write a game, through a temporary file so it is never seen half written
+    pub fn save(&self, game: &CorrespondenceGame) -> io::Result<()> {
+        let temporary = self.directory.join(format!("game_{}.tmp", game.id));
+        fs::write(&temporary, game.to_text())?;
+        fs::rename(temporary, self.path(game.id))
+    }
+
+    /// Every game in the directory by id, unreadable files are skipped
+    pub fn games(&self, now: u64) -> io::Result<Vec<CorrespondenceGame>> {
+        let mut games = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let name = entry?.file_name();
+            let id = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("game_"))
+                .and_then(|name| name.strip_suffix(".txt"))
+                .and_then(|id| id.parse().ok());
+            if let Some(id) = id {
+                match self.load(id, now) {
+                    Ok(game) => games.push(game),
+                    Err(error) => println!("Skipped correspondence game {}: {}", id, error),
+                }
+            }
+        }
+        games.sort_by_key(|game| game.id);
+        Ok(games)
+    }
+
+    /// Open a challenge by `player`, with `days` for each move or 0 for no deadline
+    pub fn challenge(
+        &self,
+        player: &str,
+        target: u32,
+        days: u32,
+        now: u64,
+    ) -> io::Result<CorrespondenceGame> {
+        if days > MAX_DEADLINE_DAYS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("moves may take at most {} days", MAX_DEADLINE_DAYS),
+            ));
+        }
+        let id = self.games(now)?.last().map_or(1, |game| game.id + 1);
+        let deadline = Some(days * 24 * 60 * 60).filter(|deadline| *deadline > 0);
+        let game = CorrespondenceGame::new(id, player, target, deadline, now);
+        self.save(&game)?;
+        Ok(game)
+    }
+
+    /// Take up the open challenge `id` as O
+    pub fn accept(&self, id: u32, player: &str, now: u64) -> io::Result<CorrespondenceGame> {
+        let refused = |reason: String| io::Error::new(io::ErrorKind::InvalidInput, reason);
+        let mut game = self
+            .load(id, now)
+            .map_err(|_| refused(format!("there is no game {}", id)))?;
+        if game.o.is_some() {
+            return Err(refused(format!("game {} has already been accepted", id)));
+        }
+        if game.x == player {
+            return Err(refused("you cannot accept your own challenge".to_string()));
+        }
+        game.o = Some(player.to_string());
+        // The deadline counts from when the game really starts
+        game.updated = now;
+        self.save(&game)?;
+        Ok(game)
+    }
+}
+
+/// Unit tests for correspondence games
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fresh directory for a test's games
+    fn store(name: &str) -> CorrespondenceStore {
+        let directory =
+            std::env::temp_dir().join(format!("correspondence_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        CorrespondenceStore::open(directory).unwrap()
+    }
+
+    /// Games are saved, loaded with their board rebuilt, and listed by id
+    #[test]
+    fn test_store_round_trip() {
+        let store = store("round_trip");
+        let mut challenge = store.challenge("alice", 3, 3, 1000).unwrap();
+        assert_eq!(challenge.id, 1);
+        assert_eq!(store.challenge("bob", 3, 0, 1000).unwrap().id, 2);
+        // The challenger waits for an opponent before moving
+        assert_eq!(
+            challenge.play(&PlayerTurn::X, (0, 0), 1500),
+            Err(MoveError::NoOpponent)
+        );
+
+        let mut game = store.accept(1, "bob", 2000).unwrap();
+        assert!(store.accept(1, "carol", 2000).is_err());
+        assert!(store.accept(2, "bob", 2000).is_err());
+        game.play(&PlayerTurn::X, (0, 0), 3000).unwrap();
+        game.play(&PlayerTurn::O, (1, 0), 4000).unwrap();
+        store.save(&game).unwrap();
+
+        let loaded = store.load(1, 5000).unwrap();
+        assert_eq!(loaded.to_text(), game.to_text());
+        assert_eq!(
+            loaded.record(5000),
+            GameRecord {
+                moves: vec![(0, 0), (1, 0)],
+                elapsed: 1000
+            }
+        );
+        assert_eq!(loaded.seat("bob"), Some(PlayerTurn::O));
+        assert_eq!(loaded.due(), Some(4000 + 3 * 24 * 60 * 60));
+        let ids: Vec<u32> = store
+            .games(5000)
+            .unwrap()
+            .iter()
+            .map(|game| game.id)
+            .collect();
+        assert_eq!(ids, [1, 2]);
+    }
+
+    /// The player to move forfeits once their deadline has passed
+    #[test]
+    fn test_deadline() {
+        let store = store("deadline");
+        store.challenge("alice", 3, 1, 0).unwrap();
+        store.accept(1, "bob", 0).unwrap();
+        assert_eq!(store.load(1, 24 * 60 * 60).unwrap().winner(), None);
+
+        let game = store.load(1, 24 * 60 * 60 + 1).unwrap();
+        assert_eq!(game.winner(), Some(PlayerTag::O));
+        assert_eq!(game.due(), None);
+        // The forfeit was saved
+        assert!(store.load(1, 0).unwrap().to_text().contains("resigned X"));
+    }
+}
@@ -0,0 +1,238 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::{decode_settings, encode_settings, RoomSettings, PROTOCOL_VERSION};
+
+/// UDP port hosts answer discovery queries on
+pub const DISCOVERY_PORT: u16 = 7879;
+
+/// How often the local network is asked for games
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A game that has not answered for this long is dropped from the list
+const GAME_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What a host tells the local network about its game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameAdvert {
+    pub name: String, // Host name of the machine
+    pub port: u16,    // TCP port to join the game on
+    pub players: u32, // 1 while waiting for an opponent, 2 once playing
+    pub settings: RoomSettings,
+}
+
+impl GameAdvert {
+    /// Datagram sent in answer to a query
+    pub fn encode(&self) -> String {
+        format!(
+            "GAME {} {} {} {} {}",
+            PROTOCOL_VERSION,
+            self.port,
+            self.players,
+            encode_settings(&self.settings),
+            self.name
+        )
+    }
+
+    /// Parse an answer, None for anything else, including other protocol versions
+    pub fn decode(datagram: &str) -> Option<GameAdvert> {
+        let fields: Vec<&str> = datagram.trim().splitn(8, ' ').collect();
+        match fields.as_slice() {
+            ["GAME", version, port, players, settings @ .., name] if settings.len() == 3 => {
+                if version.parse::<u32>().ok()? != PROTOCOL_VERSION {
+                    return None;
+                }
+                Some(GameAdvert {
+                    name: name.to_string(),
+                    port: port.parse().ok()?,
+                    players: players.parse().ok()?,
+                    settings: decode_settings(settings)?,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Name of this machine shown to other players, from the environment or /etc/hostname
+pub fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "Unknown host".to_string())
+}
+
+/// Answers discovery queries for a hosted game
+pub struct DiscoveryResponder {
+    socket: UdpSocket,
+    pub advert: GameAdvert,
+}
+
+impl DiscoveryResponder {
+    /// Listen for queries on `address`, normally DISCOVERY_PORT on every interface
+    pub fn bind(address: impl ToSocketAddrs, advert: GameAdvert) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(DiscoveryResponder { socket, advert })
+    }
+
+    /// Address queries are received on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Answer every query received since the last call
+    pub fn answer(&mut self) -> io::Result<()> {
+        let mut datagram = [0; 512];
+        loop {
+            let (read, sender) = match self.socket.recv_from(&mut datagram) {
+                Ok(received) => received,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(error) => return Err(error),
+            };
+            let query = String::from_utf8_lossy(&datagram[..read]);
+            if query.trim() == format!("DISCOVER {}", PROTOCOL_VERSION) {
+                self.socket
+                    .send_to(self.advert.encode().as_bytes(), sender)?;
+            }
+        }
+    }
+}
+
+/// A game found on the local network
+#[derive(Debug, Clone)]
+pub struct DiscoveredGame {
+    pub address: SocketAddr, // Where to join it
+    pub advert: GameAdvert,
+    last_seen: Instant,
+}
+
+/// Asks the local network for games and keeps the list of those that answer
+pub struct DiscoveryBrowser {
+    socket: UdpSocket,
+    target: SocketAddr, // Where queries are sent, the broadcast address outside tests
+    last_query: Option<Instant>,
+    games: Vec<DiscoveredGame>,
+}
+
+impl DiscoveryBrowser {
+    /// Query every host on the local network
+    pub fn lan() -> io::Result<Self> {
+        DiscoveryBrowser::new((Ipv4Addr::BROADCAST, DISCOVERY_PORT).into())
+    }
+
+    /// Query the responder at `target`
+    pub fn new(target: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(DiscoveryBrowser {
+            socket,
+            target,
+            last_query: None,
+            games: Vec::new(),
+        })
+    }
+
+    /// Games that answered recently, in the order they were found
+    pub fn games(&self) -> &[DiscoveredGame] {
+        &self.games
+    }
+
+    /// Send a query when one is due, read the answers and drop games that
+    /// stopped answering. Returns whether the list changed.
+    pub fn poll(&mut self) -> io::Result<bool> {
+        let now = Instant::now();
+        let query_due = match self.last_query {
+            Some(last) => now.duration_since(last) >= QUERY_INTERVAL,
+            None => true,
+        };
+        if query_due {
+            let query = format!("DISCOVER {}", PROTOCOL_VERSION);
+            self.socket.send_to(query.as_bytes(), self.target)?;
+            self.last_query = Some(now);
+        }
+
+        let mut changed = false;
+        let mut datagram = [0; 512];
+        loop {
+            let (read, sender) = match self.socket.recv_from(&mut datagram) {
+                Ok(received) => received,
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            };
+            let Some(advert) = GameAdvert::decode(&String::from_utf8_lossy(&datagram[..read]))
+            else {
+                continue;
+            };
+            let address = SocketAddr::new(sender.ip(), advert.port);
+            match self.games.iter_mut().find(|game| game.address == address) {
+                Some(game) => {
+                    changed |= game.advert != advert;
+                    game.advert = advert;
+                    game.last_seen = now;
+                }
+                None => {
+                    changed = true;
+                    self.games.push(DiscoveredGame {
+                        address,
+                        advert,
+                        last_seen: now,
+                    });
+                }
+            }
+        }
+
+        let found = self.games.len();
+        self.games
+            .retain(|game| now.duration_since(game.last_seen) < GAME_TIMEOUT);
+        Ok(changed || self.games.len() != found)
+    }
+}
+
+/// Unit tests for LAN discovery
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Adverts survive being encoded and decoded, names may contain spaces
+    #[test]
+    fn test_advert_round_trip() {
+        let advert = GameAdvert {
+            name: "office pc 2".to_string(),
+            port: 7878,
+            players: 1,
+            settings: RoomSettings::default(),
+        };
+        assert_eq!(GameAdvert::decode(&advert.encode()), Some(advert));
+        assert_eq!(GameAdvert::decode("GAME 1 7878 1 3 600 classic old"), None);
+        assert_eq!(GameAdvert::decode("DISCOVER 3"), None);
+    }
+
+    /// A browser finds a responder and lists its game at the game's port
+    #[test]
+    fn test_discovery() {
+        let advert = GameAdvert {
+            name: "host".to_string(),
+            port: 4242,
+            players: 1,
+            settings: RoomSettings::default(),
+        };
+        let mut responder = DiscoveryResponder::bind("127.0.0.1:0", advert.clone()).unwrap();
+        let mut browser = DiscoveryBrowser::new(responder.local_addr().unwrap()).unwrap();
+
+        let start = Instant::now();
+        while browser.games().is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            responder.answer().unwrap();
+            browser.poll().unwrap();
+        }
+        let game = &browser.games()[0];
+        assert_eq!(game.address, "127.0.0.1:4242".parse().unwrap());
+        assert_eq!(game.advert, advert);
+    }
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::prelude::Resource;
+
+use crate::{
+    generate_sub_board_combinations, generate_winning_combinations, has_two_tuples, PlayerTag,
+    Position,
+};
+
+/// Version of the weights file format
+pub const WEIGHTS_VERSION: u32 = 1;
+
+/// Where the bots look for their evaluation weights
+pub const WEIGHTS_PATH: &str = "assets/bot/eval_weights.txt";
+
+/// First line of every weights file
+const WEIGHTS_HEADER: &str = "cascading-tic-tac-toe evaluation weights";
+
+/// Number of evaluation features
+pub const FEATURE_COUNT: usize = 4;
+
+/// Names of the evaluation features, in the order they are stored
+pub const FEATURE_NAMES: [&str; FEATURE_COUNT] =
+    ["open_twos", "blocked_cells", "reach_back", "margin"];
+
+/// Feature values of a position, each counted for X minus the same count for O:
+/// - open_twos: scorable combinations holding two of the player's marks
+/// - blocked_cells: the player's marks on otherwise open combinations that
+///   has_two_tuples blocks, because they share two cells with a scored one
+/// - reach_back: the player's marks on reach-back combinations that open up
+///   when the board next grows
+/// - margin: the score difference
+pub type Features = [i32; FEATURE_COUNT];
+
+/// A resource holding the weight of each evaluation feature
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct EvalWeights(pub [i32; FEATURE_COUNT]);
+
+/// Default constructor for EvalWeights, the hand-written evaluation
+impl Default for EvalWeights {
+    fn default() -> Self {
+        EvalWeights([10, 0, 0, 1000])
+    }
+}
+
+impl EvalWeights {
+    /// Weights file contents
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nversion {}\n", WEIGHTS_HEADER, WEIGHTS_VERSION);
+        for (name, weight) in FEATURE_NAMES.iter().zip(self.0.iter()) {
+            text.push_str(&format!("{} {}\n", name, weight));
+        }
+        text
+    }
+
+    /// Parse a weights file, rejecting other formats and versions
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut lines = text.lines();
+
+        if lines.next() != Some(WEIGHTS_HEADER) {
+            return Err(invalid("not an evaluation weights file".to_string()));
+        }
+        let mut value = |name: &str| -> io::Result<i32> {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| invalid(format!("missing {}", name)))
+        };
+        let version = value("version")?;
+        if version != WEIGHTS_VERSION as i32 {
+            return Err(invalid(format!(
+                "weights version {} is not supported, expected {}",
+                version, WEIGHTS_VERSION
+            )));
+        }
+        let mut weights = [0; FEATURE_COUNT];
+        for (weight, name) in weights.iter_mut().zip(FEATURE_NAMES) {
+            *weight = value(name)?;
+        }
+        Ok(EvalWeights(weights))
+    }
+
+    /// Write the weights to a file, creating its directory if needed
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if let Some(directory) = path.as_ref().parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, self.to_text())
+    }
+
+    /// Read a weights file
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        EvalWeights::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// Read a weights file, or fall back to the default weights
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        match EvalWeights::load(path) {
+            Ok(weights) => weights,
+            Err(error) => {
+                if error.kind() != io::ErrorKind::NotFound {
+                    println!("Evaluation weights not loaded: {}", error);
+                }
+                EvalWeights::default()
+            }
+        }
+    }
+}
+
+/// Feature values of a position, see Features
+pub fn features(position: &Position) -> Features {
+    let n = position.round_count();
+    let mut used = position.game_combinations().clone();
+    // Marks on a combination held by one player only, positive for X
+    let owner = |combination: &[(u32, u32); 3]| {
+        let count = |tag: PlayerTag| {
+            combination
+                .iter()
+                .filter(|&&cell| position.cell(cell) == Some(&tag))
+                .count() as i32
+        };
+        match (count(PlayerTag::X), count(PlayerTag::O)) {
+            (x, 0) => x,
+            (0, o) => -o,
+            _ => 0,
+        }
+    };
+
+    let mut combinations = Vec::new();
+    generate_winning_combinations(n, &mut combinations);
+    let mut open_twos = 0;
+    let mut blocked_cells = 0;
+    for combination in combinations.iter() {
+        if used.contains(combination) {
+            continue;
+        }
+        let marks = owner(combination);
+        if has_two_tuples(&mut used, combination) {
+            blocked_cells += marks;
+        } else if marks.abs() == 2 {
+            open_twos += marks.signum();
+        }
+    }
+
+    // The first 8 combinations of a sub-board lie inside it, the rest reach back
+    let mut next = Vec::new();
+    generate_sub_board_combinations(n + 1, &mut next);
+    let mut reach_back = 0;
+    for combination in next[8..].iter() {
+        if !has_two_tuples(&mut used, combination) {
+            reach_back += owner(combination);
+        }
+    }
+
+    let margin = position.x_score() as i32 - position.o_score() as i32;
+    [open_twos, blocked_cells, reach_back, margin]
+}
+
+/// Static evaluation of a position for X
+pub fn evaluate(position: &Position, weights: &EvalWeights) -> i32 {
+    features(position)
+        .iter()
+        .zip(weights.0.iter())
+        .map(|(feature, weight)| feature * weight)
+        .sum()
+}
+
+/// Unit tests for the evaluation function
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    /// Position reached by playing the given moves from the empty board
+    fn play(moves: &[(u32, u32)]) -> Position {
+        moves
+            .iter()
+            .fold(Position::new(), |position, &cell| position.play(cell))
+    }
+
+    /// Features of hand-checked positions
+    #[test_case(&[], [0, 0, 0, 0])]
+    #[test_case(&[(0, 0), (2, 2), (0, 1)], [1, 0, -1, 0])]
+    #[test_case(&[(2, 1), (0, 0), (2, 2)], [1, 0, 4, 0])]
+    #[test_case(&[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)], [-1, 0, 0, 1])]
+    #[test_case(&[(0, 1), (0, 0), (1, 1), (1, 0), (2, 1)], [-1, 2, 0, 1])]
+    fn test_features(moves: &[(u32, u32)], expected: Features) {
+        assert_eq!(features(&play(moves)), expected);
+    }
+
+    /// Weights survive being written out and read back, other versions are rejected
+    #[test]
+    fn test_weights_file() {
+        let weights = EvalWeights([3, -4, 5, 600]);
+        assert_eq!(EvalWeights::from_text(&weights.to_text()).unwrap(), weights);
+        let text = weights.to_text().replace("version 1", "version 2");
+        assert!(EvalWeights::from_text(&text).is_err());
+    }
+}
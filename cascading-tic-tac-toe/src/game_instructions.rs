@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{GameScreenTag, GameState, PlayerTag, PlayerTurn};
+use crate::{EndlessMode, GameScreenTag, GameState, PlayerTag, PlayerTurn, RoundInit, TieBreak, TieRule};
 use crate::theme::theme::UiTheme;
 
 /// Component to hold the gameplay instructions text
@@ -59,10 +59,28 @@ pub fn setup_instructions(mut commands: Commands, theme: Res<UiTheme>, asset_ser
     });
 }
 
+/// How the game was decided, for the result text: the final score of an
+/// endless game, or the tie rule of a timed game that ran out level
+fn result_detail(tie_break: &TieBreak, endless: &EndlessMode, round: &RoundInit) -> String {
+    if endless.0 {
+        return format!(" {} - {} after {} rounds", round.x_score, round.o_score, round.round_count);
+    }
+    match tie_break.0 {
+        Some(TieRule::SuddenDeath) => " in sudden death",
+        Some(TieRule::Overtime) => " in overtime",
+        Some(TieRule::TiebreakRound) => " in the tiebreak round",
+        Some(TieRule::Draw) | None => "",
+    }
+    .to_string()
+}
+
 /// System to update the game instructions based on state changes
 pub fn update_instruction_on_state_change(
     player_turn_state: Res<State<PlayerTurn>>,
     game_state: Res<State<GameState>>,
+    tie_break: Res<TieBreak>,
+    endless: Res<EndlessMode>,
+    round: Res<RoundInit>,
     mut instructions: Query<&mut Text, With<InstructionText>>,
 ) {
     // If player turn changes, update instruction text accordingly
@@ -78,11 +96,14 @@ pub fn update_instruction_on_state_change(
     // If game state changes, update instruction text accordingly
     if game_state.is_changed() {
         let mut ui_text = instructions.single_mut();
+        let decided = result_detail(&tie_break, &endless, &round);
         
         match game_state.get() {
-            &GameState::Won(PlayerTag::X) => ui_text.sections[0].value = "X Won!!!".to_string(),
-            &GameState::Won(PlayerTag::O) => ui_text.sections[0].value = "O Won!!!".to_string(),
+            &GameState::Won(PlayerTag::X) => ui_text.sections[0].value = format!("X Won{}!!!", decided),
+            &GameState::Won(PlayerTag::O) => ui_text.sections[0].value = format!("O Won{}!!!", decided),
+            &GameState::Draw => ui_text.sections[0].value = format!("Draw{}!!!", decided),
             &GameState::GameOngoing => (),
+            &GameState::Paused => (),
             &GameState::RestartingGame => (),
             &GameState::Updating => (),
             &GameState::NotPlaying => (),
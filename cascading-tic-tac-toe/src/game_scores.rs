@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 
 use crate::theme::theme::UiTheme;
-use crate::{GameScreenTag, RoundInit};
+use crate::{EndlessMode, GameScreenTag, PlayerClockText, PlayerClocks, RoundInit};
 
 /// Component to store the game scores text
 #[derive(Component)]
@@ -45,14 +45,29 @@ fn text(asset_server: &Res<AssetServer>, theme: &Res<UiTheme>, label: &str) -> T
     };
 }
 
-/// System to set up the game scores
-pub fn setup_scores_text(mut commands: Commands, theme: Res<UiTheme>, asset_server: Res<AssetServer>, round: Res<RoundInit>) {
+/// System to set up the game scores, with the players' clocks under them when the game has them
+/// and the round being played in endless games
+pub fn setup_scores_text(
+    mut commands: Commands,
+    theme: Res<UiTheme>,
+    asset_server: Res<AssetServer>,
+    round: Res<RoundInit>,
+    clocks: Res<PlayerClocks>,
+    endless: Res<EndlessMode>,
+) {
     
-    let label = format!("X Score: {}\nO Score: {}", round.x_score, round.o_score);
+    let mut label = format!("X Score: {}\nO Score: {}", round.x_score, round.o_score);
+    if endless.0 {
+        label.push_str(&format!("\nRound: {}", round.round_count + 1));
+    }
 
     commands.spawn(root()).with_children(|parent| {
         parent
             .spawn((text(&asset_server, &theme, &label), GameScreenTag)) // Spawn text node for instruction
             .insert(ScoresText); // Add ScoresText component to the text node entity
+        if clocks.is_running() {
+            // Filled in by update_player_clock_text
+            parent.spawn((text(&asset_server, &theme, ""), PlayerClockText, GameScreenTag));
+        }
     });
 }
\ No newline at end of file
@@ -1,15 +1,25 @@
+use std::time::Duration;
+
 use crate::utils::despawn_screen::despawn_screen;
 use crate::{
-    board_cell_interaction_system, button_interactions, on_cell_clicked, setup_board,
-    setup_instructions, setup_menu_button, setup_scores_text, update_instruction_on_state_change,
-    GameState, PlayerTag, PlayerTurn, PlayingState, RoundInit, RoundState, WinningLogicPlugin,
+    board_cell_interaction_system, board_view_moved, button_interactions, fit_board,
+    on_cell_clicked, reset_player_clocks, scroll_board, setup_board, setup_instructions,
+    setup_menu_button, setup_move_log_text, setup_scores_text, update_instruction_on_state_change,
+    update_move_log_text, BoardGrid, BoardRoot, BoardView, BotPlugin, ChatPlugin, EndlessMode,
+    FirstPlayer, GameState, LocalPlayers, MoveLog, OnlinePlugin, OpeningBook, PlayerClockPlugin,
+    PlayerTag, PlayerTurn, PlayingState, RoundInit, RoundState, TargetScore, TranspositionTable,
+    TurnLimitPlugin, WinningLogicPlugin, BOOK_PATH,
 };
 
-use crate::timer::{time, Counter, TEXT_COLOR, TIME};
+use crate::timer::{
+    reset_clock, time, update_clock_text, ClockText, GameClock, TieBreak, TieRule, TimeLimit,
+    TimedMode, OVERTIME, TEXT_COLOR,
+};
+use crate::winning_logic::is_round_over;
+use crate::winner_on_time;
 use bevy::app::{App, Plugin, Update};
 use bevy::prelude::*;
 use bevy::prelude::{in_state, Component, IntoSystemConfigs, NextState, OnEnter, ResMut};
-use std::time::Duration;
 
 /// Component that stores the gameboard screen to spawn/despawn
 #[derive(Component)]
@@ -23,18 +33,38 @@ impl Plugin for GameScreen {
     fn build(&self, app: &mut App) {
         app.add_event::<crate::CellClickedEvent>()
             // setup
-            .insert_resource(RoundInit::new(3))
+            .insert_resource(RoundInit::new(TargetScore::default().0))
+            .insert_resource(OpeningBook::load_or_default(BOOK_PATH))
+            .init_resource::<MoveLog>()
+            .init_resource::<BoardGrid>()
+            .init_resource::<BoardView>()
+            .init_resource::<LocalPlayers>()
+            .init_resource::<TimeLimit>()
+            .init_resource::<TimedMode>()
+            .init_resource::<EndlessMode>()
+            .init_resource::<GameClock>()
+            .init_resource::<TieRule>()
+            .init_resource::<TieBreak>()
             .insert_state(GameState::NotPlaying)
             .insert_state(RoundState::NotUpdating)
             .insert_state(PlayerTurn::X)
             .add_plugins(WinningLogicPlugin)
+            .add_plugins(BotPlugin)
+            .add_plugins(OnlinePlugin)
+            .add_plugins(ChatPlugin)
+            .add_plugins(PlayerClockPlugin)
+            .add_plugins(TurnLimitPlugin)
             .add_systems(
                 OnEnter(GameState::LoadingNewGame),
                 (
+                    reset_clock,
+                    reset_player_clocks,
+                    fit_board,
                     setup_board,
                     setup_menu_button,
                     setup_instructions,
                     setup_scores_text,
+                    setup_move_log_text,
                     setup_timer_text,
                     loading_finished,
                 )
@@ -45,10 +75,12 @@ impl Plugin for GameScreen {
                 (
                     despawn_screen::<GameScreenTag>,
                     (
+                        fit_board,
                         setup_board,
                         setup_menu_button,
                         setup_instructions,
                         setup_scores_text,
+                        setup_move_log_text,
                         setup_timer_text,
                         loading_finished,
                     )
@@ -57,27 +89,52 @@ impl Plugin for GameScreen {
             )
             // interactions
             .add_systems(Update, button_interactions)
+            .add_systems(Update, update_clock_text.after(update_time))
             .add_systems(
                 Update,
                 (
                     board_cell_interaction_system,
                     on_cell_clicked,
                     update_instruction_on_state_change,
+                    update_move_log_text,
                     update_time,
                 )
                     // .chain()
                     .run_if(in_state(GameState::GameOngoing)),
             )
+            // Only the board in view is laid out, again whenever it is scrolled
+            .add_systems(
+                Update,
+                (
+                    scroll_board,
+                    (despawn_screen::<BoardRoot>, setup_board)
+                        .chain()
+                        .run_if(board_view_moved),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::GameOngoing)),
+            )
+            // Online games end when the server or host says time is up
+            .add_systems(
+                Update,
+                end_timed_game
+                    .after(update_time)
+                    .after(is_round_over)
+                    .run_if(in_state(GameState::GameOngoing))
+                    .run_if(not(in_state(PlayingState::Online))),
+            )
             .add_systems(
                 OnEnter(GameState::Won(PlayerTag::X)),
                 (
                     despawn_screen::<GameScreenTag>,
                     (
+                        fit_board,
                         setup_board,
                         setup_menu_button,
                         setup_instructions,
                         update_instruction_on_state_change,
                         setup_scores_text,
+                        setup_move_log_text,
                         setup_timer_text,
                         loading_finished,
                     )
@@ -89,11 +146,31 @@ impl Plugin for GameScreen {
                 (
                     despawn_screen::<GameScreenTag>,
                     (
+                        fit_board,
+                        setup_board,
+                        setup_menu_button,
+                        setup_instructions,
+                        update_instruction_on_state_change,
+                        setup_scores_text,
+                        setup_move_log_text,
+                        setup_timer_text,
+                        loading_finished,
+                    )
+                        .chain(),
+                ),
+            )
+            .add_systems(
+                OnEnter(GameState::Draw),
+                (
+                    despawn_screen::<GameScreenTag>,
+                    (
+                        fit_board,
                         setup_board,
                         setup_menu_button,
                         setup_instructions,
                         update_instruction_on_state_change,
                         setup_scores_text,
+                        setup_move_log_text,
                         setup_timer_text,
                         loading_finished,
                     )
@@ -102,6 +179,8 @@ impl Plugin for GameScreen {
             )
             // teardown
             .add_systems(OnExit(PlayingState::Local), despawn_screen::<GameScreenTag>)
+            .add_systems(OnExit(PlayingState::VsComputer), despawn_screen::<GameScreenTag>)
+            .add_systems(OnExit(PlayingState::Online), despawn_screen::<GameScreenTag>)
             //restarting game
             .add_systems(
                 OnEnter(GameState::RestartingGame),
@@ -128,13 +207,30 @@ fn loading_finished(
     next_game_state.set(GameState::GameOngoing);
 }
 
-/// A system that triggers restarting the game by changing states
+/// A system that triggers restarting the game by changing states. Local
+/// games keep the target and first player chosen on the new game menu,
+/// online games keep to the defaults both sides agree on.
+#[allow(clippy::too_many_arguments)]
 fn restart_game(
     mut round_init: ResMut<RoundInit>,
     mut next_player_turn: ResMut<NextState<PlayerTurn>>,
+    mut move_log: ResMut<MoveLog>,
+    mut grid: ResMut<BoardGrid>,
+    mut table: ResMut<TranspositionTable>,
+    playing_state: Res<State<PlayingState>>,
+    target: Res<TargetScore>,
+    first_player: Res<FirstPlayer>,
 ) {
-    *round_init = RoundInit::new(3);
-    next_player_turn.set(PlayerTurn::X);
+    if *playing_state.get() == PlayingState::Online {
+        *round_init = RoundInit::new(TargetScore::default().0);
+        next_player_turn.set(PlayerTurn::X);
+    } else {
+        *round_init = RoundInit::new(target.0);
+        next_player_turn.set(first_player.turn());
+    }
+    *move_log = MoveLog::default();
+    *grid = BoardGrid::default();
+    table.clear();
 }
 
 /// A system that flags when restarting the game has completed
@@ -142,45 +238,126 @@ fn finished_restarting(mut next_game_state: ResMut<NextState<GameState>>) {
     next_game_state.set(GameState::LoadingNewGame);
 }
 
-/// A system that handles the timer countdown in the gamescreen
-fn setup_timer_text(mut commands: Commands, asset_sever: Res<AssetServer>) {
-    let counter = Counter::new();
-    //counter.pause();
-
-    commands
-        .spawn((
-            TextBundle {
-                text: Text::from_section(
-                    format!("{}", time(Duration::from_secs(TIME.into()))),
-                    TextStyle {
-                        font: asset_sever.load("fonts/FiraMono-Medium.ttf"),
-                        font_size: 50.,
-                        color: TEXT_COLOR,
-                    },
-                ),
-                style: Style {
-                    position_type: PositionType::Absolute,
-                    ..default()
+/// A system that shows the game clock in the gamescreen
+fn setup_timer_text(
+    mut commands: Commands,
+    asset_sever: Res<AssetServer>,
+    clock: Res<GameClock>,
+) {
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                time(clock.remaining()),
+                TextStyle {
+                    font: asset_sever.load("fonts/FiraMono-Medium.ttf"),
+                    font_size: 50.,
+                    color: TEXT_COLOR,
                 },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
                 ..default()
             },
-            GameScreenTag,
-        ))
-        .insert(counter);
+            ..default()
+        },
+        ClockText,
+        GameScreenTag,
+    ));
 }
 
-/// A system that updates the current value of the countdown timer
-fn update_time(mut query: Query<(&mut Text, &mut Counter)>, os_time: Res<Time>) {
-    for (mut text, mut counter) in &mut query {
-        if counter.paused() {
-            continue;
-        }
-        counter.tick(os_time.delta());
-        if counter.unit_just_finished() {
-            text.sections[0].value = format!(
-                "{}",
-                time(counter.duration() - Duration::from_secs_f32(counter.elapsed_secs_round()))
-            )
+/// A system that counts down the game clock
+fn update_time(mut clock: ResMut<GameClock>, os_time: Res<Time>) {
+    if clock.paused() {
+        return;
+    }
+    clock.tick(os_time.delta());
+}
+
+/// A system that ends a timed game once its clock runs out: the higher
+/// score wins, and equal scores are a draw or go on to the chosen tie rule
+#[allow(clippy::too_many_arguments)]
+fn end_timed_game(
+    timed: Res<TimedMode>,
+    tie_rule: Res<TieRule>,
+    mut tie_break: ResMut<TieBreak>,
+    mut round_init: ResMut<RoundInit>,
+    mut grid: ResMut<BoardGrid>,
+    mut clock: ResMut<GameClock>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_round_state: ResMut<NextState<RoundState>>,
+) {
+    if !timed.0 {
+        return;
+    }
+    let winner = winner_on_time(round_init.x_score, round_init.o_score);
+    let decided = match tie_break.0 {
+        // The first line scored since the clock ran out decides the game
+        Some(TieRule::SuddenDeath) => winner.is_some(),
+        // The fresh sub-board is over once it is scored or filled
+        Some(TieRule::TiebreakRound) => winner.is_some() || round_init.round_count > 0,
+        _ => !clock.paused() && clock.just_finished(),
+    };
+    if !decided {
+        return;
+    }
+    if winner.is_none() && tie_break.0.is_none() && *tie_rule != TieRule::Draw {
+        tie_break.0 = Some(*tie_rule);
+        match *tie_rule {
+            TieRule::Overtime => clock.extend(Duration::from_secs(OVERTIME.into())),
+            TieRule::TiebreakRound => {
+                clock.pause();
+                // Rebuild the board as a single empty sub-board, even when
+                // the game tied on round 0
+                round_init.round_count = 0;
+                round_init.game_combinations.clear();
+                *grid = BoardGrid::default();
+                next_game_state.set(GameState::Updating);
+                next_round_state.set(RoundState::UpdatingRound);
+            }
+            _ => clock.pause(),
         }
+        return;
+    }
+    clock.pause();
+    next_round_state.set(RoundState::NotUpdating);
+    next_game_state.set(match winner {
+        Some(winner) => GameState::Won(winner),
+        None => GameState::Draw,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    /// A game tied on its first round plays the tiebreak on an empty sub-board
+    #[test]
+    fn test_round_zero_tiebreak() {
+        let mut world = World::new();
+        let mut clock = GameClock::new(1);
+        clock.tick(Duration::from_secs(1));
+        let mut grid = BoardGrid::default();
+        grid.fill((2, 2), PlayerTag::X);
+        grid.fill((2, 3), PlayerTag::O);
+        world.insert_resource(TimedMode(true));
+        world.insert_resource(TieRule::TiebreakRound);
+        world.insert_resource(TieBreak(None));
+        world.insert_resource(RoundInit::new(TargetScore::default().0));
+        world.insert_resource(grid);
+        world.insert_resource(clock);
+        world.init_resource::<NextState<GameState>>();
+        world.init_resource::<NextState<RoundState>>();
+
+        world.run_system_once(end_timed_game);
+
+        assert_eq!(world.resource::<TieBreak>().0, Some(TieRule::TiebreakRound));
+        let grid = world.resource::<BoardGrid>();
+        assert_eq!(grid.round_count(), 0);
+        assert_eq!(grid.filled_cells().count(), 0);
+        assert_eq!(
+            world.resource::<NextState<GameState>>().0,
+            Some(GameState::Updating)
+        );
     }
 }
@@ -1,6 +1,7 @@
 use bevy::prelude::*;
 
-use crate::{GameScreenTag, GameState, MenuState, PlayingState, RoundState};
+use crate::pause_menu::pause_game;
+use crate::{GameScreenTag, GameState, LocalPlayers, MenuState, PlayingState, ResignEvent, RestartEvent, RoundState};
 use crate::theme::theme::UiTheme;
 
 /// Component that stores all in-game menu buttons
@@ -8,6 +9,8 @@ use crate::theme::theme::UiTheme;
 pub enum InGameButtonActions{
     MainMenuButton,
     RestartButton,
+    ResignButton,
+    PauseButton,
 }
 
 
@@ -21,6 +24,10 @@ fn root(button:InGameButtonActions) -> NodeBundle {
         InGameButtonActions::MainMenuButton=>{
             height = 14.0;
         } 
+        // Online games have a resign button, and never pause
+        InGameButtonActions::ResignButton | InGameButtonActions::PauseButton=>{
+            height = 21.0;
+        }
    }
     NodeBundle {
         style: Style {
@@ -77,19 +84,26 @@ pub fn button_text_game(
     };
 }
 
-/// System to set up the restart button
+/// System to set up the restart button, and the resign button in online games
+/// or the pause button in the others.
+/// Spectators, who control neither player, only get the main menu button.
 pub fn setup_menu_button(
     mut commands: Commands,
     theme: Res<UiTheme>,
     asset_server: Res<AssetServer>,
+    playing_state: Res<State<PlayingState>>,
+    local_players: Res<LocalPlayers>,
 ) {
-    commands.spawn((root(InGameButtonActions::RestartButton), GameScreenTag)).with_children(|parent| {
-        parent
-            .spawn((button_game(&theme),InGameButtonActions::RestartButton))
-            .with_children(|parent| {
-                parent.spawn(button_text_game(&asset_server, &theme, "Restart"));
-            });
-    });
+    let spectating = local_players.0.is_empty();
+    if !spectating {
+        commands.spawn((root(InGameButtonActions::RestartButton), GameScreenTag)).with_children(|parent| {
+            parent
+                .spawn((button_game(&theme),InGameButtonActions::RestartButton))
+                .with_children(|parent| {
+                    parent.spawn(button_text_game(&asset_server, &theme, "Restart"));
+                });
+        });
+    }
     commands.spawn((root(InGameButtonActions::MainMenuButton), GameScreenTag)).with_children(|parent| {
         parent
             .spawn((button_game(&theme),InGameButtonActions::MainMenuButton))
@@ -97,20 +111,47 @@ pub fn setup_menu_button(
                 parent.spawn(button_text_game(&asset_server, &theme, "Main Menu"));
             });
     });
+    if *playing_state.get() == PlayingState::Online && !spectating {
+        commands.spawn((root(InGameButtonActions::ResignButton), GameScreenTag)).with_children(|parent| {
+            parent
+                .spawn((button_game(&theme),InGameButtonActions::ResignButton))
+                .with_children(|parent| {
+                    parent.spawn(button_text_game(&asset_server, &theme, "Resign"));
+                });
+        });
+    }
+    if *playing_state.get() != PlayingState::Online {
+        commands.spawn((root(InGameButtonActions::PauseButton), GameScreenTag)).with_children(|parent| {
+            parent
+                .spawn((button_game(&theme),InGameButtonActions::PauseButton))
+                .with_children(|parent| {
+                    parent.spawn(button_text_game(&asset_server, &theme, "Pause"));
+                });
+        });
+    }
 }
 
 /// System to handle interactions with the in game menu buttons
+#[allow(clippy::too_many_arguments)]
 pub fn button_interactions(
     theme: Res<UiTheme>,
     mut buttons: Query<
         (&Interaction, &InGameButtonActions, &mut BackgroundColor),
         (Changed<Interaction>, With<Button>),
     >,
+    game_state: Res<State<GameState>>,
     mut next_game_state: ResMut<NextState<GameState>>,
     mut next_menu_state: ResMut<NextState<MenuState>>,
     mut next_playing_state: ResMut<NextState<PlayingState>>,
     mut next_round_state: ResMut<NextState<RoundState>>,
+    mut send_resign: EventWriter<ResignEvent>,
+    mut send_restart: EventWriter<RestartEvent>,
+    playing_state: Res<State<PlayingState>>,
 ) {
+    // The pause menu has its own buttons
+    if *game_state.get() == GameState::Paused {
+        return;
+    }
     for (interaction,in_game_menu_button_action,mut color) in buttons.iter_mut() {
         match *interaction {
             Interaction::Pressed => {
@@ -124,8 +165,24 @@ pub fn button_interactions(
                     }
                     InGameButtonActions::RestartButton =>{
                         *color = theme.button;
-                        next_game_state.set(GameState::RestartingGame);
-                        next_round_state.set(RoundState::NotUpdating);
+                        // Online games may need the opponent to agree
+                        if *playing_state.get() == PlayingState::Online {
+                            send_restart.send(RestartEvent);
+                        } else {
+                            next_game_state.set(GameState::RestartingGame);
+                            next_round_state.set(RoundState::NotUpdating);
+                        }
+                    }
+                    InGameButtonActions::ResignButton =>{
+                        *color = theme.button;
+                        send_resign.send(ResignEvent);
+                    }
+                    InGameButtonActions::PauseButton =>{
+                        *color = theme.button;
+                        // Only a game in play can be paused
+                        if *game_state.get() == GameState::GameOngoing {
+                            pause_game(&mut next_game_state, &mut next_menu_state);
+                        }
                     }
                 }
                 
@@ -0,0 +1,74 @@
+pub use board::*;
+pub use board_grid::*;
+pub use bot::*;
+pub use chat::*;
+pub use components::*;
+pub use correspondence::*;
+pub use discovery::*;
+pub use evaluation::*;
+pub use game_instructions::*;
+pub use in_game_menu::*;
+pub use timer::*;
+pub use game_screen::*;
+pub use game_scores::*;
+pub use menus::*;
+pub use move_log::*;
+pub use online::*;
+pub use opening_book::*;
+pub use player_clock::*;
+pub use position::*;
+pub use protocol::*;
+pub use ratings::*;
+pub use resources::*;
+pub use server::*;
+pub use solver::*;
+pub use states::*;
+pub use symmetry::*;
+pub use transposition::*;
+pub use turn_limit::*;
+pub use tuning::*;
+pub use winning_logic::*;
+
+mod board;
+mod board_grid;
+mod bot;
+mod chat;
+mod components;
+mod correspondence;
+mod discovery;
+mod evaluation;
+mod game_instructions;
+mod game_screen;
+mod game_scores;
+mod in_game_menu;
+mod menus;
+mod move_log;
+mod online;
+mod opening_book;
+mod player_clock;
+mod position;
+mod protocol;
+mod ratings;
+mod resources;
+mod server;
+mod solver;
+mod states;
+mod symmetry;
+mod transposition;
+mod turn_limit;
+mod tuning;
+mod winning_logic;
+mod timer;
+
+pub mod ui_components {
+    pub mod bundles;
+}
+
+pub mod theme {
+    pub mod theme;
+}
+
+pub mod utils {
+    pub mod despawn_screen;
+    pub mod modify_text;
+}
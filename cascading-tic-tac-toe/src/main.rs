@@ -1,50 +1,14 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use crate::theme::theme::UiTheme;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy::winit::WinitWindows;
 use bevy_kira_audio::prelude::*;
+use cascading_tic_tac_toe::theme::theme::UiTheme;
+use cascading_tic_tac_toe::*;
 use std::io::Cursor;
 use winit::window::Icon;
 
-pub use board::*;
-pub use components::*;
-pub use game_instructions::*;
-pub use in_game_menu::*;
-pub use timer::*;
-pub use game_screen::*;
-pub use game_scores::*;
-pub use menus::*;
-pub use resources::*;
-pub use states::*;
-pub use winning_logic::*;
-
-mod board;
-mod components;
-mod game_instructions;
-mod game_screen;
-mod game_scores;
-mod in_game_menu;
-mod menus;
-mod resources;
-mod states;
-mod winning_logic;
-mod timer;
-
-mod ui_components {
-    pub mod bundles;
-}
-
-mod theme {
-    pub mod theme;
-}
-
-mod utils {
-    pub mod despawn_screen;
-    pub mod modify_text;
-}
-
 /// This is the main driver of the application
 /// All primary Plugins, Resources, and States are included here
 fn main() {
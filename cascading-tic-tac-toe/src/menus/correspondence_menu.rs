@@ -0,0 +1,417 @@
+use crate::{
+    host_name, main_menu::*, online_menu::*, unix_time, valid_player_name, ButtonActions,
+    CorrespondenceInfo, MenuState, Message, OnCorrespondenceMenuScreen, OnlineSession, PlayerTag,
+    PlayerTurn, MAX_NAME_LENGTH,
+};
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+
+/// Days per move the Deadline button cycles through, 0 for no deadline
+const DEADLINES: [u32; 4] = [0, 1, 3, 7];
+
+/// Name the server knows the player by, typed on the correspondence and
+/// leaderboard menus
+#[derive(Resource)]
+pub struct PlayerName(pub String);
+
+/// Default constructor for PlayerName, the name of this machine without spaces
+impl Default for PlayerName {
+    fn default() -> Self {
+        let name: String = host_name()
+            .chars()
+            .filter(|character| character.is_ascii_graphic())
+            .take(MAX_NAME_LENGTH)
+            .collect();
+        PlayerName(if name.is_empty() || name == "-" {
+            "player".to_string()
+        } else {
+            name
+        })
+    }
+}
+
+/// Days per move of the games challenged from the menu
+#[derive(Resource)]
+pub struct CorrespondenceDeadline(pub u32);
+
+/// Default constructor for CorrespondenceDeadline, three days per move
+impl Default for CorrespondenceDeadline {
+    fn default() -> Self {
+        CorrespondenceDeadline(3)
+    }
+}
+
+/// The player's games and open challenges, last listed by the server
+#[derive(Resource, Default)]
+pub struct CorrespondenceList(pub Vec<CorrespondenceInfo>);
+
+/// Actions of the correspondence menu buttons
+#[derive(Component)]
+pub enum CorrespondenceMenuAction {
+    Deadline, // Next deadline for a new game
+    Challenge,
+    Refresh,
+    Back,
+    OpenGame(u32),
+    AcceptGame(u32),
+}
+
+/// Tag component for the node holding the game list
+#[derive(Component)]
+pub struct CorrespondenceGameList;
+
+/// Tag component for the typed name and the deadline text
+#[derive(Component)]
+pub struct CorrespondenceSettingsText;
+
+/// Description of a deadline in days per move
+fn deadline_label(days: u32) -> String {
+    match days {
+        0 => "no deadline".to_string(),
+        1 => "1 day per move".to_string(),
+        days => format!("{} days per move", days),
+    }
+}
+
+/// Time left until `due`, in days and hours
+fn due_label(due: u64, now: u64) -> String {
+    let hours = due.saturating_sub(now) / (60 * 60);
+    if hours >= 24 {
+        format!("due in {}d {}h", hours / 24, hours % 24)
+    } else {
+        format!("due in {}h", hours)
+    }
+}
+
+/// How a game is listed to `player`, and what its button does
+fn game_entry(
+    game: &CorrespondenceInfo,
+    player: &str,
+    now: u64,
+) -> (String, CorrespondenceMenuAction) {
+    let Some(o) = &game.o else {
+        if game.x == player {
+            let label = format!("Game {}: waiting for an opponent", game.id);
+            return (label, CorrespondenceMenuAction::OpenGame(game.id));
+        }
+        let label = format!("Accept game {} from {}", game.id, game.x);
+        return (label, CorrespondenceMenuAction::AcceptGame(game.id));
+    };
+    let (seat, opponent) = if game.x == player {
+        (PlayerTag::X, o)
+    } else {
+        (PlayerTag::O, &game.x)
+    };
+    let status = match &game.winner {
+        Some(winner) if *winner == seat => "you won".to_string(),
+        Some(_) => "you lost".to_string(),
+        None => {
+            let mine = matches!(
+                (&game.turn, &seat),
+                (PlayerTurn::X, PlayerTag::X) | (PlayerTurn::O, PlayerTag::O)
+            );
+            let turn = if mine { "your turn" } else { "their turn" };
+            match game.due {
+                Some(due) => format!("{}, {}", turn, due_label(due, now)),
+                None => turn.to_string(),
+            }
+        }
+    };
+    let label = format!("Game {} against {}: {}", game.id, opponent, status);
+    (label, CorrespondenceMenuAction::OpenGame(game.id))
+}
+
+/// Name and deadline shown above the buttons
+fn settings_text(name: &PlayerName, deadline: &CorrespondenceDeadline) -> String {
+    format!(
+        "Name: {}   New game: {}",
+        name.0,
+        deadline_label(deadline.0)
+    )
+}
+
+/// Spawn a menu button
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    action: CorrespondenceMenuAction,
+    text: String,
+    width: f32,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Px(10.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: NORMAL_BUTTON.into(),
+                ..default()
+            },
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 25.0,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// System to setup the correspondence menu and ask the server for the player's games
+pub fn correspondence_menu_setup(
+    mut commands: Commands,
+    name: Res<PlayerName>,
+    deadline: Res<CorrespondenceDeadline>,
+    mut session: ResMut<OnlineSession>,
+    mut status: ResMut<OnlineStatus>,
+) {
+    let text_style = TextStyle {
+        font_size: 25.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    if valid_player_name(&name.0) {
+        let message = Message::Games {
+            player: name.0.clone(),
+        };
+        if let Err(error) = session.send(&message) {
+            status.0 = format!("Could not list games: {}", error);
+        }
+    }
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnCorrespondenceMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(20.0)),
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "My Games",
+                        TextStyle {
+                            font_size: 40.0,
+                            color: TEXT_COLOR,
+                            ..default()
+                        },
+                    ));
+                    // Games are listed here as buttons that open or accept them
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                margin: UiRect::all(Val::Px(10.0)),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        CorrespondenceGameList,
+                    ));
+                    // The name is typed directly, backspace deletes
+                    parent.spawn((
+                        TextBundle::from_section(
+                            settings_text(&name, &deadline),
+                            text_style.clone(),
+                        ),
+                        CorrespondenceSettingsText,
+                    ));
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::CRIMSON.into(),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for (action, text) in [
+                                (CorrespondenceMenuAction::Deadline, "Deadline"),
+                                (CorrespondenceMenuAction::Challenge, "New Game"),
+                                (CorrespondenceMenuAction::Refresh, "Refresh"),
+                                (CorrespondenceMenuAction::Back, "Back"),
+                            ] {
+                                spawn_button(parent, action, text.into(), 180.0);
+                            }
+                        });
+                    parent.spawn((
+                        TextBundle::from_section(status.0.clone(), text_style),
+                        OnlineStatusText,
+                    ));
+                });
+        });
+}
+
+/// System to type the player's name
+pub fn player_name_input(
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut name: ResMut<PlayerName>,
+) {
+    for event in characters.read() {
+        for character in event.char.chars() {
+            if character.is_ascii_graphic() && name.0.len() < MAX_NAME_LENGTH {
+                name.0.push(character);
+            }
+        }
+    }
+    if keys.just_pressed(KeyCode::Backspace) {
+        name.0.pop();
+    }
+}
+
+/// System to handle the correspondence menu buttons
+pub fn correspondence_menu_action(
+    interaction_query: ButtonActions<CorrespondenceMenuAction>,
+    mut commands: Commands,
+    mut session: ResMut<OnlineSession>,
+    name: Res<PlayerName>,
+    mut deadline: ResMut<CorrespondenceDeadline>,
+    mut status: ResMut<OnlineStatus>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let player = name.0.clone();
+        let message = match action {
+            CorrespondenceMenuAction::Deadline => {
+                let index = DEADLINES.iter().position(|days| *days == deadline.0);
+                deadline.0 = DEADLINES[index.map_or(0, |index| (index + 1) % DEADLINES.len())];
+                continue;
+            }
+            CorrespondenceMenuAction::Back => {
+                commands.remove_resource::<OnlineSession>();
+                status.0.clear();
+                menu_state.set(MenuState::Online);
+                continue;
+            }
+            CorrespondenceMenuAction::Challenge => Message::Challenge {
+                player,
+                days: deadline.0,
+            },
+            CorrespondenceMenuAction::Refresh => Message::Games { player },
+            CorrespondenceMenuAction::OpenGame(game) => Message::Open {
+                player,
+                game: *game,
+            },
+            CorrespondenceMenuAction::AcceptGame(game) => Message::Accept {
+                player,
+                game: *game,
+            },
+        };
+        // The server drops connections that send a malformed name
+        if !valid_player_name(&name.0) {
+            status.0 = "Type your name first".to_string();
+            continue;
+        }
+        status.0.clear();
+        if let Err(error) = session.send(&message) {
+            status.0 = format!("Could not reach the server: {}", error);
+        }
+    }
+}
+
+/// System to list the player's games and show the typed name and deadline
+pub fn update_correspondence_menu(
+    mut commands: Commands,
+    games: Res<CorrespondenceList>,
+    name: Res<PlayerName>,
+    deadline: Res<CorrespondenceDeadline>,
+    list: Query<(Entity, Ref<CorrespondenceGameList>)>,
+    mut settings_texts: Query<&mut Text, With<CorrespondenceSettingsText>>,
+) {
+    if name.is_changed() || deadline.is_changed() {
+        for mut text in settings_texts.iter_mut() {
+            text.sections[0].value = settings_text(&name, &deadline);
+        }
+    }
+    let now = unix_time();
+    for (entity, tag) in &list {
+        // Also filled when the screen has just been spawned
+        if !games.is_changed() && !tag.is_added() {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .despawn_descendants()
+            .with_children(|parent| {
+                let text_style = TextStyle {
+                    font_size: 25.0,
+                    color: TEXT_COLOR,
+                    ..default()
+                };
+                if games.0.is_empty() {
+                    parent.spawn(TextBundle::from_section(
+                        "No games yet, start one with New Game",
+                        text_style,
+                    ));
+                }
+                for game in &games.0 {
+                    let (label, action) = game_entry(game, &name.0, now);
+                    spawn_button(parent, action, label, 600.0);
+                }
+            });
+    }
+}
+
+/// Unit tests for the correspondence menu
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Games are described from the point of view of the player
+    #[test]
+    fn test_game_entry() {
+        let game = CorrespondenceInfo {
+            id: 4,
+            x: "alice".to_string(),
+            o: Some("bob".to_string()),
+            turn: PlayerTurn::O,
+            winner: None,
+            due: Some(100 + 30 * 60 * 60),
+        };
+        assert_eq!(
+            game_entry(&game, "bob", 100).0,
+            "Game 4 against alice: your turn, due in 1d 6h"
+        );
+        assert_eq!(
+            game_entry(&game, "alice", 100).0,
+            "Game 4 against bob: their turn, due in 1d 6h"
+        );
+        let open = CorrespondenceInfo { o: None, ..game };
+        assert!(matches!(
+            game_entry(&open, "bob", 100),
+            (_, CorrespondenceMenuAction::AcceptGame(4))
+        ));
+    }
+}
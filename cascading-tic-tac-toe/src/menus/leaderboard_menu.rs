@@ -0,0 +1,328 @@
+use crate::{
+    correspondence_menu::PlayerName, main_menu::*, online_menu::*, valid_player_name,
+    ButtonActions, MatchResult, MenuState, Message, OnLeaderboardMenuScreen, OnlineSession,
+    PlayerRating, PlayerTag,
+};
+use bevy::prelude::*;
+
+/// Ratings and the player's latest games, last sent by the server
+#[derive(Resource, Default)]
+pub struct LeaderboardList {
+    pub leaders: Vec<PlayerRating>, // Best players, then the player if they have an account
+    pub history: Vec<MatchResult>,  // Newest first
+}
+
+/// Actions of the leaderboard menu buttons
+#[derive(Component)]
+pub enum LeaderboardMenuAction {
+    Refresh,
+    Back,
+}
+
+/// Tag component for the node holding the ratings and history
+#[derive(Component)]
+pub struct LeaderboardTable;
+
+/// Tag component for the typed name text
+#[derive(Component)]
+pub struct LeaderboardNameText;
+
+/// Short description of an account's rating and record
+fn rating_label(player: &PlayerRating) -> String {
+    format!(
+        "{} {} ({} won, {} drawn, {} lost)",
+        player.name, player.rating, player.wins, player.draws, player.losses
+    )
+}
+
+/// How a game is described to `player`
+fn result_label(result: &MatchResult, player: &str) -> String {
+    let (seat, opponent) = if result.x == player {
+        (PlayerTag::X, &result.o)
+    } else {
+        (PlayerTag::O, &result.x)
+    };
+    match &result.winner {
+        Some(winner) if *winner == seat => format!("Won against {} (+{})", opponent, result.change),
+        Some(_) => format!("Lost to {} (-{})", opponent, result.change),
+        // The change of a draw is what X took from O
+        None => {
+            let change = if seat == PlayerTag::X {
+                result.change
+            } else {
+                -result.change
+            };
+            format!("Drew with {} ({:+})", opponent, change)
+        }
+    }
+}
+
+/// Lines of the leaderboard: ranked players, the player's own rating and their games
+fn leaderboard_lines(list: &LeaderboardList, player: &str) -> Vec<String> {
+    let mut leaders = list.leaders.as_slice();
+    let mut own = None;
+    if let Some((last, best)) = leaders.split_last() {
+        if last.name == player {
+            own = Some(last);
+            leaders = best;
+        }
+    }
+
+    let mut lines: Vec<String> = leaders
+        .iter()
+        .enumerate()
+        .map(|(rank, leader)| format!("{}. {}", rank + 1, rating_label(leader)))
+        .collect();
+    if lines.is_empty() {
+        lines.push("No rated games yet".to_string());
+    }
+    match own {
+        Some(own) => lines.push(format!("You: {}", rating_label(own))),
+        None => lines.push("You have no rated games yet".to_string()),
+    }
+    lines.extend(
+        list.history
+            .iter()
+            .map(|result| result_label(result, player)),
+    );
+    lines
+}
+
+/// Spawn a menu button
+fn spawn_button(parent: &mut ChildBuilder, action: LeaderboardMenuAction, text: &str) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(180.0),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Px(10.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: NORMAL_BUTTON.into(),
+                ..default()
+            },
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 25.0,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Ask the server for the leaderboard and the player's history
+fn request_leaderboard(session: &mut OnlineSession, name: &PlayerName, status: &mut OnlineStatus) {
+    // The server drops connections that send a malformed name
+    if !valid_player_name(&name.0) {
+        status.0 = "Type your name first".to_string();
+        return;
+    }
+    let message = Message::Leaders {
+        player: name.0.clone(),
+    };
+    if let Err(error) = session.send(&message) {
+        status.0 = format!("Could not reach the server: {}", error);
+    }
+}
+
+/// System to setup the leaderboard menu and ask the server for the ratings
+pub fn leaderboard_menu_setup(
+    mut commands: Commands,
+    name: Res<PlayerName>,
+    mut session: ResMut<OnlineSession>,
+    mut status: ResMut<OnlineStatus>,
+) {
+    let text_style = TextStyle {
+        font_size: 25.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    request_leaderboard(&mut session, &name, &mut status);
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnLeaderboardMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(20.0)),
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Ratings",
+                        TextStyle {
+                            font_size: 40.0,
+                            color: TEXT_COLOR,
+                            ..default()
+                        },
+                    ));
+                    // The name is typed directly, backspace deletes
+                    parent.spawn((
+                        TextBundle::from_section(format!("Name: {}", name.0), text_style.clone()),
+                        LeaderboardNameText,
+                    ));
+                    // Ratings and games are listed here, one per line
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                margin: UiRect::all(Val::Px(10.0)),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        LeaderboardTable,
+                    ));
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::CRIMSON.into(),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            spawn_button(parent, LeaderboardMenuAction::Refresh, "Refresh");
+                            spawn_button(parent, LeaderboardMenuAction::Back, "Back");
+                        });
+                    parent.spawn((
+                        TextBundle::from_section(status.0.clone(), text_style),
+                        OnlineStatusText,
+                    ));
+                });
+        });
+}
+
+/// System to handle the leaderboard menu buttons
+pub fn leaderboard_menu_action(
+    interaction_query: ButtonActions<LeaderboardMenuAction>,
+    mut commands: Commands,
+    mut session: ResMut<OnlineSession>,
+    name: Res<PlayerName>,
+    mut status: ResMut<OnlineStatus>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            LeaderboardMenuAction::Refresh => {
+                status.0.clear();
+                request_leaderboard(&mut session, &name, &mut status);
+            }
+            LeaderboardMenuAction::Back => {
+                commands.remove_resource::<OnlineSession>();
+                status.0.clear();
+                menu_state.set(MenuState::Online);
+            }
+        }
+    }
+}
+
+/// System to show the ratings, the player's games and the typed name
+pub fn update_leaderboard_menu(
+    mut commands: Commands,
+    list: Res<LeaderboardList>,
+    name: Res<PlayerName>,
+    tables: Query<(Entity, Ref<LeaderboardTable>)>,
+    mut name_texts: Query<&mut Text, With<LeaderboardNameText>>,
+) {
+    if name.is_changed() {
+        for mut text in name_texts.iter_mut() {
+            text.sections[0].value = format!("Name: {}", name.0);
+        }
+    }
+    for (entity, tag) in &tables {
+        // Also filled when the screen has just been spawned
+        if !list.is_changed() && !tag.is_added() {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .despawn_descendants()
+            .with_children(|parent| {
+                for line in leaderboard_lines(&list, &name.0) {
+                    parent.spawn(TextBundle::from_section(
+                        line,
+                        TextStyle {
+                            font_size: 25.0,
+                            color: TEXT_COLOR,
+                            ..default()
+                        },
+                    ));
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The player's own account is shown apart from the ranking
+    #[test]
+    fn test_leaderboard_lines() {
+        let alice = PlayerRating {
+            name: "alice".to_string(),
+            rating: 1516,
+            wins: 1,
+            draws: 0,
+            losses: 0,
+        };
+        let bob = PlayerRating {
+            name: "bob".to_string(),
+            rating: 1484,
+            wins: 0,
+            draws: 0,
+            losses: 1,
+        };
+        let list = LeaderboardList {
+            leaders: vec![alice, bob.clone(), bob],
+            history: vec![MatchResult {
+                time: 10,
+                x: "alice".to_string(),
+                o: "bob".to_string(),
+                winner: Some(PlayerTag::X),
+                change: 16,
+            }],
+        };
+        assert_eq!(
+            leaderboard_lines(&list, "bob"),
+            [
+                "1. alice 1516 (1 won, 0 drawn, 0 lost)",
+                "2. bob 1484 (0 won, 0 drawn, 1 lost)",
+                "You: bob 1484 (0 won, 0 drawn, 1 lost)",
+                "Lost to alice (-16)",
+            ]
+        );
+    }
+}
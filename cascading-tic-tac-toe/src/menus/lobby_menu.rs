@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use crate::{
+    main_menu::*, online_menu::*, time, ButtonActions, MenuState, Message, OnLobbyMenuScreen,
+    OnlineSession, RoomInfo, RoomSettings, PROTOCOL_VERSION,
+};
+use bevy::prelude::*;
+
+/// Targets the Target button cycles through
+const TARGETS: [u32; 5] = [1, 2, 3, 4, 5];
+
+/// Clocks the Clock button cycles through, in seconds
+const TIMERS: [u32; 4] = [60, 5 * 60, 10 * 60, 20 * 60];
+
+/// Open rooms last listed by the server
+#[derive(Resource, Default)]
+pub struct LobbyRooms(pub Vec<RoomInfo>);
+
+/// Games being played on the server, last listed by it
+#[derive(Resource, Default)]
+pub struct LobbyGames(pub Vec<RoomInfo>);
+
+/// Settings of the room made by the Create button
+#[derive(Resource, Default)]
+pub struct LobbySettings(pub RoomSettings);
+
+/// Actions of the lobby menu buttons
+#[derive(Component)]
+pub enum LobbyMenuAction {
+    Target, // Next target for a created room
+    Timer,  // Next clock for a created room
+    Create,
+    QuickMatch,
+    Refresh,
+    Leave,
+    Back,
+    JoinRoom(u32),
+    WatchGame(u32),
+}
+
+/// Tag component for the node holding the room list
+#[derive(Component)]
+pub struct LobbyRoomList;
+
+/// Tag component for the settings text
+#[derive(Component)]
+pub struct LobbySettingsText;
+
+/// Short description of room settings
+pub fn settings_label(settings: &RoomSettings) -> String {
+    format!(
+        "to {}, {} clock, {} rules",
+        settings.target,
+        time(Duration::from_secs(settings.timer.into())),
+        settings.variant
+    )
+}
+
+/// Spawn a menu button
+fn spawn_button(parent: &mut ChildBuilder, action: LobbyMenuAction, text: String, width: f32) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Px(10.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: NORMAL_BUTTON.into(),
+                ..default()
+            },
+            action,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 25.0,
+                    color: TEXT_COLOR,
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// System to setup the lobby menu
+pub fn lobby_menu_setup(
+    mut commands: Commands,
+    settings: Res<LobbySettings>,
+    status: Res<OnlineStatus>,
+) {
+    let text_style = TextStyle {
+        font_size: 25.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    let row = || NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: Color::CRIMSON.into(),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnLobbyMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(20.0)),
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Lobby",
+                        TextStyle {
+                            font_size: 40.0,
+                            color: TEXT_COLOR,
+                            ..default()
+                        },
+                    ));
+                    // Rooms are listed here as buttons that join them
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                margin: UiRect::all(Val::Px(10.0)),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        LobbyRoomList,
+                    ));
+                    // Settings of a new room
+                    parent.spawn((
+                        TextBundle::from_section(
+                            format!("New room: {}", settings_label(&settings.0)),
+                            text_style.clone(),
+                        ),
+                        LobbySettingsText,
+                    ));
+                    parent.spawn(row()).with_children(|parent| {
+                        spawn_button(parent, LobbyMenuAction::Target, "Target".into(), 150.0);
+                        spawn_button(parent, LobbyMenuAction::Timer, "Clock".into(), 150.0);
+                        spawn_button(parent, LobbyMenuAction::Create, "Create".into(), 150.0);
+                    });
+                    parent.spawn(row()).with_children(|parent| {
+                        for (action, text) in [
+                            (LobbyMenuAction::QuickMatch, "Quick Match"),
+                            (LobbyMenuAction::Refresh, "Refresh"),
+                            (LobbyMenuAction::Leave, "Leave Room"),
+                            (LobbyMenuAction::Back, "Back"),
+                        ] {
+                            spawn_button(parent, action, text.into(), 180.0);
+                        }
+                    });
+                    parent.spawn((
+                        TextBundle::from_section(status.0.clone(), text_style),
+                        OnlineStatusText,
+                    ));
+                });
+        });
+}
+
+/// Value after `current` in `values`, going back to the first after the last
+fn next_value(values: &[u32], current: u32) -> u32 {
+    let index = values.iter().position(|value| *value == current);
+    values[index.map_or(0, |index| (index + 1) % values.len())]
+}
+
+/// System to handle the lobby menu buttons
+pub fn lobby_menu_action(
+    interaction_query: ButtonActions<LobbyMenuAction>,
+    mut commands: Commands,
+    mut session: ResMut<OnlineSession>,
+    mut settings: ResMut<LobbySettings>,
+    mut status: ResMut<OnlineStatus>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let message = match action {
+            LobbyMenuAction::Target => {
+                settings.0.target = next_value(&TARGETS, settings.0.target);
+                continue;
+            }
+            LobbyMenuAction::Timer => {
+                settings.0.timer = next_value(&TIMERS, settings.0.timer);
+                continue;
+            }
+            LobbyMenuAction::Back => {
+                commands.remove_resource::<OnlineSession>();
+                status.0.clear();
+                menu_state.set(MenuState::Online);
+                continue;
+            }
+            LobbyMenuAction::Create => Message::Create(settings.0.clone()),
+            LobbyMenuAction::QuickMatch => Message::QuickMatch,
+            LobbyMenuAction::Refresh => Message::List,
+            LobbyMenuAction::Leave => {
+                status.0 = "In the lobby".to_string();
+                Message::Leave
+            }
+            LobbyMenuAction::JoinRoom(room) => Message::Join { room: *room },
+            LobbyMenuAction::WatchGame(game) => Message::Watch {
+                version: PROTOCOL_VERSION,
+                game: *game,
+            },
+        };
+        if let Err(error) = session.send(&message) {
+            status.0 = format!("Could not reach the server: {}", error);
+        }
+    }
+}
+
+/// System to list the open rooms and the games that can be watched, and
+/// show the settings of a new room
+pub fn update_lobby_menu(
+    mut commands: Commands,
+    rooms: Res<LobbyRooms>,
+    games: Res<LobbyGames>,
+    settings: Res<LobbySettings>,
+    list: Query<(Entity, Ref<LobbyRoomList>)>,
+    mut settings_text: Query<&mut Text, With<LobbySettingsText>>,
+) {
+    if settings.is_changed() {
+        for mut text in settings_text.iter_mut() {
+            text.sections[0].value = format!("New room: {}", settings_label(&settings.0));
+        }
+    }
+    for (entity, tag) in &list {
+        // Also filled when the screen has just been spawned
+        if !rooms.is_changed() && !games.is_changed() && !tag.is_added() {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .despawn_descendants()
+            .with_children(|parent| {
+                if rooms.0.is_empty() {
+                    parent.spawn(TextBundle::from_section(
+                        "No open rooms",
+                        TextStyle {
+                            font_size: 25.0,
+                            color: TEXT_COLOR,
+                            ..default()
+                        },
+                    ));
+                }
+                for room in &rooms.0 {
+                    let text = format!("Room {}: {}", room.id, settings_label(&room.settings));
+                    spawn_button(parent, LobbyMenuAction::JoinRoom(room.id), text, 500.0);
+                }
+                for game in &games.0 {
+                    let text =
+                        format!("Watch game {}: {}", game.id, settings_label(&game.settings));
+                    spawn_button(parent, LobbyMenuAction::WatchGame(game.id), text, 500.0);
+                }
+            });
+    }
+}
@@ -1,7 +1,13 @@
 use crate::{
-    display_menu::*, sound_menu::*, DisplaySize, GameState, MenuButtonAction, MenuState,
-    OnDisplaySettingsMenuScreen, OnMainMenuScreen, OnSettingsMenuScreen, OnSoundSettingsMenuScreen,
-    PlayingState, ResolutionSettings, SelectedOption, SoundVolume,
+    correspondence_menu::*, display_menu::*, leaderboard_menu::*, lobby_menu::*, new_game_menu::*,
+    online_menu::*, pause_menu::*, sound_menu::*, timer_menu::*, Bot, DisplaySize, EndlessMode,
+    FirstPlayer, GameMode, GameState, LocalPlayers, MenuButtonAction, MenuState,
+    OnCorrespondenceMenuScreen, OnDisplaySettingsMenuScreen, OnLeaderboardMenuScreen,
+    OnLobbyMenuScreen, OnMainMenuScreen, OnNewGameMenuScreen, OnOnlineMenuScreen,
+    OnPauseMenuScreen, OnSettingsMenuScreen, OnSoundSettingsMenuScreen, OnTargetMenuScreen,
+    OnTimerMenuScreen, OnlineSession, Opponent, PlayerTurn, PlayingState, ResolutionSettings,
+    RoundInit, SelectedOption, SoundVolume, TargetScore, TieRule, TimeControl, TimeLimit,
+    TimedMode, TimeoutPenalty, TurnLimit,
 };
 use bevy::{app::AppExit, prelude::*};
 
@@ -36,6 +42,65 @@ impl Plugin for MenuPlugin {
         // Systems to handle the main menu screen
         .add_systems(OnEnter(MenuState::Main), main_menu_setup)
         .add_systems(OnExit(MenuState::Main), despawn_screen::<OnMainMenuScreen>)
+        // Systems to handle the new game menu screen
+        .init_resource::<GameMode>()
+        .init_resource::<TargetScore>()
+        .init_resource::<FirstPlayer>()
+        .init_resource::<Opponent>()
+        .add_systems(OnEnter(MenuState::Round), new_game_menu_setup)
+        .add_systems(
+            Update,
+            (
+                setting_button::<GameMode>,
+                setting_button::<FirstPlayer>,
+                setting_button::<Opponent>,
+                setting_button::<TurnLimit>,
+                setting_button::<TimeoutPenalty>,
+            )
+                .run_if(in_state(MenuState::Round)),
+        )
+        .add_systems(
+            OnExit(MenuState::Round),
+            despawn_screen::<OnNewGameMenuScreen>,
+        )
+        // Systems to handle the target score menu screen
+        .add_systems(OnEnter(MenuState::RoundTarget), target_menu_setup)
+        .add_systems(
+            Update,
+            setting_button::<TargetScore>.run_if(in_state(MenuState::RoundTarget)),
+        )
+        .add_systems(
+            OnExit(MenuState::RoundTarget),
+            despawn_screen::<OnTargetMenuScreen>,
+        )
+        // Systems to handle the timed game menu screen
+        .add_systems(OnEnter(MenuState::RoundTimer), timer_menu_setup)
+        .add_systems(
+            Update,
+            (
+                setting_button::<TimeLimit>,
+                setting_button::<TimeControl>,
+                setting_button::<TieRule>,
+            )
+                .run_if(in_state(MenuState::RoundTimer)),
+        )
+        .add_systems(
+            OnExit(MenuState::RoundTimer),
+            despawn_screen::<OnTimerMenuScreen>,
+        )
+        // Systems to handle the pause menu, over the game screen
+        .add_systems(Update, toggle_pause)
+        .add_systems(OnEnter(MenuState::Paused), pause_menu_setup)
+        .add_systems(
+            Update,
+            pause_menu_action.run_if(in_state(MenuState::Paused)),
+        )
+        .add_systems(
+            OnExit(MenuState::Paused),
+            despawn_screen::<OnPauseMenuScreen>,
+        )
+        .add_systems(OnEnter(GameState::Paused), hide_board)
+        .add_systems(OnExit(GameState::Paused), show_board)
         // Systems to handle the settings menu screen
         .add_systems(OnEnter(MenuState::Settings), settings_menu_setup)
         .add_systems(
@@ -65,6 +130,85 @@ impl Plugin for MenuPlugin {
             OnExit(MenuState::SettingsSound),
             despawn_screen::<OnSoundSettingsMenuScreen>,
         )
+        // Systems to handle the online menu screen
+        .init_resource::<OnlineAddress>()
+        .init_resource::<OnlineStatus>()
+        .add_systems(
+            OnEnter(MenuState::Online),
+            (online_menu_setup, start_lan_browser),
+        )
+        .add_systems(
+            Update,
+            (online_address_input, online_menu_action, update_lan_games)
+                .run_if(in_state(MenuState::Online)),
+        )
+        .add_systems(
+            Update,
+            (poll_online_lobby, update_online_menu_text).run_if(
+                in_state(MenuState::Online)
+                    .or_else(in_state(MenuState::Lobby))
+                    .or_else(in_state(MenuState::Correspondence))
+                    .or_else(in_state(MenuState::Leaderboard)),
+            ),
+        )
+        .add_systems(
+            OnExit(MenuState::Online),
+            (despawn_screen::<OnOnlineMenuScreen>, stop_lan_browser),
+        )
+        // Systems to handle the lobby of a game server
+        .init_resource::<LobbyRooms>()
+        .init_resource::<LobbyGames>()
+        .init_resource::<LobbySettings>()
+        .add_systems(OnEnter(MenuState::Lobby), lobby_menu_setup)
+        .add_systems(
+            Update,
+            (
+                lobby_menu_action.run_if(resource_exists::<OnlineSession>),
+                update_lobby_menu,
+            )
+                .run_if(in_state(MenuState::Lobby)),
+        )
+        .add_systems(OnExit(MenuState::Lobby), despawn_screen::<OnLobbyMenuScreen>)
+        // Systems to handle the correspondence games of a game server
+        .init_resource::<PlayerName>()
+        .init_resource::<CorrespondenceDeadline>()
+        .init_resource::<CorrespondenceList>()
+        .add_systems(
+            OnEnter(MenuState::Correspondence),
+            correspondence_menu_setup.run_if(resource_exists::<OnlineSession>),
+        )
+        .add_systems(
+            Update,
+            (
+                player_name_input,
+                correspondence_menu_action.run_if(resource_exists::<OnlineSession>),
+                update_correspondence_menu,
+            )
+                .run_if(in_state(MenuState::Correspondence)),
+        )
+        .add_systems(
+            OnExit(MenuState::Correspondence),
+            despawn_screen::<OnCorrespondenceMenuScreen>,
+        )
+        // Systems to handle the ratings of a game server
+        .init_resource::<LeaderboardList>()
+        .add_systems(
+            OnEnter(MenuState::Leaderboard),
+            leaderboard_menu_setup.run_if(resource_exists::<OnlineSession>),
+        )
+        .add_systems(
+            Update,
+            (
+                player_name_input,
+                leaderboard_menu_action.run_if(resource_exists::<OnlineSession>),
+                update_leaderboard_menu,
+            )
+                .run_if(in_state(MenuState::Leaderboard)),
+        )
+        .add_systems(
+            OnExit(MenuState::Leaderboard),
+            despawn_screen::<OnLeaderboardMenuScreen>,
+        )
         // Systems to adjust Audio volume
         // .add_systems(Update, toggle_volume)
         // Systems to adjust screen resolution
@@ -73,7 +217,7 @@ impl Plugin for MenuPlugin {
         .add_systems(
             Update,
             (menu_action, button_system, toggle_volume, toggle_resolution)
-                .run_if(in_state(PlayingState::NotPlaying)),
+                .run_if(in_state(PlayingState::NotPlaying).or_else(in_state(GameState::Paused))),
         );
     }
 }
@@ -87,6 +231,12 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             icon_path: "texture/icons/right-arrow.png",
             action: MenuButtonAction::Play,
         },
+        ButtonParams {
+            text: "Play Online",
+            text_color: TEXT_COLOR,
+            icon_path: "texture/icons/right-arrow.png",
+            action: MenuButtonAction::PlayOnline,
+        },
         ButtonParams {
             text: "Settings",
             text_color: TEXT_COLOR,
@@ -166,11 +316,15 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
+/// The button of the value of setting `T` that is currently selected
+type SelectedButton<'w, 's, T> =
+    Query<'w, 's, (Entity, &'static mut BackgroundColor), (With<SelectedOption>, With<T>)>;
+
 /// This system updates the settings when a new value for a setting is selected,
 /// and marks the button as the one currently selected
 fn setting_button<T: Resource + Component + PartialEq + Copy>(
     interaction_query: Query<(&Interaction, &T, Entity), (Changed<Interaction>, With<Button>)>,
-    mut selected_query: Query<(Entity, &mut BackgroundColor), With<SelectedOption>>,
+    mut selected_query: SelectedButton<T>,
     mut commands: Commands,
     mut setting: ResMut<T>,
 ) {
@@ -185,6 +339,141 @@ fn setting_button<T: Resource + Component + PartialEq + Copy>(
     }
 }
 
+/// Spawn the full screen node and the column that every new game menu is laid out in
+pub fn spawn_menu_column(
+    commands: &mut Commands,
+    tag: impl Component,
+    spawn_children: impl FnOnce(&mut ChildBuilder),
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            tag,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                })
+                .with_children(spawn_children);
+        });
+}
+
+/// Spawn a row of buttons, one for each value of a setting, with the current one selected
+pub fn spawn_option_row<T: Component + PartialEq + Copy>(
+    parent: &mut ChildBuilder,
+    title: &str,
+    options: Vec<(T, String)>,
+    current: T,
+) {
+    let text_style = TextStyle {
+        font_size: 25.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::CRIMSON.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(title, text_style.clone()).with_style(Style {
+                    width: Val::Px(130.0),
+                    ..default()
+                }),
+            );
+            for (value, label) in options {
+                let mut entity = parent.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(130.0),
+                            height: Val::Px(45.0),
+                            margin: UiRect::all(Val::Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: NORMAL_BUTTON.into(),
+                        ..default()
+                    },
+                    value,
+                ));
+                entity.with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(label, text_style.clone()));
+                });
+                if value == current {
+                    entity.insert(SelectedOption);
+                }
+            }
+        });
+}
+
+/// Spawn a row of menu buttons, such as Start and Back at the bottom of a menu
+pub fn spawn_action_row<const N: usize>(
+    parent: &mut ChildBuilder,
+    actions: [(MenuButtonAction, &str); N],
+) {
+    let button_style = Style {
+        width: Val::Px(200.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 40.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::CRIMSON.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            for (action, text) in actions {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: button_style.clone(),
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        },
+                        action,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(text, button_text_style.clone()));
+                    });
+            }
+        });
+}
+
 /// This system sets up the settings menu to toggle resolution and sound volume
 fn settings_menu_setup(mut commands: Commands) {
     let button_style = Style {
@@ -271,6 +560,7 @@ fn button_system(
 }
 
 /// A system for handling individual menu actions (Pressed)
+#[allow(clippy::too_many_arguments)]
 fn menu_action(
     interaction_query: Query<
         (&Interaction, &MenuButtonAction),
@@ -280,6 +570,17 @@ fn menu_action(
     mut menu_state: ResMut<NextState<MenuState>>,
     mut playing_state: ResMut<NextState<PlayingState>>,
     mut game_state: ResMut<NextState<GameState>>,
+    current_game_state: Res<State<GameState>>,
+    mut next_player_turn: ResMut<NextState<PlayerTurn>>,
+    mut local_players: ResMut<LocalPlayers>,
+    mut round_init: ResMut<RoundInit>,
+    mut timed: ResMut<TimedMode>,
+    mut endless: ResMut<EndlessMode>,
+    mode: Res<GameMode>,
+    target: Res<TargetScore>,
+    first_player: Res<FirstPlayer>,
+    opponent: Res<Opponent>,
+    bot: Res<Bot>,
 ) {
     for (interaction, menu_button_action) in &interaction_query {
         if *interaction == Interaction::Pressed {
@@ -287,11 +588,46 @@ fn menu_action(
                 MenuButtonAction::Quit => {
                     app_exit_events.send(AppExit);
                 }
-                MenuButtonAction::Play => {
-                    playing_state.set(PlayingState::Local);
+                MenuButtonAction::Play => menu_state.set(MenuState::Round),
+                MenuButtonAction::RoundOptions if *mode == GameMode::Target => {
+                    menu_state.set(MenuState::RoundTarget);
+                }
+                MenuButtonAction::RoundOptions if *mode == GameMode::Timed => {
+                    menu_state.set(MenuState::RoundTimer);
+                }
+                // Endless games have nothing more to choose
+                MenuButtonAction::RoundOptions | MenuButtonAction::StartGame => {
+                    *round_init = RoundInit::new(target.0);
+                    timed.0 = *mode == GameMode::Timed;
+                    endless.0 = *mode == GameMode::Endless;
+                    next_player_turn.set(first_player.turn());
+                    match *opponent {
+                        Opponent::Human => {
+                            *local_players = LocalPlayers::default();
+                            playing_state.set(PlayingState::Local);
+                        }
+                        Opponent::Computer => {
+                            // The human plays whichever side the computer does not
+                            let human = match bot.player {
+                                PlayerTurn::X => PlayerTurn::O,
+                                PlayerTurn::O => PlayerTurn::X,
+                            };
+                            *local_players = LocalPlayers(vec![human]);
+                            playing_state.set(PlayingState::VsComputer);
+                        }
+                    }
                     game_state.set(GameState::LoadingNewGame);
                     menu_state.set(MenuState::Disabled);
                 }
+                // Online games end at the target score or on the room's clock,
+                // and always start with X
+                MenuButtonAction::PlayOnline => {
+                    *round_init = RoundInit::new(TargetScore::default().0);
+                    timed.0 = false;
+                    endless.0 = false;
+                    next_player_turn.set(PlayerTurn::X);
+                    menu_state.set(MenuState::Online);
+                }
                 MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
                 MenuButtonAction::SettingsDisplay => {
                     menu_state.set(MenuState::SettingsDisplay);
@@ -299,10 +635,18 @@ fn menu_action(
                 MenuButtonAction::SettingsSound => {
                     menu_state.set(MenuState::SettingsSound);
                 }
-                MenuButtonAction::BackToMainMenu => menu_state.set(MenuState::Main),
+                // Settings opened from the pause menu go back to it
+                MenuButtonAction::BackToMainMenu => {
+                    if *current_game_state.get() == GameState::Paused {
+                        menu_state.set(MenuState::Paused);
+                    } else {
+                        menu_state.set(MenuState::Main);
+                    }
+                }
                 MenuButtonAction::BackToSettings => {
                     menu_state.set(MenuState::Settings);
                 }
+                MenuButtonAction::BackToNewGame => menu_state.set(MenuState::Round),
             }
         }
     }
@@ -1,3 +1,9 @@
 pub mod main_menu;
 pub mod display_menu;
-pub mod sound_menu;
\ No newline at end of file
+pub mod sound_menu;
+pub mod online_menu;
+pub mod lobby_menu;
+pub mod correspondence_menu;
+pub mod leaderboard_menu;
+pub mod timer_menu;
+pub mod pause_menu;pub mod new_game_menu;
@@ -0,0 +1,102 @@
+use crate::{
+    main_menu::*, FirstPlayer, GameMode, MenuButtonAction, OnNewGameMenuScreen, OnTargetMenuScreen,
+    Opponent, TargetScore, TimeoutPenalty, TurnLimit, GAME_MODES, TARGET_SCORES,
+    TIMEOUT_PENALTIES, TURN_LIMITS,
+};
+use bevy::prelude::*;
+
+/// A system that sets up the new game menu, where the mode, the first
+/// player, the opponent and the time for each move are chosen
+pub fn new_game_menu_setup(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    first_player: Res<FirstPlayer>,
+    opponent: Res<Opponent>,
+    turn_limit: Res<TurnLimit>,
+    penalty: Res<TimeoutPenalty>,
+) {
+    spawn_menu_column(&mut commands, OnNewGameMenuScreen, |parent| {
+        spawn_option_row(
+            parent,
+            "Mode",
+            GAME_MODES
+                .iter()
+                .map(|&(mode, label)| (mode, label.to_string()))
+                .collect(),
+            *mode,
+        );
+        spawn_option_row(
+            parent,
+            "First",
+            vec![
+                (FirstPlayer::X, "X".to_string()),
+                (FirstPlayer::O, "O".to_string()),
+            ],
+            *first_player,
+        );
+        spawn_option_row(
+            parent,
+            "Opponent",
+            vec![
+                (Opponent::Human, "Human".to_string()),
+                (Opponent::Computer, "Computer".to_string()),
+            ],
+            *opponent,
+        );
+        // Moves can be limited in every mode, not only timed games
+        spawn_option_row(
+            parent,
+            "Per move",
+            TURN_LIMITS
+                .iter()
+                .map(|&seconds| {
+                    let label = match seconds {
+                        0 => "Off".to_string(),
+                        seconds => format!("{}s", seconds),
+                    };
+                    (TurnLimit(seconds), label)
+                })
+                .collect(),
+            *turn_limit,
+        );
+        spawn_option_row(
+            parent,
+            "Timeout",
+            TIMEOUT_PENALTIES
+                .iter()
+                .map(|&(penalty, label)| (penalty, label.to_string()))
+                .collect(),
+            *penalty,
+        );
+        spawn_action_row(
+            parent,
+            [
+                (MenuButtonAction::RoundOptions, "Next"),
+                (MenuButtonAction::BackToMainMenu, "Back"),
+            ],
+        );
+    });
+}
+
+/// A system that sets up the target menu, where the score that wins a
+/// target score game is chosen
+pub fn target_menu_setup(mut commands: Commands, target: Res<TargetScore>) {
+    spawn_menu_column(&mut commands, OnTargetMenuScreen, |parent| {
+        spawn_option_row(
+            parent,
+            "Target",
+            TARGET_SCORES
+                .iter()
+                .map(|&score| (TargetScore(score), score.to_string()))
+                .collect(),
+            *target,
+        );
+        spawn_action_row(
+            parent,
+            [
+                (MenuButtonAction::StartGame, "Start"),
+                (MenuButtonAction::BackToNewGame, "Back"),
+            ],
+        );
+    });
+}
@@ -0,0 +1,596 @@
+use std::net::SocketAddr;
+
+use crate::{
+    correspondence_menu::{CorrespondenceList, PlayerName},
+    forget_session,
+    leaderboard_menu::LeaderboardList,
+    lobby_menu::{settings_label, LobbyGames, LobbyRooms},
+    main_menu::*,
+    saved_session, valid_player_name, ButtonActions, DiscoveryBrowser, GameState, LocalPlayers,
+    MenuState, Message, OnOnlineMenuScreen, OnlineSession, PlayingState, DEFAULT_PORT,
+    PROTOCOL_VERSION, SESSION_PATH,
+};
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+
+/// Longest address that can be typed
+const MAX_ADDRESS_LENGTH: usize = 64;
+
+/// Address typed on the online menu. Join and Lobby connect to it, Host
+/// listens on its port.
+#[derive(Resource)]
+pub struct OnlineAddress(pub String);
+
+/// Default constructor for OnlineAddress, this machine on the default port
+impl Default for OnlineAddress {
+    fn default() -> Self {
+        OnlineAddress(format!("127.0.0.1:{}", DEFAULT_PORT))
+    }
+}
+
+/// Progress of hosting or joining, shown on the online menu
+#[derive(Resource, Default)]
+pub struct OnlineStatus(pub String);
+
+/// Finds games on the local network while the online menu is open
+#[derive(Resource)]
+pub struct LanBrowser(pub DiscoveryBrowser);
+
+/// Actions of the online menu buttons
+#[derive(Component)]
+pub enum OnlineMenuAction {
+    Host,
+    Join,
+    Lobby,          // Join a game server's lobby
+    Resume,         // Take back the seat of a server game left earlier
+    Watch,          // Watch the game of a host
+    Correspondence, // Play correspondence games on a game server
+    Leaderboard,    // See the ratings of a game server
+    Back,
+    JoinLan(SocketAddr),  // Join a game found on the local network
+    WatchLan(SocketAddr), // Watch a full game found on the local network
+}
+
+/// Tag component for the node holding the games found on the local network
+#[derive(Component)]
+pub struct LanGameList;
+
+/// Tag component for the typed address text
+#[derive(Component)]
+pub struct OnlineAddressText;
+
+/// Tag component for the status text
+#[derive(Component)]
+pub struct OnlineStatusText;
+
+/// System to setup the online menu
+pub fn online_menu_setup(
+    mut commands: Commands,
+    address: Res<OnlineAddress>,
+    status: Res<OnlineStatus>,
+) {
+    let button_style = Style {
+        width: Val::Px(200.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 40.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    let small_text_style = TextStyle {
+        font_size: 25.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnOnlineMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(20.0)),
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Play Online",
+                        button_text_style.clone(),
+                    ));
+                    // The address is typed directly, backspace deletes
+                    parent.spawn((
+                        TextBundle::from_section(
+                            format!("Address: {}", address.0),
+                            small_text_style.clone(),
+                        ),
+                        OnlineAddressText,
+                    ));
+                    // Games on the local network are listed here as buttons that join them
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                margin: UiRect::all(Val::Px(10.0)),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        LanGameList,
+                    ));
+                    // Display a button for each online action
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::CRIMSON.into(),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            let mut actions = vec![
+                                (OnlineMenuAction::Host, "Host"),
+                                (OnlineMenuAction::Join, "Join"),
+                                (OnlineMenuAction::Lobby, "Lobby"),
+                                (OnlineMenuAction::Watch, "Watch"),
+                                (OnlineMenuAction::Correspondence, "My Games"),
+                                (OnlineMenuAction::Leaderboard, "Ratings"),
+                            ];
+                            // Only offered while a server game is saved
+                            if saved_session(SESSION_PATH).is_some() {
+                                actions.push((OnlineMenuAction::Resume, "Resume"));
+                            }
+                            actions.push((OnlineMenuAction::Back, "Back"));
+                            for (action, text) in actions {
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: button_style.clone(),
+                                            background_color: NORMAL_BUTTON.into(),
+                                            ..default()
+                                        },
+                                        action,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            text,
+                                            button_text_style.clone(),
+                                        ));
+                                    });
+                            }
+                        });
+                    parent.spawn((
+                        TextBundle::from_section(status.0.clone(), small_text_style),
+                        OnlineStatusText,
+                    ));
+                });
+        });
+}
+
+/// System to type the address, which is locked once hosting or joining
+pub fn online_address_input(
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut address: ResMut<OnlineAddress>,
+    session: Option<Res<OnlineSession>>,
+) {
+    if session.is_some() {
+        characters.clear();
+        return;
+    }
+    for event in characters.read() {
+        for character in event.char.chars() {
+            let allowed = character.is_ascii_alphanumeric() || ".:-[]".contains(character);
+            if allowed && address.0.len() < MAX_ADDRESS_LENGTH {
+                address.0.push(character);
+            }
+        }
+    }
+    if keys.just_pressed(KeyCode::Backspace) {
+        address.0.pop();
+    }
+}
+
+/// Port part of an address, or the default port
+fn address_port(address: &str) -> u16 {
+    address
+        .rsplit_once(':')
+        .and_then(|(_, port)| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT)
+}
+
+/// Connect to a host and wait for it to accept
+fn join_host(commands: &mut Commands, address: &str, status: &mut OnlineStatus) {
+    status.0 = "Connecting, waiting for the host".to_string();
+    commands.insert_resource(OnlineSession::join(address));
+}
+
+/// Connect to a host to watch its game
+fn watch_host(commands: &mut Commands, address: &str, status: &mut OnlineStatus) {
+    status.0 = "Connecting, waiting for the host".to_string();
+    // Hosts have a single game, the id is only used by game servers
+    commands.insert_resource(OnlineSession::watch(address, 0));
+}
+
+/// Connect to a game server and open `menu` once it answers
+fn join_server(commands: &mut Commands, address: &str, menu: MenuState, status: &mut OnlineStatus) {
+    status.0 = "Connecting, waiting for the server".to_string();
+    commands.insert_resource(OnlineSession::join_server(address, menu));
+}
+
+/// System to handle the online menu buttons
+pub fn online_menu_action(
+    interaction_query: ButtonActions<OnlineMenuAction>,
+    mut commands: Commands,
+    mut address: ResMut<OnlineAddress>,
+    mut status: ResMut<OnlineStatus>,
+    session: Option<Res<OnlineSession>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            OnlineMenuAction::Host if session.is_none() => {
+                match OnlineSession::host(address_port(&address.0)) {
+                    Ok(session) => {
+                        status.0 = format!(
+                            "Hosting on port {}, waiting for an opponent",
+                            session.port().unwrap_or_default()
+                        );
+                        commands.insert_resource(session);
+                    }
+                    Err(error) => status.0 = format!("Could not host: {}", error),
+                }
+            }
+            OnlineMenuAction::Join if session.is_none() => {
+                join_host(&mut commands, &address.0, &mut status)
+            }
+            OnlineMenuAction::JoinLan(game) if session.is_none() => {
+                address.0 = game.to_string();
+                join_host(&mut commands, &address.0, &mut status);
+            }
+            OnlineMenuAction::Watch if session.is_none() => {
+                watch_host(&mut commands, &address.0, &mut status)
+            }
+            OnlineMenuAction::WatchLan(game) if session.is_none() => {
+                address.0 = game.to_string();
+                watch_host(&mut commands, &address.0, &mut status);
+            }
+            OnlineMenuAction::Correspondence if session.is_none() => join_server(
+                &mut commands,
+                &address.0,
+                MenuState::Correspondence,
+                &mut status,
+            ),
+            OnlineMenuAction::Leaderboard if session.is_none() => join_server(
+                &mut commands,
+                &address.0,
+                MenuState::Leaderboard,
+                &mut status,
+            ),
+            OnlineMenuAction::Lobby if session.is_none() => {
+                join_server(&mut commands, &address.0, MenuState::Lobby, &mut status)
+            }
+            OnlineMenuAction::Resume if session.is_none() => match saved_session(SESSION_PATH) {
+                Some((server, token)) => {
+                    status.0 = "Connecting, resuming the game".to_string();
+                    commands.insert_resource(OnlineSession::resume(&server, token));
+                }
+                None => status.0 = "No game to resume".to_string(),
+            },
+            OnlineMenuAction::Host
+            | OnlineMenuAction::Join
+            | OnlineMenuAction::Lobby
+            | OnlineMenuAction::Resume
+            | OnlineMenuAction::Watch
+            | OnlineMenuAction::Correspondence
+            | OnlineMenuAction::Leaderboard
+            | OnlineMenuAction::JoinLan(_)
+            | OnlineMenuAction::WatchLan(_) => (),
+            OnlineMenuAction::Back => {
+                commands.remove_resource::<OnlineSession>();
+                status.0.clear();
+                menu_state.set(MenuState::Main);
+            }
+        }
+    }
+}
+
+/// System that completes the handshake, follows the server's lobby and
+/// starts the online game
+#[allow(clippy::too_many_arguments)]
+pub fn poll_online_lobby(
+    mut commands: Commands,
+    session: Option<ResMut<OnlineSession>>,
+    mut status: ResMut<OnlineStatus>,
+    mut rooms: ResMut<LobbyRooms>,
+    mut games: ResMut<LobbyGames>,
+    mut correspondence_games: ResMut<CorrespondenceList>,
+    mut leaderboard: ResMut<LeaderboardList>,
+    name: Res<PlayerName>,
+    mut local_players: ResMut<LocalPlayers>,
+    mut playing_state: ResMut<NextState<PlayingState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+) {
+    let Some(mut session) = session else {
+        return;
+    };
+    let messages = match session.poll() {
+        Ok(messages) => messages,
+        Err(error) => {
+            status.0 = if session.is_connecting() {
+                format!("Could not connect: {}", error)
+            } else {
+                format!("Connection lost: {}", error)
+            };
+            commands.remove_resource::<OnlineSession>();
+            if session.lobby {
+                menu_state.set(MenuState::Online);
+            }
+            return;
+        }
+    };
+
+    let mut messages = messages.into_iter();
+    while let Some(message) = messages.next() {
+        let start = match message {
+            Message::Hello { version } if version == PROTOCOL_VERSION => session
+                .send(&Message::Welcome {
+                    version: PROTOCOL_VERSION,
+                })
+                .is_ok(),
+            Message::Hello { version } => {
+                let reason = format!(
+                    "protocol version {} is not supported, host uses {}",
+                    version, PROTOCOL_VERSION
+                );
+                // Best effort, the connection is closed either way
+                let _ = session.send(&Message::Reject {
+                    reason: reason.clone(),
+                });
+                status.0 = format!("Refused a player: {}", reason);
+                commands.remove_resource::<OnlineSession>();
+                return;
+            }
+            Message::Welcome { version } if version == PROTOCOL_VERSION => {
+                if let Some(token) = session.resuming() {
+                    if let Err(error) = session.send(&Message::Resume { token }) {
+                        status.0 = format!("Could not resume: {}", error);
+                    }
+                    false
+                } else if session.lobby {
+                    // Lobby games are rated for the player's account
+                    let named = !valid_player_name(&name.0)
+                        || session
+                            .send(&Message::Name {
+                                player: name.0.clone(),
+                            })
+                            .is_ok();
+                    status.0.clear();
+                    if session.server_menu == MenuState::Lobby {
+                        status.0 = "In the lobby".to_string();
+                        if let Err(error) = session.send(&Message::List) {
+                            status.0 = format!("Could not list rooms: {}", error);
+                        }
+                    }
+                    if !named {
+                        status.0 = "Could not send your name, games will not be rated".to_string();
+                    }
+                    menu_state.set(session.server_menu);
+                    false
+                } else {
+                    true
+                }
+            }
+            Message::Welcome { version } => {
+                status.0 = format!("Host uses protocol version {}", version);
+                commands.remove_resource::<OnlineSession>();
+                return;
+            }
+            Message::Rooms(open) => {
+                rooms.0 = open;
+                false
+            }
+            Message::Playing(playing) => {
+                games.0 = playing;
+                false
+            }
+            Message::GameList(listed) => {
+                correspondence_games.0 = listed;
+                false
+            }
+            Message::Leaderboard(leaders) => {
+                leaderboard.leaders = leaders;
+                false
+            }
+            Message::History(history) => {
+                leaderboard.history = history;
+                false
+            }
+            Message::Room { room } => {
+                status.0 = format!("Waiting in room {} for an opponent", room);
+                false
+            }
+            Message::Invalid { reason } => {
+                status.0 = format!("Server refused: {}", reason);
+                false
+            }
+            Message::Start(player, settings) => {
+                session.local_player = player;
+                session.settings = Some(settings);
+                true
+            }
+            Message::Spectate(settings) => {
+                session.spectating = true;
+                session.settings = Some(settings);
+                true
+            }
+            Message::Reject { reason } if session.resuming().is_some() => {
+                // The game is over, or the seat was lost
+                forget_session(SESSION_PATH);
+                status.0 = format!("Could not resume: {}", reason);
+                commands.remove_resource::<OnlineSession>();
+                return;
+            }
+            Message::Reject { reason } => {
+                status.0 = format!("Host refused: {}", reason);
+                commands.remove_resource::<OnlineSession>();
+                return;
+            }
+            _ => false,
+        };
+        if start {
+            status.0.clear();
+            // Spectators control neither player
+            *local_players = if session.spectating {
+                LocalPlayers(Vec::new())
+            } else {
+                LocalPlayers(vec![session.local_player.clone()])
+            };
+            playing_state.set(PlayingState::Online);
+            game_state.set(GameState::LoadingNewGame);
+            menu_state.set(MenuState::Disabled);
+            // Later messages, like the server's first snapshot, are for the game
+            for message in messages {
+                session.defer(message);
+            }
+            return;
+        }
+    }
+}
+
+/// System that starts looking for games on the local network
+pub fn start_lan_browser(mut commands: Commands) {
+    match DiscoveryBrowser::lan() {
+        Ok(browser) => commands.insert_resource(LanBrowser(browser)),
+        Err(error) => println!("Could not look for games on the local network: {}", error),
+    }
+}
+
+/// System that stops looking for games on the local network
+pub fn stop_lan_browser(mut commands: Commands) {
+    commands.remove_resource::<LanBrowser>();
+}
+
+/// System to list the games found on the local network as they come and go
+pub fn update_lan_games(
+    mut commands: Commands,
+    browser: Option<ResMut<LanBrowser>>,
+    list: Query<(Entity, Ref<LanGameList>)>,
+) {
+    let Some(mut browser) = browser else {
+        return;
+    };
+    let changed = match browser.0.poll() {
+        Ok(changed) => changed,
+        Err(error) => {
+            println!("Stopped looking for games on the local network: {}", error);
+            commands.remove_resource::<LanBrowser>();
+            return;
+        }
+    };
+    let text_style = TextStyle {
+        font_size: 25.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+    for (entity, tag) in &list {
+        // Also filled when the screen has just been spawned
+        if !changed && !tag.is_added() {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .despawn_descendants()
+            .with_children(|parent| {
+                if browser.0.games().is_empty() {
+                    parent.spawn(TextBundle::from_section(
+                        "No games found on your network",
+                        text_style.clone(),
+                    ));
+                }
+                for game in browser.0.games() {
+                    let label = format!(
+                        "{} ({}): {}/2 players, {}",
+                        game.advert.name,
+                        game.address,
+                        game.advert.players,
+                        settings_label(&game.advert.settings)
+                    );
+                    // Full games can only be watched
+                    let (action, label) = if game.advert.players >= 2 {
+                        (
+                            OnlineMenuAction::WatchLan(game.address),
+                            format!("Watch {}", label),
+                        )
+                    } else {
+                        (OnlineMenuAction::JoinLan(game.address), label)
+                    };
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(700.0),
+                                    height: Val::Px(40.0),
+                                    margin: UiRect::all(Val::Px(5.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            action,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(label, text_style.clone()));
+                        });
+                }
+            });
+    }
+}
+
+/// System to show the typed address and the connection status
+pub fn update_online_menu_text(
+    address: Res<OnlineAddress>,
+    status: Res<OnlineStatus>,
+    mut address_text: Query<&mut Text, (With<OnlineAddressText>, Without<OnlineStatusText>)>,
+    mut status_text: Query<&mut Text, (With<OnlineStatusText>, Without<OnlineAddressText>)>,
+) {
+    if address.is_changed() {
+        for mut text in address_text.iter_mut() {
+            text.sections[0].value = format!("Address: {}", address.0);
+        }
+    }
+    if status.is_changed() {
+        for mut text in status_text.iter_mut() {
+            text.sections[0].value = status.0.clone();
+        }
+    }
+}
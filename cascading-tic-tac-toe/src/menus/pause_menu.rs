@@ -0,0 +1,205 @@
+use crate::{
+    main_menu::*, winner_on_time, BoardRoot, ButtonActions, EndlessMode, GameState, MenuState,
+    OnPauseMenuScreen, PlayerClocks, PlayingState, RoundInit, RoundState, TimedMode,
+};
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+
+/// Actions of the pause menu buttons
+#[derive(Component)]
+pub enum PauseMenuAction {
+    Resume,
+    EndGame, // Endless games only, the higher score wins
+    Restart,
+    Settings,
+    MainMenu,
+}
+
+/// Pause the game, showing the pause menu
+pub fn pause_game(
+    next_game_state: &mut NextState<GameState>,
+    next_menu_state: &mut NextState<MenuState>,
+) {
+    next_game_state.set(GameState::Paused);
+    next_menu_state.set(MenuState::Paused);
+}
+
+/// System to pause the game, or resume it, with Esc. Online games are
+/// never paused, the opponent's clock keeps running.
+pub fn toggle_pause(
+    keys: Res<ButtonInput<KeyCode>>,
+    game_state: Res<State<GameState>>,
+    menu_state: Res<State<MenuState>>,
+    playing_state: Res<State<PlayingState>>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_menu_state: ResMut<NextState<MenuState>>,
+) {
+    if !keys.just_pressed(KeyCode::Escape) || *playing_state.get() == PlayingState::Online {
+        return;
+    }
+    match (game_state.get(), menu_state.get()) {
+        (GameState::GameOngoing, _) => pause_game(&mut next_game_state, &mut next_menu_state),
+        (GameState::Paused, MenuState::Paused) => {
+            next_game_state.set(GameState::GameOngoing);
+            next_menu_state.set(MenuState::Disabled);
+        }
+        _ => (),
+    }
+}
+
+/// System to set up the pause menu over the game screen. Endless games are
+/// ended from here.
+pub fn pause_menu_setup(mut commands: Commands, endless: Res<EndlessMode>) {
+    let button_style = Style {
+        width: Val::Px(250.0),
+        height: Val::Px(65.0),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font_size: 40.0,
+        color: TEXT_COLOR,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                // The board and game buttons underneath cannot be pressed
+                focus_policy: FocusPolicy::Block,
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+            OnPauseMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::CRIMSON.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(
+                        TextBundle::from_section(
+                            "Paused",
+                            TextStyle {
+                                font_size: 60.0,
+                                ..button_text_style.clone()
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::all(Val::Px(30.0)),
+                            ..default()
+                        }),
+                    );
+                    let mut actions = vec![(PauseMenuAction::Resume, "Resume")];
+                    if endless.0 {
+                        actions.push((PauseMenuAction::EndGame, "End Game"));
+                    }
+                    actions.extend([
+                        (PauseMenuAction::Restart, "Restart"),
+                        (PauseMenuAction::Settings, "Settings"),
+                        (PauseMenuAction::MainMenu, "Main Menu"),
+                    ]);
+                    for (action, text) in actions {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                action,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    text,
+                                    button_text_style.clone(),
+                                ));
+                            });
+                    }
+                });
+        });
+}
+
+/// System to handle the pause menu buttons
+pub fn pause_menu_action(
+    interaction_query: ButtonActions<PauseMenuAction>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_round_state: ResMut<NextState<RoundState>>,
+    mut next_playing_state: ResMut<NextState<PlayingState>>,
+    mut next_menu_state: ResMut<NextState<MenuState>>,
+    round_init: Res<RoundInit>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            PauseMenuAction::Resume => {
+                next_game_state.set(GameState::GameOngoing);
+                next_menu_state.set(MenuState::Disabled);
+            }
+            // The result shows the final score and the rounds played
+            PauseMenuAction::EndGame => {
+                next_round_state.set(RoundState::NotUpdating);
+                next_game_state.set(
+                    match winner_on_time(round_init.x_score, round_init.o_score) {
+                        Some(winner) => GameState::Won(winner),
+                        None => GameState::Draw,
+                    },
+                );
+                next_menu_state.set(MenuState::Disabled);
+            }
+            PauseMenuAction::Restart => {
+                next_round_state.set(RoundState::NotUpdating);
+                next_game_state.set(GameState::RestartingGame);
+                next_menu_state.set(MenuState::Disabled);
+            }
+            PauseMenuAction::Settings => next_menu_state.set(MenuState::Settings),
+            PauseMenuAction::MainMenu => {
+                next_round_state.set(RoundState::NotUpdating);
+                next_game_state.set(GameState::NotPlaying);
+                next_playing_state.set(PlayingState::NotPlaying);
+                next_menu_state.set(MenuState::Main);
+            }
+        }
+    }
+}
+
+/// System to hide the board while a timed game is paused, so nobody gets
+/// free time to think
+pub fn hide_board(
+    timed: Res<TimedMode>,
+    clocks: Res<PlayerClocks>,
+    mut boards: Query<&mut Visibility, With<BoardRoot>>,
+) {
+    if !timed.0 && !clocks.is_running() {
+        return;
+    }
+    for mut visibility in &mut boards {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+/// System to show the board again once the game is no longer paused
+pub fn show_board(mut boards: Query<&mut Visibility, With<BoardRoot>>) {
+    for mut visibility in &mut boards {
+        *visibility = Visibility::Inherited;
+    }
+}
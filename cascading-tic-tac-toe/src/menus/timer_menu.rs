@@ -0,0 +1,55 @@
+use crate::{
+    main_menu::*, MenuButtonAction, OnTimerMenuScreen, TieRule, TimeControl, TimeLimit, TIE_RULES,
+    TIME_CONTROL_PRESETS,
+};
+use bevy::prelude::*;
+
+/// Minutes a timed game can last, one button each
+const MINUTES: [u32; 4] = [1, 3, 5, 10];
+
+/// A system that sets up the timed game menu, where the length of the game,
+/// the players' clocks and what breaks a tie are chosen
+pub fn timer_menu_setup(
+    mut commands: Commands,
+    time_limit: Res<TimeLimit>,
+    time_control: Res<TimeControl>,
+    tie_rule: Res<TieRule>,
+) {
+    spawn_menu_column(&mut commands, OnTimerMenuScreen, |parent| {
+        spawn_option_row(
+            parent,
+            "Minutes",
+            MINUTES
+                .iter()
+                .map(|&minutes| (TimeLimit(minutes * 60), minutes.to_string()))
+                .collect(),
+            *time_limit,
+        );
+        // Per-player clocks, run alongside the game clock
+        spawn_option_row(
+            parent,
+            "Clocks",
+            TIME_CONTROL_PRESETS
+                .iter()
+                .map(|&(control, label)| (control, label.to_string()))
+                .collect(),
+            *time_control,
+        );
+        spawn_option_row(
+            parent,
+            "Ties",
+            TIE_RULES
+                .iter()
+                .map(|&(rule, label)| (rule, label.to_string()))
+                .collect(),
+            *tie_rule,
+        );
+        spawn_action_row(
+            parent,
+            [
+                (MenuButtonAction::StartGame, "Start"),
+                (MenuButtonAction::BackToNewGame, "Back"),
+            ],
+        );
+    });
+}
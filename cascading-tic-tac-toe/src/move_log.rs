@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+
+use crate::theme::theme::UiTheme;
+use crate::{GameScreenTag, PlayerTurn};
+
+/// Number of moves shown on the game screen
+const SHOWN_MOVES: usize = 12;
+
+/// A single move in the move log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedMove {
+    pub player: PlayerTurn,
+    pub cell: (u32, u32),
+    pub book_move: bool,
+}
+
+/// A resource holding every move played in the current game
+#[derive(Resource, Default)]
+pub struct MoveLog {
+    pub moves: Vec<LoggedMove>,
+}
+
+impl MoveLog {
+    /// Add a move to the end of the log
+    pub fn record(&mut self, player: &PlayerTurn, cell: (u32, u32), book_move: bool) {
+        self.moves.push(LoggedMove {
+            player: player.clone(),
+            cell,
+            book_move,
+        });
+    }
+
+    /// Text for the most recent moves, numbered from the start of the game
+    pub fn label(&self) -> String {
+        let first = self.moves.len().saturating_sub(SHOWN_MOVES);
+        let mut label = "Moves".to_string();
+        for (index, logged) in self.moves.iter().enumerate().skip(first) {
+            label.push_str(&format!(
+                "\n{}. {:?} ({},{}){}",
+                index + 1,
+                logged.player,
+                logged.cell.0,
+                logged.cell.1,
+                if logged.book_move { " book" } else { "" }
+            ));
+        }
+        label
+    }
+}
+
+/// Component to store the move log text
+#[derive(Component)]
+pub struct MoveLogText;
+
+/// Define the root node for the UI move log text
+fn root() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::FlexStart,
+            align_items: AlignItems::FlexEnd,
+            padding: UiRect {
+                left: Val::Px(0.),
+                right: Val::Px(20.),
+                top: Val::Px(120.),
+                bottom: Val::Px(0.),
+            },
+            ..Default::default()
+        },
+        background_color: Color::NONE.into(),
+        ..Default::default()
+    }
+}
+
+/// Function to create the text node for the move log
+fn text(asset_server: &Res<AssetServer>, theme: &Res<UiTheme>, label: &str) -> TextBundle {
+    TextBundle {
+        text: Text::from_section(
+            label,
+            TextStyle {
+                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font_size: 18.0,
+                color: theme.button_text,
+            },
+        ),
+        ..Default::default()
+    }
+}
+
+/// System to set up the move log
+pub fn setup_move_log_text(
+    mut commands: Commands,
+    theme: Res<UiTheme>,
+    asset_server: Res<AssetServer>,
+    move_log: Res<MoveLog>,
+) {
+    commands
+        .spawn((root(), GameScreenTag))
+        .with_children(|parent| {
+            parent
+                .spawn(text(&asset_server, &theme, &move_log.label()))
+                .insert(MoveLogText);
+        });
+}
+
+/// System to update the move log text when a move is played
+pub fn update_move_log_text(
+    move_log: Res<MoveLog>,
+    mut move_log_text: Query<&mut Text, With<MoveLogText>>,
+) {
+    if move_log.is_changed() {
+        for mut text in move_log_text.iter_mut() {
+            text.sections[0].value = move_log.label();
+        }
+    }
+}
@@ -0,0 +1,1149 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::{
+    clean_chat, host_name, on_cell_clicked, reset_clock, setup_board, winner_on_time, BoardGrid,
+    CellClickedEvent, CellState, ChatContent, ChatReceivedEvent, ClockReading, Connection,
+    DiscoveryResponder, GameAdvert, GameClock, GameRecord, GameScreenTag, GameSnapshot, GameState,
+    LocalPlayers, MenuState, Message, MoveLog, PendingConnection, PlayerTag, PlayerTurn,
+    PlayingState, Position, RoomSettings, RoundInit, RoundState, TimeLimit, DISCOVERY_PORT,
+    PROTOCOL_VERSION, TEXT_COLOR,
+};
+
+/// How long joining waits for the host to answer the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a lost connection to a server game is retried, the server's default grace period
+const RECONNECT_TIME: Duration = Duration::from_secs(60);
+
+/// Time between attempts to reconnect, each one waits this long for the server
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time between pings to the server or host, which measure the delay of the clock readings
+const PING_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Notice shown once the clock of the game has run out
+const TIME_UP_NOTICE: &str = "Time is up";
+
+/// Notice shown while waiting for the opponent to agree to a restart
+const RESTART_ASKED_NOTICE: &str = "Restart asked, waiting for the opponent to agree";
+
+/// Notice shown when the opponent asks to restart a server game
+const RESTART_OFFERED_NOTICE: &str = "Opponent asks to restart, press Restart to agree";
+
+/// File remembering the server game being played, so it can be resumed
+/// after the game was closed
+pub const SESSION_PATH: &str = "online_session.txt";
+
+/// Remember the server and seat of the game being played
+pub fn save_session(path: &str, address: &str, token: u64) -> io::Result<()> {
+    fs::write(path, format!("{} {:016x}\n", address, token))
+}
+
+/// Server address and session token of the saved game, if there is one
+pub fn saved_session(path: &str) -> Option<(String, u64)> {
+    let text = fs::read_to_string(path).ok()?;
+    let (address, token) = text.trim().rsplit_once(' ')?;
+    Some((address.to_string(), u64::from_str_radix(token, 16).ok()?))
+}
+
+/// Forget the saved game
+pub fn forget_session(path: &str) {
+    // Nothing to forget when no game was saved
+    let _ = fs::remove_file(path);
+}
+
+/// Event sent when the local player resigns an online game
+#[derive(Event)]
+pub struct ResignEvent;
+
+/// Event sent when the local player asks to restart an online game
+#[derive(Event)]
+pub struct RestartEvent;
+
+/// A resource holding the connection to the other player, or to the game
+/// server, of an online game. It exists from pressing Host or Join until the
+/// online game ends.
+#[derive(Resource)]
+pub struct OnlineSession {
+    listener: Option<TcpListener>, // Waiting for the opponent, host only
+    responder: Option<DiscoveryResponder>, // Advertises the game on the local network, host only
+    connection: Option<Connection>,
+    connecting: Option<PendingConnection>, // Opened in the background, joining or reconnecting
+    pub local_player: PlayerTurn, // X for the host, O for the joining player, or as the server says
+    pub lobby: bool,              // Connected to a game server rather than a host
+    pub server_menu: MenuState,   // Menu a game server connection opens on
+    pub settings: Option<RoomSettings>, // Settings of the server's room, once the game starts
+    deferred: Vec<Message>,       // Received before the game screen was ready
+    pending_moves: VecDeque<(u32, u32)>, // Opponent moves not yet on the board
+    server_state: Option<GameSnapshot>, // Latest scores from the server, not yet checked
+    sent_moves: usize,            // Entries of the MoveLog already sent
+    remote_restart: bool,         // The restart in progress was asked for by the opponent
+    restart_asked: bool,          // Waiting for the server to restart the game, server games only
+    address: String,              // Where the game server is, for reconnecting
+    pub token: Option<u64>,       // Seat in a server game that can be taken back
+    resuming: Option<u64>,        // Token to take a seat back with once the server answers
+    reconnect_deadline: Option<Instant>, // Set while the connection to the server is lost
+    last_attempt: Option<Instant>, // Last attempt to reconnect
+    resync: Option<GameRecord>,   // The game so far, sent by the server after reconnecting
+    clock_resync: Option<u32>,    // Seconds the game has been going on for
+    clock: Option<(ClockReading, Instant)>, // Latest reading of the clock that keeps time, and when it came
+    clocked_moves: usize, // Entries of the MoveLog whose clock was sent, host only
+    started: Instant,     // Pings are stamped with the milliseconds since then
+    last_ping: Option<Instant>,
+    pub latency: Duration,     // Half the round trip to the server or host
+    pub notice: String,        // Shown on the game screen, like a lost connection
+    opponent_away: bool,       // The server is holding the opponent's seat
+    pub spectating: bool,      // Watching rather than playing
+    pub spectators: u32,       // How many are watching the game
+    arriving: Vec<Connection>, // Accepted by the host, not yet known to play or watch
+    new_spectators: Vec<Connection>, // Watching the hosted game, not yet sent the game so far
+    watchers: Vec<Connection>, // Watching the hosted game
+    relayed_moves: usize,      // Entries of the MoveLog already sent to watchers
+}
+
+impl OnlineSession {
+    /// Listen for an opponent on every interface
+    pub fn host(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let advert = GameAdvert {
+            name: host_name(),
+            port: listener.local_addr()?.port(),
+            players: 1,
+            // Hosted games are played with the default settings
+            settings: RoomSettings::default(),
+        };
+        let mut session = OnlineSession::new(Some(listener), None, PlayerTurn::X);
+        // The game can still be joined by address without it
+        match DiscoveryResponder::bind(("0.0.0.0", DISCOVERY_PORT), advert) {
+            Ok(responder) => session.responder = Some(responder),
+            Err(error) => println!("Game not advertised on the local network: {}", error),
+        }
+        Ok(session)
+    }
+
+    /// Connect to a host and ask to join its game. The connection is opened
+    /// in the background, polling reports whether it failed.
+    pub fn join(address: &str) -> Self {
+        let greeting = vec![Message::Hello {
+            version: PROTOCOL_VERSION,
+        }];
+        let mut session = OnlineSession::new(None, None, PlayerTurn::O);
+        session.connecting = Some(PendingConnection::start(address, CONNECT_TIMEOUT, greeting));
+        session.address = address.to_string();
+        session
+    }
+
+    /// Still opening the connection to the host or server
+    pub fn is_connecting(&self) -> bool {
+        self.connecting.is_some()
+    }
+
+    /// Connect to a game server and open `menu` once it answers: its lobby,
+    /// correspondence games or leaderboard
+    pub fn join_server(address: &str, menu: MenuState) -> Self {
+        let mut session = OnlineSession::join(address);
+        session.lobby = true;
+        session.server_menu = menu;
+        session
+    }
+
+    /// Connect to a game server to take back the seat of a game left earlier
+    pub fn resume(address: &str, token: u64) -> Self {
+        let mut session = OnlineSession::join(address);
+        session.resuming = Some(token);
+        session
+    }
+
+    /// Connect to a host, or a game server, to watch one of its games
+    pub fn watch(address: &str, game: u32) -> Self {
+        let greeting = vec![Message::Watch {
+            version: PROTOCOL_VERSION,
+            game,
+        }];
+        let mut session = OnlineSession::new(None, None, PlayerTurn::X);
+        session.connecting = Some(PendingConnection::start(address, CONNECT_TIMEOUT, greeting));
+        session.address = address.to_string();
+        session.spectating = true;
+        session
+    }
+
+    /// Token to send once the server has welcomed a resuming player
+    pub fn resuming(&self) -> Option<u64> {
+        self.resuming
+    }
+
+    fn new(
+        listener: Option<TcpListener>,
+        connection: Option<Connection>,
+        local_player: PlayerTurn,
+    ) -> Self {
+        OnlineSession {
+            listener,
+            responder: None,
+            connection,
+            connecting: None,
+            local_player,
+            lobby: false,
+            server_menu: MenuState::Lobby,
+            settings: None,
+            deferred: Vec::new(),
+            pending_moves: VecDeque::new(),
+            server_state: None,
+            sent_moves: 0,
+            remote_restart: false,
+            address: String::new(),
+            token: None,
+            resuming: None,
+            reconnect_deadline: None,
+            last_attempt: None,
+            resync: None,
+            clock_resync: None,
+            clock: None,
+            clocked_moves: 0,
+            started: Instant::now(),
+            last_ping: None,
+            latency: Duration::ZERO,
+            notice: String::new(),
+            opponent_away: false,
+            spectating: false,
+            spectators: 0,
+            arriving: Vec::new(),
+            new_spectators: Vec::new(),
+            watchers: Vec::new(),
+            relayed_moves: 0,
+            restart_asked: false,
+        }
+    }
+
+    /// Port the host is listening on
+    pub fn port(&self) -> Option<u16> {
+        self.listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+            .map(|address| address.port())
+    }
+
+    /// Whether the clock of this side is the one that counts: the host keeps
+    /// time for its game, the server for its own
+    pub fn keeps_time(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    /// Take the delay from the answer to a ping, averaged with the earlier ones
+    fn measure_latency(&mut self, stamp: u64) {
+        let Some(round_trip) = self
+            .started
+            .elapsed()
+            .checked_sub(Duration::from_millis(stamp))
+        else {
+            return;
+        };
+        let latency = round_trip / 2;
+        self.latency = if self.latency.is_zero() {
+            latency
+        } else {
+            (self.latency * 3 + latency) / 4
+        };
+    }
+
+    /// Send a message to the opponent, once one is connected
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        match self.connection.as_mut() {
+            Some(connection) => connection.send(message),
+            None => Ok(()),
+        }
+    }
+
+    /// Send a message to the spectators of a hosted game, dropping those who have left
+    pub fn send_spectators(&mut self, message: &Message) {
+        self.watchers
+            .retain_mut(|watcher| watcher.send(message).is_ok());
+    }
+
+    /// Text shown on the game screen: the notice and how many are watching
+    pub fn notice_label(&self) -> String {
+        let mut lines = Vec::new();
+        if self.spectating {
+            lines.push("Spectating".to_string());
+        }
+        if !self.notice.is_empty() {
+            lines.push(self.notice.clone());
+        }
+        if self.spectators > 0 {
+            lines.push(format!("{} watching", self.spectators));
+        }
+        lines.join("\n")
+    }
+
+    /// Sort connections accepted by the host by their first message: the
+    /// first HELLO is the opponent, WATCH is a spectator
+    fn admit(&mut self, messages: &mut Vec<Message>) {
+        for mut connection in std::mem::take(&mut self.arriving) {
+            let Ok(mut received) = connection.poll() else {
+                continue;
+            };
+            if received.is_empty() {
+                self.arriving.push(connection);
+                continue;
+            }
+            let refusal = match received.remove(0) {
+                Message::Watch { version, .. } if version == PROTOCOL_VERSION => {
+                    self.new_spectators.push(connection);
+                    continue;
+                }
+                hello @ Message::Hello { .. } if self.connection.is_none() => {
+                    self.connection = Some(connection);
+                    messages.push(hello);
+                    messages.extend(received);
+                    continue;
+                }
+                Message::Watch { version, .. } => format!(
+                    "protocol version {} is not supported, host uses {}",
+                    version, PROTOCOL_VERSION
+                ),
+                _ => "the game already has two players".to_string(),
+            };
+            // Best effort, the connection is closed either way
+            let _ = connection.send(&Message::Reject { reason: refusal });
+        }
+    }
+
+    /// Keep a message that arrived while connecting for the game to handle
+    pub fn defer(&mut self, message: Message) {
+        self.deferred.push(message);
+    }
+
+    /// Start connecting to the server again to ask for the seat back
+    fn reconnect(&mut self, token: u64) {
+        let greeting = vec![
+            Message::Hello {
+                version: PROTOCOL_VERSION,
+            },
+            Message::Resume { token },
+        ];
+        self.connecting = Some(PendingConnection::start(
+            &self.address,
+            RECONNECT_INTERVAL,
+            greeting,
+        ));
+    }
+
+    /// Accept a waiting opponent, answer discovery queries and return every
+    /// message received since the last poll, after any deferred ones. A lost
+    /// connection to a server game is retried until RECONNECT_TIME has passed.
+    /// A failed connection is reported while is_connecting is still true.
+    pub fn poll(&mut self) -> io::Result<Vec<Message>> {
+        let mut messages = std::mem::take(&mut self.deferred);
+        if let Some(responder) = self.responder.as_mut() {
+            responder.advert.players = if self.connection.is_some() { 2 } else { 1 };
+            if let Err(error) = responder.answer() {
+                println!("Stopped advertising the game: {}", error);
+                self.responder = None;
+            }
+        }
+        // Stays open after the opponent arrives, for spectators
+        if let Some(listener) = &self.listener {
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => self.arriving.push(Connection::new(stream)?),
+                    Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+        self.admit(&mut messages);
+        // Spectators only ping, polling also notices those who left
+        self.watchers.retain_mut(|watcher| match watcher.poll() {
+            Ok(messages) => messages.into_iter().all(|message| match message {
+                Message::Ping { stamp } => watcher.send(&Message::Pong { stamp }).is_ok(),
+                _ => true,
+            }),
+            Err(_) => false,
+        });
+        if let Some(connected) = self.connecting.as_mut().and_then(PendingConnection::poll) {
+            match connected {
+                Ok(connection) => {
+                    self.connection = Some(connection);
+                    self.connecting = None;
+                    self.reconnect_deadline = None;
+                }
+                // Tried again once RECONNECT_INTERVAL has passed
+                Err(error) if self.reconnect_deadline.is_some() => {
+                    println!("Could not reconnect: {}", error);
+                    self.connecting = None;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        if let (None, None, Some(deadline), Some(token)) = (
+            &self.connection,
+            &self.connecting,
+            self.reconnect_deadline,
+            self.token,
+        ) {
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "could not reconnect to the server",
+                ));
+            }
+            let due = match self.last_attempt {
+                Some(last) => last.elapsed() >= RECONNECT_INTERVAL,
+                None => true,
+            };
+            if due {
+                self.last_attempt = Some(Instant::now());
+                self.reconnect(token);
+            }
+        }
+        if let Some(connection) = self.connection.as_mut() {
+            match connection.poll() {
+                Ok(received) => messages.extend(received),
+                // The server keeps the seat for a while
+                Err(error) if self.token.is_some() => {
+                    println!("Connection to the server lost: {}", error);
+                    self.connection = None;
+                    self.reconnect_deadline = Some(Instant::now() + RECONNECT_TIME);
+                    self.notice = "Connection lost, reconnecting".to_string();
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// Struct for online games
+pub struct OnlinePlugin;
+
+/// Plugin that exchanges moves, restarts and resignations with the opponent
+impl Plugin for OnlinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ResignEvent>()
+            .add_event::<RestartEvent>()
+            .add_systems(
+                Update,
+                (
+                    receive_online_messages,
+                    send_local_moves,
+                    send_resignation,
+                    ask_restart,
+                    ping_opponent,
+                    apply_resync,
+                    resync_clock.after(apply_resync),
+                    update_online_notice,
+                    serve_spectators,
+                )
+                    .run_if(in_state(PlayingState::Online)),
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_remote_moves
+                        .before(on_cell_clicked)
+                        .before(apply_resync),
+                    apply_server_state,
+                    apply_clock,
+                    send_clock,
+                    run_out_clock.after(apply_clock),
+                )
+                    .run_if(in_state(PlayingState::Online))
+                    .run_if(in_state(GameState::GameOngoing)),
+            )
+            .add_systems(
+                OnEnter(GameState::LoadingNewGame),
+                apply_room_settings
+                    .before(reset_clock)
+                    .before(setup_board)
+                    .run_if(in_state(PlayingState::Online)),
+            )
+            .add_systems(
+                OnEnter(GameState::RestartingGame),
+                send_restart.run_if(in_state(PlayingState::Online)),
+            )
+            .add_systems(OnExit(PlayingState::Online), end_online_session);
+    }
+}
+
+/// Leave the online game and go back to the main menu
+fn leave_online_game(
+    next_game_state: &mut NextState<GameState>,
+    next_round_state: &mut NextState<RoundState>,
+    next_playing_state: &mut NextState<PlayingState>,
+    next_menu_state: &mut NextState<MenuState>,
+) {
+    next_round_state.set(RoundState::NotUpdating);
+    next_game_state.set(GameState::NotPlaying);
+    next_playing_state.set(PlayingState::NotPlaying);
+    next_menu_state.set(MenuState::Main);
+}
+
+/// System that handles messages from the opponent during the game
+fn receive_online_messages(
+    mut session: ResMut<OnlineSession>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_round_state: ResMut<NextState<RoundState>>,
+    mut next_playing_state: ResMut<NextState<PlayingState>>,
+    mut next_menu_state: ResMut<NextState<MenuState>>,
+    mut chat_events: EventWriter<ChatReceivedEvent>,
+) {
+    let messages = match session.poll() {
+        Ok(messages) => messages,
+        Err(error) => {
+            println!("Online game ended: {}", error);
+            leave_online_game(
+                &mut next_game_state,
+                &mut next_round_state,
+                &mut next_playing_state,
+                &mut next_menu_state,
+            );
+            return;
+        }
+    };
+
+    for message in messages {
+        match message {
+            Message::Move { cell } => session.pending_moves.push_back(cell),
+            // The server sends a restart to ask for agreement, and to both
+            // players once it has been given
+            Message::Restart if session.lobby && !session.restart_asked => {
+                if !session.spectating {
+                    session.notice = RESTART_OFFERED_NOTICE.to_string();
+                }
+            }
+            Message::Restart => {
+                session.restart_asked = false;
+                session.remote_restart = true;
+                next_game_state.set(GameState::RestartingGame);
+                next_round_state.set(RoundState::NotUpdating);
+            }
+            Message::Resign => {
+                if session.opponent_away {
+                    session.notice = "Opponent did not reconnect and forfeits".to_string();
+                }
+                let (winner, opponent) = match session.local_player {
+                    PlayerTurn::X => (PlayerTag::X, PlayerTurn::O),
+                    PlayerTurn::O => (PlayerTag::O, PlayerTurn::X),
+                };
+                session.send_spectators(&Message::Resigned(opponent));
+                next_game_state.set(GameState::Won(winner));
+            }
+            Message::Resigned(player) => {
+                let winner = match player {
+                    PlayerTurn::X => PlayerTag::O,
+                    PlayerTurn::O => PlayerTag::X,
+                };
+                next_game_state.set(GameState::Won(winner));
+            }
+            Message::Spectators { count } => session.spectators = count,
+            Message::Clock(reading) => session.clock = Some((reading, Instant::now())),
+            Message::TimeUp(winner) => {
+                session.notice = TIME_UP_NOTICE.to_string();
+                session.send_spectators(&Message::TimeUp(winner.clone()));
+                next_game_state.set(winner.map_or(GameState::Draw, GameState::Won));
+            }
+            Message::Ping { stamp } => {
+                if let Err(error) = session.send(&Message::Pong { stamp }) {
+                    println!("Could not answer ping: {}", error);
+                }
+            }
+            Message::Pong { stamp } => session.measure_latency(stamp),
+            Message::Chat { text } => {
+                if let Some(text) = clean_chat(&text) {
+                    chat_events.send(ChatReceivedEvent(ChatContent::Text(text)));
+                }
+            }
+            Message::Emote(emote) => {
+                chat_events.send(ChatReceivedEvent(ChatContent::Emote(emote)));
+            }
+            Message::State(snapshot) => session.server_state = Some(snapshot),
+            // A refused move is followed by a Sync that takes it back off the board
+            Message::Invalid { reason } => {
+                println!("Refused by the server: {}", reason);
+                if session.restart_asked {
+                    session.restart_asked = false;
+                    session.notice = reason;
+                }
+            }
+            Message::Session { token } => {
+                session.token = Some(token);
+                if let Err(error) = save_session(SESSION_PATH, &session.address, token) {
+                    println!("Could not save the online session: {}", error);
+                }
+            }
+            Message::Sync(record) => {
+                // Moves received after the record are played on the rebuilt board
+                session.pending_moves.clear();
+                session.notice.clear();
+                session.resync = Some(record);
+            }
+            Message::Away { seconds } => {
+                let who = if session.spectating {
+                    "a player"
+                } else {
+                    "opponent"
+                };
+                session.notice = format!(
+                    "Waiting for {} to reconnect, they forfeit in {} seconds",
+                    who, seconds
+                );
+                session.opponent_away = true;
+            }
+            Message::Back => {
+                session.notice.clear();
+                session.opponent_away = false;
+            }
+            Message::Reject { reason } => {
+                println!("Could not get back into the online game: {}", reason);
+                session.token = None;
+                leave_online_game(
+                    &mut next_game_state,
+                    &mut next_round_state,
+                    &mut next_playing_state,
+                    &mut next_menu_state,
+                );
+                return;
+            }
+            // Only used while connecting or in the lobby
+            Message::Hello { .. }
+            | Message::Welcome { .. }
+            | Message::Resume { .. }
+            | Message::Watch { .. }
+            | Message::Playing(_)
+            | Message::Spectate(_)
+            | Message::List
+            | Message::Rooms(_)
+            | Message::Create(_)
+            | Message::Join { .. }
+            | Message::Leave
+            | Message::QuickMatch
+            | Message::Room { .. }
+            | Message::Start(..)
+            | Message::Games { .. }
+            | Message::GameList(_)
+            | Message::Challenge { .. }
+            | Message::Accept { .. }
+            | Message::Open { .. }
+            | Message::Name { .. }
+            | Message::Leaders { .. }
+            | Message::Leaderboard(_)
+            | Message::History(_) => (),
+        }
+    }
+}
+
+/// System that plays the opponent's next move through the cell-click pipeline
+fn apply_remote_moves(
+    mut session: ResMut<OnlineSession>,
+    local_players: Res<LocalPlayers>,
+    player_turn: Res<State<PlayerTurn>>,
+    grid: Res<BoardGrid>,
+    mut send_cell_clicked: EventWriter<CellClickedEvent>,
+) {
+    // Moves after a resync wait for the rebuilt board
+    if local_players.controls(player_turn.get()) || session.resync.is_some() {
+        return;
+    }
+    let Some(cell) = session.pending_moves.pop_front() else {
+        return;
+    };
+    match grid.get(cell) {
+        CellState::Valid => {
+            send_cell_clicked.send(CellClickedEvent {
+                cell,
+                book_move: false,
+            });
+        }
+        _ => println!("Ignored invalid move from opponent: {:?}", cell),
+    }
+}
+
+/// System that takes the scores from the server once this board has caught up
+/// with its latest snapshot, so scores never differ from the server's
+fn apply_server_state(
+    mut session: ResMut<OnlineSession>,
+    move_log: Res<MoveLog>,
+    mut round_init: ResMut<RoundInit>,
+) {
+    let Some(snapshot) = &session.server_state else {
+        return;
+    };
+    if session.resync.is_some() {
+        return;
+    }
+    if snapshot.moves as usize != move_log.moves.len()
+        || snapshot.round_count != round_init.round_count
+    {
+        return;
+    }
+    if (round_init.x_score, round_init.o_score) != (snapshot.x_score, snapshot.o_score) {
+        println!("Scores corrected by the server");
+        round_init.x_score = snapshot.x_score;
+        round_init.o_score = snapshot.o_score;
+    }
+    session.server_state = None;
+}
+
+/// System that rebuilds the board, scores, move log and turn from the
+/// server's record after reconnecting. The filled cells go into the
+/// BoardGrid for setup_board to lay out the board of the current round.
+#[allow(clippy::too_many_arguments)]
+fn apply_resync(
+    mut commands: Commands,
+    mut session: ResMut<OnlineSession>,
+    game_state: Res<State<GameState>>,
+    screen: Query<Entity, With<GameScreenTag>>,
+    mut round_init: ResMut<RoundInit>,
+    mut move_log: ResMut<MoveLog>,
+    mut grid: ResMut<BoardGrid>,
+    mut next_player_turn: ResMut<NextState<PlayerTurn>>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_round_state: ResMut<NextState<RoundState>>,
+) {
+    // Wait for the game screen when resuming from the menu
+    if !matches!(
+        game_state.get(),
+        GameState::GameOngoing | GameState::Won(_) | GameState::Draw
+    ) {
+        return;
+    }
+    let Some(record) = session.resync.take() else {
+        return;
+    };
+
+    let mut position = Position::new();
+    let mut log = MoveLog::default();
+    for &cell in &record.moves {
+        if !position.moves().contains(&cell) {
+            println!("Ignored resync with an unplayable move: {:?}", cell);
+            return;
+        }
+        log.record(position.turn(), cell, false);
+        position = position.play(cell);
+    }
+
+    round_init.round_count = position.round_count();
+    round_init.x_score = position.x_score();
+    round_init.o_score = position.o_score();
+    round_init.game_combinations = position.game_combinations().clone();
+    *move_log = log;
+    session.sent_moves = move_log.moves.len();
+    session.clock_resync = Some(record.elapsed);
+    next_player_turn.set(position.turn().clone());
+
+    for entity in &screen {
+        commands.entity(entity).despawn_recursive();
+    }
+    *grid = position.board().clone();
+    // Rebuilt like a new round, the game is then checked for a winner
+    next_game_state.set(GameState::Updating);
+    next_round_state.set(RoundState::UpdatingRound);
+}
+
+/// System that winds the clock of a resynced game forward by the time
+/// it was played on the server
+fn resync_clock(mut session: ResMut<OnlineSession>, mut clock: ResMut<GameClock>) {
+    let Some(elapsed) = session.clock_resync.take() else {
+        return;
+    };
+    clock.set_elapsed(Duration::from_secs(elapsed.into()));
+}
+
+/// System that pings the server or host now and then, to learn how late
+/// its clock readings arrive
+fn ping_opponent(mut session: ResMut<OnlineSession>) {
+    if session.keeps_time() {
+        return;
+    }
+    if session
+        .last_ping
+        .is_some_and(|last| last.elapsed() < PING_INTERVAL)
+    {
+        return;
+    }
+    session.last_ping = Some(Instant::now());
+    let stamp = session.started.elapsed().as_millis() as u64;
+    // A lost connection is noticed when polling
+    let _ = session.send(&Message::Ping { stamp });
+}
+
+/// System that sets the clock to the latest reading of the server or host
+/// once this board has caught up with it, adding the time the reading took
+/// to arrive and has waited since
+fn apply_clock(
+    mut session: ResMut<OnlineSession>,
+    move_log: Res<MoveLog>,
+    round_init: Res<RoundInit>,
+    mut clock: ResMut<GameClock>,
+) {
+    let Some((reading, received)) = &session.clock else {
+        return;
+    };
+    if session.resync.is_some()
+        || reading.moves as usize != move_log.moves.len()
+        || reading.round_count != round_init.round_count
+    {
+        return;
+    }
+    let elapsed =
+        Duration::from_millis(reading.elapsed.into()) + session.latency + received.elapsed();
+    clock.set_elapsed(elapsed);
+    if elapsed < clock.duration() {
+        clock.unpause();
+    } else {
+        clock.pause();
+    }
+    session.clock = None;
+}
+
+/// System that stamps every move with the host's clock, for the opponent
+/// and the spectators
+fn send_clock(
+    mut session: ResMut<OnlineSession>,
+    move_log: Res<MoveLog>,
+    round_init: Res<RoundInit>,
+    clock: Res<GameClock>,
+) {
+    // The log is cleared when the game restarts
+    if move_log.moves.len() < session.clocked_moves {
+        session.clocked_moves = 0;
+    }
+    if !session.keeps_time() || move_log.moves.len() == session.clocked_moves {
+        return;
+    }
+    session.clocked_moves = move_log.moves.len();
+    let reading = Message::Clock(ClockReading {
+        moves: move_log.moves.len() as u32,
+        round_count: round_init.round_count,
+        elapsed: clock.elapsed().as_millis() as u32,
+    });
+    session.send_spectators(&reading);
+    if let Err(error) = session.send(&reading) {
+        println!("Could not send the clock: {}", error);
+    }
+}
+
+/// System that stops the clock at zero. Only the host, or the server, ends
+/// the game when time is up, so both players always see the same result.
+fn run_out_clock(
+    mut session: ResMut<OnlineSession>,
+    round_init: Res<RoundInit>,
+    mut clock: ResMut<GameClock>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+) {
+    if clock.paused() || !clock.just_finished() {
+        return;
+    }
+    clock.pause();
+    if !session.keeps_time() {
+        return;
+    }
+    let winner = winner_on_time(round_init.x_score, round_init.o_score);
+    session.notice = TIME_UP_NOTICE.to_string();
+    session.send_spectators(&Message::TimeUp(winner.clone()));
+    if let Err(error) = session.send(&Message::TimeUp(winner.clone())) {
+        println!("Could not send the end of the clock: {}", error);
+    }
+    next_game_state.set(winner.map_or(GameState::Draw, GameState::Won));
+}
+
+/// Tag component for the notice about lost connections on the game screen
+#[derive(Component)]
+pub struct OnlineNoticeText;
+
+/// System that shows the session's notice on the game screen, which is
+/// rebuilt every round
+fn update_online_notice(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    session: Res<OnlineSession>,
+    game_state: Res<State<GameState>>,
+    mut notices: Query<&mut Text, With<OnlineNoticeText>>,
+) {
+    if notices.is_empty() {
+        if matches!(
+            game_state.get(),
+            GameState::GameOngoing | GameState::Won(_) | GameState::Draw
+        ) {
+            commands.spawn((
+                TextBundle {
+                    text: Text::from_section(
+                        session.notice_label(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 30.,
+                            color: TEXT_COLOR,
+                        },
+                    ),
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        bottom: Val::Px(10.0),
+                        left: Val::Px(10.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                OnlineNoticeText,
+                GameScreenTag,
+            ));
+        }
+        return;
+    }
+    if session.is_changed() {
+        for mut text in notices.iter_mut() {
+            let label = session.notice_label();
+            if text.sections[0].value != label {
+                text.sections[0].value = label;
+            }
+        }
+    }
+}
+
+/// System that keeps the spectators of a hosted game up to date: new ones
+/// are sent the game so far, then every move as it is played
+fn serve_spectators(
+    mut session: ResMut<OnlineSession>,
+    move_log: Res<MoveLog>,
+    round_init: Res<RoundInit>,
+    time_limit: Res<TimeLimit>,
+    clock: Res<GameClock>,
+) {
+    let first = session.relayed_moves.min(move_log.moves.len());
+    for logged in move_log.moves[first..].iter() {
+        session.send_spectators(&Message::Move { cell: logged.cell });
+    }
+    session.relayed_moves = move_log.moves.len();
+
+    if !session.new_spectators.is_empty() {
+        let settings = RoomSettings {
+            target: round_init.target,
+            timer: time_limit.0,
+            ..RoomSettings::default()
+        };
+        let record = GameRecord {
+            moves: move_log.moves.iter().map(|logged| logged.cell).collect(),
+            elapsed: clock.elapsed().as_secs() as u32,
+        };
+        for mut spectator in std::mem::take(&mut session.new_spectators) {
+            let joined = spectator
+                .send(&Message::Spectate(settings.clone()))
+                .and_then(|()| spectator.send(&Message::Sync(record.clone())));
+            if joined.is_ok() {
+                session.watchers.push(spectator);
+            }
+        }
+    }
+
+    // Only the host has watchers, the other side hears the count from it
+    let count = session.watchers.len() as u32;
+    if session.listener.is_some() && count != session.spectators {
+        session.spectators = count;
+        let message = Message::Spectators { count };
+        session.send_spectators(&message);
+        if let Err(error) = session.send(&message) {
+            println!("Could not send the spectator count: {}", error);
+        }
+    }
+}
+
+/// System that plays to the target and clock of the server's room. Hosted
+/// games are advertised with the default settings, so both sides play to
+/// those rather than whatever their last local game used.
+fn apply_room_settings(
+    session: Res<OnlineSession>,
+    mut round_init: ResMut<RoundInit>,
+    mut time_limit: ResMut<TimeLimit>,
+) {
+    let settings = session.settings.clone().unwrap_or_default();
+    round_init.target = settings.target;
+    time_limit.0 = settings.timer;
+}
+
+/// System that sends the local player's moves once they are on the board
+fn send_local_moves(
+    mut session: ResMut<OnlineSession>,
+    move_log: Res<MoveLog>,
+    local_players: Res<LocalPlayers>,
+) {
+    // The log is cleared when the game restarts
+    if move_log.moves.len() < session.sent_moves {
+        session.sent_moves = 0;
+    }
+    let first = session.sent_moves;
+    for logged in move_log.moves[first..].iter() {
+        if local_players.controls(&logged.player) {
+            if let Err(error) = session.send(&Message::Move { cell: logged.cell }) {
+                println!("Could not send move: {}", error);
+            }
+        }
+    }
+    session.sent_moves = move_log.moves.len();
+}
+
+/// System that tells the opponent about a restart asked for on this side
+fn send_restart(mut session: ResMut<OnlineSession>) {
+    session.pending_moves.clear();
+    session.clock = None;
+    if matches!(
+        session.notice.as_str(),
+        TIME_UP_NOTICE | RESTART_ASKED_NOTICE | RESTART_OFFERED_NOTICE
+    ) {
+        session.notice.clear();
+    }
+    session.send_spectators(&Message::Restart);
+    session.relayed_moves = 0;
+    if session.remote_restart {
+        session.remote_restart = false;
+    } else if let Err(error) = session.send(&Message::Restart) {
+        println!("Could not send restart: {}", error);
+    }
+}
+
+/// System that restarts the online game for the local player. A hosted game
+/// restarts at once, a server game once the opponent agrees.
+fn ask_restart(
+    mut events: EventReader<RestartEvent>,
+    mut session: ResMut<OnlineSession>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_round_state: ResMut<NextState<RoundState>>,
+) {
+    for _ in events.read() {
+        if !session.lobby {
+            next_game_state.set(GameState::RestartingGame);
+            next_round_state.set(RoundState::NotUpdating);
+        } else if !session.spectating && !session.restart_asked {
+            if let Err(error) = session.send(&Message::Restart) {
+                println!("Could not send restart: {}", error);
+                continue;
+            }
+            session.restart_asked = true;
+            session.notice = RESTART_ASKED_NOTICE.to_string();
+        }
+    }
+}
+
+/// System that resigns the online game for the local player
+fn send_resignation(
+    mut events: EventReader<ResignEvent>,
+    mut session: ResMut<OnlineSession>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+) {
+    for _ in events.read() {
+        if let Err(error) = session.send(&Message::Resign) {
+            println!("Could not send resignation: {}", error);
+        }
+        let local_player = session.local_player.clone();
+        session.send_spectators(&Message::Resigned(local_player));
+        let winner = match session.local_player {
+            PlayerTurn::X => PlayerTag::O,
+            PlayerTurn::O => PlayerTag::X,
+        };
+        next_game_state.set(GameState::Won(winner));
+    }
+}
+
+/// System that closes the connection when the online game is left
+fn end_online_session(
+    mut commands: Commands,
+    mut local_players: ResMut<LocalPlayers>,
+    mut time_limit: ResMut<TimeLimit>,
+) {
+    commands.remove_resource::<OnlineSession>();
+    forget_session(SESSION_PATH);
+    *local_players = LocalPlayers::default();
+    *time_limit = TimeLimit::default();
+}
+
+/// Unit tests for online sessions
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A host and a joining player find each other over loopback
+    #[test]
+    fn test_host_and_join() {
+        let mut host = OnlineSession::host(0).unwrap();
+        let port = host.port().unwrap();
+        let mut joiner = OnlineSession::join(&format!("127.0.0.1:{}", port));
+        assert_eq!(joiner.local_player, PlayerTurn::O);
+
+        let mut received = Vec::new();
+        while received.is_empty() {
+            received = host.poll().unwrap();
+        }
+        assert_eq!(
+            received,
+            vec![Message::Hello {
+                version: PROTOCOL_VERSION
+            }]
+        );
+
+        host.send(&Message::Move { cell: (1, 1) }).unwrap();
+        let mut received = Vec::new();
+        while received.is_empty() {
+            received = joiner.poll().unwrap();
+        }
+        assert_eq!(received, vec![Message::Move { cell: (1, 1) }]);
+    }
+
+    /// A host tells spectators from its opponent by their first message
+    #[test]
+    fn test_host_admits_spectators() {
+        let mut host = OnlineSession::host(0).unwrap();
+        let address = format!("127.0.0.1:{}", host.port().unwrap());
+        let spectator = OnlineSession::watch(&address, 0);
+        assert!(spectator.spectating);
+        while host.new_spectators.is_empty() {
+            assert!(host.poll().unwrap().is_empty());
+        }
+
+        let _joiner = OnlineSession::join(&address);
+        let mut received = Vec::new();
+        while received.is_empty() {
+            received = host.poll().unwrap();
+        }
+        assert_eq!(
+            received,
+            vec![Message::Hello {
+                version: PROTOCOL_VERSION
+            }]
+        );
+        assert_eq!(host.new_spectators.len(), 1);
+    }
+
+    /// The delay is half the round trip of a ping, averaged with earlier ones
+    #[test]
+    fn test_measure_latency() {
+        let mut session = OnlineSession::new(None, None, PlayerTurn::O);
+        session.started -= Duration::from_millis(1000);
+        session.measure_latency(800);
+        let first = session.latency;
+        assert!(first >= Duration::from_millis(100) && first < Duration::from_millis(150));
+        session.measure_latency(1000);
+        assert!(session.latency < first && session.latency >= first * 3 / 4);
+        // Stamps from the future are ignored
+        session.measure_latency(60_000);
+        assert!(session.latency < first);
+    }
+
+    /// A saved session is read back and forgotten
+    #[test]
+    fn test_saved_session() {
+        let path = std::env::temp_dir().join("cascading_test_session.txt");
+        let path = path.to_str().unwrap();
+        save_session(path, "example.org:7878", 0xfeed).unwrap();
+        assert_eq!(
+            saved_session(path),
+            Some(("example.org:7878".to_string(), 0xfeed))
+        );
+        forget_session(path);
+        assert_eq!(saved_session(path), None);
+    }
+}
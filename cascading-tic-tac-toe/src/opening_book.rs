@@ -0,0 +1,260 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::prelude::Resource;
+
+use crate::{
+    canonical_position, rank_moves, solve_position, EvalWeights, Position, SearchMode,
+    SolverConfig, TranspositionTable,
+};
+
+/// Version of the book file format. Books are keyed by Position::hash, so
+/// any change to the hashing or cell ids must bump it.
+pub const BOOK_VERSION: u32 = 1;
+
+/// Where the game looks for its opening book
+pub const BOOK_PATH: &str = "assets/books/opening_book.txt";
+
+/// First line of every book file
+const BOOK_HEADER: &str = "cascading-tic-tac-toe opening book";
+
+/// A resource holding recommended moves for early positions. Positions are
+/// stored in canonical form (see canonical_position), so one entry covers
+/// every position symmetric to it.
+#[derive(Resource, Debug, Default, Clone, PartialEq, Eq)]
+pub struct OpeningBook {
+    pub target: u32, // Target score the book was built for
+    moves: HashMap<u64, (u32, u32)>,
+}
+
+impl OpeningBook {
+    /// Empty book for games played to `target`
+    pub fn new(target: u32) -> Self {
+        OpeningBook {
+            target,
+            moves: HashMap::new(),
+        }
+    }
+
+    /// Number of positions in the book
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// Recommend a move for a position, keeping any earlier recommendation
+    /// for it or a symmetric position
+    pub fn insert(&mut self, position: &Position, cell: (u32, u32)) {
+        let (canonical, symmetry) = canonical_position(position);
+        let cell = symmetry.apply(cell, position.round_count());
+        self.moves.entry(canonical.hash()).or_insert(cell);
+    }
+
+    /// Recommended move for a position, mapped back onto its board
+    pub fn get(&self, position: &Position) -> Option<(u32, u32)> {
+        let (canonical, symmetry) = canonical_position(position);
+        let cell = self.moves.get(&canonical.hash())?;
+        let cell = symmetry.inverse().apply(*cell, position.round_count());
+        // Guard against a hash collision recommending an illegal move
+        position.moves().contains(&cell).then_some(cell)
+    }
+
+    /// Book file contents, one line per position sorted by hash
+    pub fn to_text(&self) -> String {
+        let mut lines: Vec<String> = self
+            .moves
+            .iter()
+            .map(|(hash, cell)| format!("{:016x} {} {}", hash, cell.0, cell.1))
+            .collect();
+        lines.sort();
+        format!(
+            "{}\nversion {}\ntarget {}\n{}\n",
+            BOOK_HEADER,
+            BOOK_VERSION,
+            self.target,
+            lines.join("\n")
+        )
+    }
+
+    /// Parse a book file, rejecting other formats and versions
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut lines = text.lines();
+
+        if lines.next() != Some(BOOK_HEADER) {
+            return Err(invalid("not an opening book".to_string()));
+        }
+        let mut header_value = |name: &str| -> io::Result<u32> {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| invalid(format!("missing {}", name)))
+        };
+        let version = header_value("version")?;
+        if version != BOOK_VERSION {
+            return Err(invalid(format!(
+                "book version {} is not supported, expected {}",
+                version, BOOK_VERSION
+            )));
+        }
+        let mut book = OpeningBook::new(header_value("target")?);
+
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let entry = match fields.as_slice() {
+                [hash, row, col] => u64::from_str_radix(hash, 16)
+                    .ok()
+                    .zip(row.parse().ok().zip(col.parse().ok())),
+                _ => None,
+            };
+            let (hash, cell) = entry.ok_or_else(|| invalid(format!("bad book line: {}", line)))?;
+            book.moves.insert(hash, cell);
+        }
+        Ok(book)
+    }
+
+    /// Write the book to a file, creating its directory if needed
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        if let Some(directory) = path.as_ref().parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, self.to_text())
+    }
+
+    /// Read a book file
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        OpeningBook::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// Read a book file, or fall back to an empty book
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        match OpeningBook::load(path) {
+            Ok(book) => book,
+            Err(error) => {
+                if error.kind() != io::ErrorKind::NotFound {
+                    println!("Opening book not loaded: {}", error);
+                }
+                OpeningBook::default()
+            }
+        }
+    }
+}
+
+/// Positions reachable from the empty board in at most `plies` moves that
+/// are still being played, one per symmetry class
+fn opening_positions(target: u32, plies: u32) -> Vec<Position> {
+    let mut seen = HashSet::new();
+    let mut positions = vec![Position::new()];
+    let mut frontier = vec![Position::new()];
+    for _ in 0..plies {
+        let mut next = Vec::new();
+        for position in frontier.iter() {
+            for cell in position.moves() {
+                let child = position.play(cell);
+                let finished = child.x_score() >= target || child.o_score() >= target;
+                if !finished && seen.insert(canonical_position(&child).0.hash()) {
+                    next.push(child);
+                }
+            }
+        }
+        positions.extend(next.iter().cloned());
+        frontier = next;
+    }
+    positions
+}
+
+/// Build a book from solver output: every opening position up to `plies`
+/// moves deep gets a move that is optimal through round `max_rounds`
+pub fn build_book_from_solver(target: u32, max_rounds: u32, plies: u32) -> OpeningBook {
+    let mut book = OpeningBook::new(target);
+    for position in opening_positions(target, plies) {
+        let report = solve_position(
+            &position,
+            SolverConfig::new(target, max_rounds, SearchMode::Exhaustive),
+        );
+        let optimal = report
+            .first_moves
+            .iter()
+            .find(|(_, value)| report.value.is_some() && *value == report.value);
+        if let Some((cell, _)) = optimal {
+            book.insert(&position, *cell);
+        }
+    }
+    book
+}
+
+/// Build a book from self-play: the bot's search plays `games` games, picking
+/// at random between its three best moves so different openings are visited,
+/// and its best move in each position of the first `plies` moves is recorded
+pub fn build_book_from_self_play(
+    target: u32,
+    plies: u32,
+    games: u32,
+    depth: u32,
+    node_limit: u64,
+    weights: &EvalWeights,
+) -> OpeningBook {
+    let mut book = OpeningBook::new(target);
+    let mut table = TranspositionTable::default();
+    let mut seed: u64 = 0x5EED;
+
+    for _ in 0..games {
+        let mut position = Position::new();
+        for _ in 0..plies {
+            if position.x_score() >= target || position.o_score() >= target {
+                break;
+            }
+            let ranked = rank_moves(&position, target, depth, node_limit, weights, &mut table);
+            book.insert(&position, ranked[0].0);
+
+            let candidates = ranked.len().min(3);
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            position = position.play(ranked[(seed >> 33) as usize % candidates].0);
+        }
+    }
+    book
+}
+
+/// Unit tests for the opening book
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Symmetry;
+
+    /// A book survives being written out and read back
+    #[test]
+    fn test_round_trip() {
+        let book = build_book_from_solver(1, 0, 2);
+        assert!(!book.is_empty());
+        assert_eq!(OpeningBook::from_text(&book.to_text()).unwrap(), book);
+    }
+
+    /// Books from another format version are rejected
+    #[test]
+    fn test_version_mismatch() {
+        let text = OpeningBook::new(3)
+            .to_text()
+            .replace("version 1", "version 99");
+        assert!(OpeningBook::from_text(&text).is_err());
+    }
+
+    /// A move stored for one position is found for its symmetric images
+    #[test]
+    fn test_symmetric_lookup() {
+        let mut book = OpeningBook::new(3);
+        let position = Position::new().play((0, 0));
+        book.insert(&position, (0, 1));
+
+        let image = Symmetry::Rotate90.transform(&position);
+        assert_eq!(book.get(&image), Some(Symmetry::Rotate90.apply((0, 1), 0)));
+        assert_eq!(book.get(&Position::new().play((1, 1))), None);
+    }
+}
@@ -0,0 +1,372 @@
+use bevy::prelude::*;
+use std::time::Duration;
+
+use crate::theme::theme::UiTheme;
+use crate::{time, GameState, MoveLog, PlayerTag, PlayerTurn, RoundState, TimedMode};
+
+/// Time under which a player's clock is shown as running low
+const LOW_TIME: Duration = Duration::from_secs(10);
+
+/// Colour of a clock that is running low
+const LOW_TIME_COLOR: Color = Color::RED;
+
+/// Time control of the per-player clocks, in seconds. Chosen on the timed
+/// game menu, where it also marks the preset buttons.
+#[derive(Resource, Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeControl {
+    #[default]
+    Off,
+    SuddenDeath {
+        base: u32,
+    },
+    /// `increment` is added to the clock after every move
+    Fischer {
+        base: u32,
+        increment: u32,
+    },
+    /// Every move waits out `delay` before the clock runs
+    Delay {
+        base: u32,
+        delay: u32,
+    },
+    /// `periods` of `period` seconds are played after the base time
+    ByoYomi {
+        base: u32,
+        period: u32,
+        periods: u32,
+    },
+}
+
+/// Time controls offered on the timed game menu, with their button labels
+pub const TIME_CONTROL_PRESETS: [(TimeControl, &str); 5] = [
+    (TimeControl::Off, "Off"),
+    (TimeControl::SuddenDeath { base: 180 }, "3 min"),
+    (
+        TimeControl::Fischer {
+            base: 180,
+            increment: 2,
+        },
+        "3 + 2s",
+    ),
+    (
+        TimeControl::Delay {
+            base: 300,
+            delay: 5,
+        },
+        "5 delay 5s",
+    ),
+    (
+        TimeControl::ByoYomi {
+            base: 300,
+            period: 30,
+            periods: 3,
+        },
+        "5 + 3x30s",
+    ),
+];
+
+/// Clock of one player, only running on their turn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerClock {
+    control: TimeControl,
+    remaining: Duration,   // Base time left
+    delay_left: Duration,  // Delay left this turn
+    period_left: Duration, // Time left in the current byo-yomi period
+    periods: u32,          // Byo-yomi periods left, including the current one
+}
+
+impl PlayerClock {
+    /// Full clock for `control`
+    pub fn new(control: TimeControl) -> Self {
+        let (base, delay, periods) = match control {
+            TimeControl::Off => (0, 0, 0),
+            TimeControl::SuddenDeath { base } | TimeControl::Fischer { base, .. } => (base, 0, 0),
+            TimeControl::Delay { base, delay } => (base, delay, 0),
+            TimeControl::ByoYomi { base, periods, .. } => (base, 0, periods),
+        };
+        let mut clock = PlayerClock {
+            control,
+            remaining: Duration::from_secs(base.into()),
+            delay_left: Duration::from_secs(delay.into()),
+            period_left: Duration::ZERO,
+            periods,
+        };
+        clock.period_left = clock.period();
+        clock
+    }
+
+    /// Length of a byo-yomi period
+    fn period(&self) -> Duration {
+        match self.control {
+            TimeControl::ByoYomi { period, .. } => Duration::from_secs(period.into()),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Run the clock for `elapsed` of the player's turn. Returns true once the flag has fallen.
+    pub fn tick(&mut self, mut elapsed: Duration) -> bool {
+        for left in [&mut self.delay_left, &mut self.remaining] {
+            let used = elapsed.min(*left);
+            *left -= used;
+            elapsed -= used;
+        }
+        while self.periods > 0 && !elapsed.is_zero() {
+            let used = elapsed.min(self.period_left);
+            self.period_left -= used;
+            elapsed -= used;
+            // A period that runs out is lost, the next one starts full
+            if self.period_left.is_zero() {
+                self.periods -= 1;
+                self.period_left = self.period();
+            }
+        }
+        self.flagged()
+    }
+
+    /// Top the clock up once the player has moved
+    pub fn end_turn(&mut self) {
+        match self.control {
+            TimeControl::Fischer { increment, .. } => {
+                self.remaining += Duration::from_secs(increment.into())
+            }
+            TimeControl::Delay { delay, .. } => self.delay_left = Duration::from_secs(delay.into()),
+            TimeControl::ByoYomi { .. } => self.period_left = self.period(),
+            TimeControl::Off | TimeControl::SuddenDeath { .. } => (),
+        }
+    }
+
+    /// Whether the player has run out of time
+    pub fn flagged(&self) -> bool {
+        self.remaining.is_zero() && self.periods == 0
+    }
+
+    /// Whether the player is playing in byo-yomi periods
+    fn in_byo_yomi(&self) -> bool {
+        self.remaining.is_zero() && self.periods > 0
+    }
+
+    /// Time shown on the clock
+    pub fn shown(&self) -> Duration {
+        if self.in_byo_yomi() {
+            self.period_left
+        } else {
+            self.remaining
+        }
+    }
+
+    /// Whether the clock is running low
+    pub fn is_low(&self) -> bool {
+        self.shown() < LOW_TIME
+    }
+
+    /// Text shown for the clock, with the periods left in byo-yomi
+    pub fn label(&self) -> String {
+        if self.in_byo_yomi() {
+            format!("{} ({})", time(self.shown()), self.periods)
+        } else {
+            time(self.shown())
+        }
+    }
+}
+
+/// Resource holding the clocks of both players, when the game has them
+#[derive(Resource, Default)]
+pub struct PlayerClocks {
+    clocks: Option<[PlayerClock; 2]>, // X then O
+    moves: usize,                     // Moves whose turn has been ended on the clocks
+}
+
+impl PlayerClocks {
+    /// Full clocks for both players, none when `control` is Off
+    pub fn new(control: TimeControl) -> Self {
+        PlayerClocks {
+            clocks: (control != TimeControl::Off)
+                .then(|| [PlayerClock::new(control), PlayerClock::new(control)]),
+            moves: 0,
+        }
+    }
+
+    /// Whether the game has per-player clocks
+    pub fn is_running(&self) -> bool {
+        self.clocks.is_some()
+    }
+
+    /// Clock of `player`
+    pub fn clock_mut(&mut self, player: &PlayerTurn) -> Option<&mut PlayerClock> {
+        let clocks = self.clocks.as_mut()?;
+        Some(match player {
+            PlayerTurn::X => &mut clocks[0],
+            PlayerTurn::O => &mut clocks[1],
+        })
+    }
+
+    /// A line of text for each clock, and whether it is running low
+    pub fn lines(&self) -> Vec<(String, bool)> {
+        let Some([x, o]) = &self.clocks else {
+            return Vec::new();
+        };
+        vec![
+            (format!("X {}", x.label()), x.is_low()),
+            (format!("\nO {}", o.label()), o.is_low()),
+        ]
+    }
+}
+
+/// Tag component for the clocks shown under the scores
+#[derive(Component)]
+pub struct PlayerClockText;
+
+/// Struct for the per-player clocks
+pub struct PlayerClockPlugin;
+
+/// Plugin that runs the clock of the player on turn and ends the game when a flag falls
+impl Plugin for PlayerClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeControl>()
+            .init_resource::<PlayerClocks>()
+            .add_systems(
+                Update,
+                (end_player_turns, run_player_clock)
+                    .chain()
+                    .run_if(in_state(GameState::GameOngoing)),
+            )
+            .add_systems(Update, update_player_clock_text.after(run_player_clock));
+    }
+}
+
+/// System that sets up the players' clocks when a game is started or restarted.
+/// Only timed games have them.
+pub fn reset_player_clocks(
+    mut clocks: ResMut<PlayerClocks>,
+    control: Res<TimeControl>,
+    timed: Res<TimedMode>,
+) {
+    *clocks = if timed.0 {
+        PlayerClocks::new(*control)
+    } else {
+        PlayerClocks::default()
+    };
+}
+
+/// System that tops up the clock of every player who has moved
+fn end_player_turns(mut clocks: ResMut<PlayerClocks>, move_log: Res<MoveLog>) {
+    if !clocks.is_running() {
+        return;
+    }
+    let first = clocks.moves.min(move_log.moves.len());
+    for logged in move_log.moves[first..].iter() {
+        if let Some(clock) = clocks.clock_mut(&logged.player) {
+            clock.end_turn();
+        }
+    }
+    clocks.moves = move_log.moves.len();
+}
+
+/// System that runs the clock of the player on turn, who loses when it runs out
+fn run_player_clock(
+    mut clocks: ResMut<PlayerClocks>,
+    player_turn: Res<State<PlayerTurn>>,
+    os_time: Res<Time>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_round_state: ResMut<NextState<RoundState>>,
+) {
+    let Some(clock) = clocks.clock_mut(player_turn.get()) else {
+        return;
+    };
+    if !clock.tick(os_time.delta()) {
+        return;
+    }
+    next_round_state.set(RoundState::NotUpdating);
+    next_game_state.set(GameState::Won(match player_turn.get() {
+        PlayerTurn::X => PlayerTag::O,
+        PlayerTurn::O => PlayerTag::X,
+    }));
+}
+
+/// System that shows both clocks, in red when they run low
+fn update_player_clock_text(
+    clocks: Res<PlayerClocks>,
+    theme: Res<UiTheme>,
+    mut query: Query<&mut Text, With<PlayerClockText>>,
+) {
+    let lines = clocks.lines();
+    for mut text in &mut query {
+        let Some(style) = text.sections.first().map(|section| section.style.clone()) else {
+            continue;
+        };
+        let unchanged = text.sections.len() == lines.len()
+            && text
+                .sections
+                .iter()
+                .zip(&lines)
+                .all(|(section, (line, low))| {
+                    section.value == *line && (section.style.color == LOW_TIME_COLOR) == *low
+                });
+        if unchanged {
+            continue;
+        }
+        text.sections = lines
+            .iter()
+            .map(|(line, low)| {
+                TextSection::new(
+                    line.clone(),
+                    TextStyle {
+                        color: if *low {
+                            LOW_TIME_COLOR
+                        } else {
+                            theme.button_text
+                        },
+                        ..style.clone()
+                    },
+                )
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Increments and delays add time, sudden death does not
+    #[test]
+    fn test_time_controls() {
+        let mut clock = PlayerClock::new(TimeControl::SuddenDeath { base: 5 });
+        assert!(!clock.tick(Duration::from_secs(4)));
+        clock.end_turn();
+        assert!(clock.is_low());
+        assert!(clock.tick(Duration::from_secs(1)));
+
+        let mut clock = PlayerClock::new(TimeControl::Fischer {
+            base: 5,
+            increment: 2,
+        });
+        clock.tick(Duration::from_secs(4));
+        clock.end_turn();
+        assert_eq!(clock.shown(), Duration::from_secs(3));
+
+        let mut clock = PlayerClock::new(TimeControl::Delay { base: 5, delay: 3 });
+        clock.tick(Duration::from_secs(4));
+        assert_eq!(clock.shown(), Duration::from_secs(4));
+        clock.end_turn();
+        assert!(!clock.tick(Duration::from_secs(6)));
+        assert!(clock.tick(Duration::from_secs(1)));
+    }
+
+    /// Byo-yomi periods restart after each move and are lost when they run out
+    #[test]
+    fn test_byo_yomi() {
+        let mut clock = PlayerClock::new(TimeControl::ByoYomi {
+            base: 10,
+            period: 5,
+            periods: 2,
+        });
+        clock.tick(Duration::from_secs(13));
+        assert_eq!(clock.label(), "00:00:02 (2)");
+        clock.end_turn();
+        assert_eq!(clock.shown(), Duration::from_secs(5));
+        assert!(!clock.tick(Duration::from_secs(6)));
+        assert_eq!(clock.label(), "00:00:04 (1)");
+        assert!(clock.tick(Duration::from_secs(4)));
+    }
+}
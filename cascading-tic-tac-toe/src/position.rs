@@ -0,0 +1,415 @@
+use crate::{
+    generate_cell_combinations, has_two_tuples, BoardGrid, CellState, PlayerTag, PlayerTurn,
+    RoundInit,
+};
+
+/// Number of playable cells after n rounds: sub-board 0 has 9 cells and
+/// every later sub-board adds 7, since it shares 2 cells with the one before
+pub fn cell_count(n: u32) -> usize {
+    9 + 7 * n as usize
+}
+
+/// Compact id of a playable board cell, None for the invalid spacer cells.
+/// Ids are numbered by the sub-board that first adds the cell, so they do not
+/// change as the board grows.
+pub fn cell_id(cell: (u32, u32)) -> Option<usize> {
+    let (row, col) = cell;
+    let base = |k: u32| 9 + 7 * (k as usize - 1);
+    if row < 3 && col < 3 {
+        return Some((3 * row + col) as usize);
+    }
+    if row % 2 == 1 {
+        // middle row of sub-board k
+        let k = (row - 1) / 2;
+        if k >= 1 && (k..=k + 2).contains(&col) {
+            return Some(base(k) + 1 + (col - k) as usize);
+        }
+    } else {
+        // top-right cell of sub-board k, the other two are shared with sub-board k - 1
+        let k = row / 2;
+        if k >= 1 && col == k + 2 {
+            return Some(base(k));
+        }
+        // bottom row of sub-board k
+        if row >= 4 {
+            let k = row / 2 - 1;
+            if (k..=k + 2).contains(&col) {
+                return Some(base(k) + 4 + (col - k) as usize);
+            }
+        }
+    }
+    None
+}
+
+/// Board coordinate of a compact cell id, the inverse of cell_id
+pub fn cell_coord(id: usize) -> (u32, u32) {
+    if id < 9 {
+        return ((id / 3) as u32, (id % 3) as u32);
+    }
+    let k = ((id - 9) / 7 + 1) as u32;
+    match ((id - 9) % 7) as u32 {
+        0 => (2 * k, k + 2),
+        local @ 1..=3 => (2 * k + 1, k + local - 1),
+        local => (2 * k + 2, k + local - 4),
+    }
+}
+
+/// Sub-board that first adds a playable cell and the cell's place in it, row
+/// by row. Sub-board k covers rows 2k to 2k + 2 and columns k to k + 2.
+pub fn sub_board_cell(cell: (u32, u32)) -> Option<(u32, usize)> {
+    cell_id(cell).map(|id| match id {
+        0..=8 => (0, id),
+        // The first two cells of later sub-boards belong to the one before
+        _ => (((id - 9) / 7 + 1) as u32, 2 + (id - 9) % 7),
+    })
+}
+
+/// Compact, hashable snapshot of a game: the board with its round count, used
+/// combinations, scores and the player to move, together with
+/// a Zobrist hash that is updated incrementally as moves are played
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    board: BoardGrid,
+    x_score: u32,
+    o_score: u32,
+    game_combinations: Vec<[(u32, u32); 3]>,
+    turn: PlayerTurn,
+    hash: u64,
+}
+
+impl Position {
+    /// The empty board at the start of a game, X moves first
+    pub fn new() -> Self {
+        Position::from_parts(Vec::new(), 0, 0, 0, Vec::new(), PlayerTurn::X)
+    }
+
+    /// Snapshot of the board being played
+    pub fn from_board(grid: &BoardGrid, round_init: &RoundInit, turn: &PlayerTurn) -> Self {
+        let filled = grid
+            .filled_cells()
+            .map(|(cell, tag)| (cell, tag.clone()))
+            .collect();
+        Position::from_parts(
+            filled,
+            round_init.round_count,
+            round_init.x_score,
+            round_init.o_score,
+            round_init.game_combinations.clone(),
+            turn.clone(),
+        )
+    }
+
+    /// Build a position from its parts and compute its hash from scratch
+    pub fn from_parts(
+        filled: Vec<((u32, u32), PlayerTag)>,
+        round_count: u32,
+        x_score: u32,
+        o_score: u32,
+        game_combinations: Vec<[(u32, u32); 3]>,
+        turn: PlayerTurn,
+    ) -> Self {
+        let mut board = BoardGrid::new(round_count);
+        for (cell, tag) in filled {
+            assert!(
+                board.get(cell) != CellState::Invalid,
+                "Position::from_parts: cell is not on the board"
+            );
+            board.fill(cell, tag);
+        }
+        let mut position = Position {
+            board,
+            x_score,
+            o_score,
+            game_combinations,
+            turn,
+            hash: 0,
+        };
+        position.hash = position.full_hash();
+        position
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn round_count(&self) -> u32 {
+        self.board.round_count()
+    }
+
+    pub fn x_score(&self) -> u32 {
+        self.x_score
+    }
+
+    pub fn o_score(&self) -> u32 {
+        self.o_score
+    }
+
+    pub fn turn(&self) -> &PlayerTurn {
+        &self.turn
+    }
+
+    pub fn game_combinations(&self) -> &Vec<[(u32, u32); 3]> {
+        &self.game_combinations
+    }
+
+    /// The board the position is played on
+    pub fn board(&self) -> &BoardGrid {
+        &self.board
+    }
+
+    /// Contents of a cell, None if it is empty or not on the board
+    pub fn cell(&self, cell: (u32, u32)) -> Option<&PlayerTag> {
+        self.board.mark(cell)
+    }
+
+    /// Every filled cell with its owner
+    pub fn filled_cells(&self) -> impl Iterator<Item = ((u32, u32), &PlayerTag)> {
+        self.board.filled_cells()
+    }
+
+    /// Coordinates of every cell that can still be played
+    pub fn moves(&self) -> Vec<(u32, u32)> {
+        self.board.valid_cells().collect()
+    }
+
+    /// Place a mark for the player to move and score the board the same way
+    /// is_round_over does: X's combinations first, then O's, and a draw when
+    /// the board fills up without a score. Each score or draw adds a sub-board.
+    pub fn play(&self, cell: (u32, u32)) -> Position {
+        let id = cell_id(cell).expect("Position::play: cell is not on the board");
+        assert!(
+            self.board.get(cell) == CellState::Valid,
+            "Position::play: cell is already filled"
+        );
+
+        let mut next = self.clone();
+        let (tag, turn) = match self.turn {
+            PlayerTurn::X => (PlayerTag::X, PlayerTurn::O),
+            PlayerTurn::O => (PlayerTag::O, PlayerTurn::X),
+        };
+        next.board.fill(cell, tag.clone());
+        next.hash ^= cell_key(id, &tag);
+        next.turn = turn;
+        next.hash ^= zobrist(TURN, 0);
+
+        // Only combinations through the new mark can have been completed
+        let mut combinations = Vec::new();
+        generate_cell_combinations(cell, self.round_count(), &mut combinations);
+
+        let mut scored = 0;
+        for combination in combinations {
+            if next.game_combinations.contains(&combination)
+                || has_two_tuples(&mut next.game_combinations, &combination)
+                || !combination
+                    .iter()
+                    .all(|&cell| next.cell(cell) == Some(&tag))
+            {
+                continue;
+            }
+            next.hash ^= combination_key(&combination);
+            next.game_combinations.push(combination);
+            scored += 1;
+        }
+
+        if scored > 0 {
+            let (kind, score) = match tag {
+                PlayerTag::X => (X_SCORE, &mut next.x_score),
+                PlayerTag::O => (O_SCORE, &mut next.o_score),
+            };
+            next.hash ^= zobrist(kind, *score as u64) ^ zobrist(kind, (*score + scored) as u64);
+            *score += scored;
+            next.grow(scored);
+        } else if next.board.is_full() {
+            next.grow(1);
+        }
+        next
+    }
+
+    /// Add sub-boards at the end of a round
+    fn grow(&mut self, rounds: u32) {
+        let round_count = self.round_count() + rounds;
+        self.hash ^= zobrist(ROUND, self.round_count() as u64) ^ zobrist(ROUND, round_count as u64);
+        self.board.grow_to(round_count);
+    }
+
+    /// Position with every cell and used combination mapped through a board
+    /// symmetry, hashed from scratch
+    pub fn transformed(&self, symmetry: impl Fn((u32, u32)) -> (u32, u32)) -> Position {
+        let mut board = BoardGrid::new(self.round_count());
+        for (cell, tag) in self.filled_cells() {
+            let image = symmetry(cell);
+            assert!(
+                board.get(image) == CellState::Valid,
+                "Position::transformed: not a board symmetry"
+            );
+            board.fill(image, tag.clone());
+        }
+        // is_winner treats used combinations as sets of cells, so they are kept sorted
+        let game_combinations = self
+            .game_combinations
+            .iter()
+            .map(|combination| {
+                let mut mapped = combination.map(&symmetry);
+                mapped.sort();
+                mapped
+            })
+            .collect();
+        let mut position = Position {
+            board,
+            game_combinations,
+            turn: self.turn.clone(),
+            ..*self
+        };
+        position.hash = position.full_hash();
+        position
+    }
+
+    /// Zobrist hash of the position computed from every feature
+    fn full_hash(&self) -> u64 {
+        let mut hash = zobrist(X_SCORE, self.x_score as u64)
+            ^ zobrist(O_SCORE, self.o_score as u64)
+            ^ zobrist(ROUND, self.round_count() as u64);
+        if self.turn == PlayerTurn::O {
+            hash ^= zobrist(TURN, 0);
+        }
+        for (cell, tag) in self.filled_cells() {
+            if let Some(id) = cell_id(cell) {
+                hash ^= cell_key(id, tag);
+            }
+        }
+        for combination in self.game_combinations.iter() {
+            hash ^= combination_key(combination);
+        }
+        hash
+    }
+}
+
+/// Default constructor for Position, the empty board
+impl Default for Position {
+    fn default() -> Self {
+        Position::new()
+    }
+}
+
+/// Hashed features of a position
+const CELL_X: u64 = 1;
+const CELL_O: u64 = 2;
+const COMBINATION: u64 = 3;
+const X_SCORE: u64 = 4;
+const O_SCORE: u64 = 5;
+const ROUND: u64 = 6;
+const TURN: u64 = 7;
+
+/// Zobrist key of a feature value. Keys are derived with the splitmix64
+/// finalizer instead of a random table, since the board has no fixed size.
+fn zobrist(feature: u64, value: u64) -> u64 {
+    let mut z = ((feature << 56) ^ value).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Zobrist key of a mark in a cell
+fn cell_key(id: usize, tag: &PlayerTag) -> u64 {
+    match tag {
+        PlayerTag::X => zobrist(CELL_X, id as u64),
+        PlayerTag::O => zobrist(CELL_O, id as u64),
+    }
+}
+
+/// Zobrist key of a used combination, independent of the order of its cells
+fn combination_key(combination: &[(u32, u32); 3]) -> u64 {
+    let mut ids = combination.map(|cell| cell_id(cell).unwrap_or(usize::MAX) as u64);
+    ids.sort();
+    ids.iter().fold(zobrist(COMBINATION, 0), |key, &id| {
+        zobrist(COMBINATION, key ^ id)
+    })
+}
+
+/// Unit tests for positions and hashing
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate_invalid_cells, generate_winning_combinations};
+    use test_case::test_case;
+
+    /// Cell ids cover exactly the cells the board leaves playable
+    #[test_case(0)]
+    #[test_case(1)]
+    #[test_case(5)]
+    fn test_cell_ids_match_board(n: u32) {
+        let mut invalid_cells = Vec::new();
+        generate_invalid_cells(n, &mut invalid_cells);
+        let mut ids = Vec::new();
+        for row in 0..2 * n + 3 {
+            for col in 0..n + 3 {
+                let id = cell_id((row, col));
+                assert_eq!(id.is_none(), invalid_cells.contains(&(row, col)));
+                if let Some(id) = id {
+                    assert_eq!(cell_coord(id), (row, col));
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort();
+        assert_eq!(ids, (0..cell_count(n)).collect::<Vec<_>>());
+    }
+
+    /// The incremental hash always equals the hash computed from scratch
+    #[test]
+    fn test_incremental_hash() {
+        let mut position = Position::new();
+        let mut hashes = vec![position.hash()];
+        // Fill the board in a fixed scrambled order through several rounds
+        for step in 0..60 {
+            let moves = position.moves();
+            position = position.play(moves[(step * 7) % moves.len()]);
+            assert_eq!(position.hash(), position.full_hash());
+            hashes.push(position.hash());
+        }
+        assert!(position.round_count() > 0);
+        hashes.sort();
+        hashes.dedup();
+        assert_eq!(hashes.len(), 61);
+    }
+
+    /// Only combinations through the new mark are checked, which matches
+    /// scanning every combination on the board
+    #[test]
+    fn test_local_combinations() {
+        let n = 4;
+        let mut all = Vec::new();
+        generate_winning_combinations(n, &mut all);
+        for id in 0..cell_count(n) {
+            let cell = cell_coord(id);
+            let mut local = Vec::new();
+            generate_cell_combinations(cell, n, &mut local);
+            let through: Vec<_> = all.iter().filter(|c| c.contains(&cell)).cloned().collect();
+            assert_eq!(local, through);
+        }
+    }
+
+    /// A completed line ends the round and cascades the board
+    #[test]
+    fn test_line_grows_board() {
+        let position = [(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)]
+            .iter()
+            .fold(Position::new(), |position, &cell| position.play(cell));
+        assert_eq!(position.x_score(), 1);
+        assert_eq!(position.round_count(), 1);
+        assert_eq!(
+            position.game_combinations(),
+            &vec![[(0, 0), (0, 1), (0, 2)]]
+        );
+        assert_eq!(position.moves().len(), cell_count(1) - 5);
+    }
+
+    /// The same cells reached in a different move order hash the same
+    #[test]
+    fn test_transposition() {
+        let a = Position::new().play((0, 0)).play((1, 1)).play((2, 2));
+        let b = Position::new().play((2, 2)).play((1, 1)).play((0, 0));
+        assert_eq!(a, b);
+        assert_eq!(a.hash(), b.hash());
+    }
+}
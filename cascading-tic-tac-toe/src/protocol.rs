@@ -0,0 +1,876 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{PlayerTag, PlayerTurn};
+
+/// Version of the online protocol, both sides must agree on it
+pub const PROTOCOL_VERSION: u32 = 11;
+
+/// Port used when an address does not give one
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Authoritative summary of a game kept by the server, sent after every change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameSnapshot {
+    pub moves: u32, // Moves played since the game started
+    pub round_count: u32,
+    pub x_score: u32,
+    pub o_score: u32,
+    pub turn: PlayerTurn,
+}
+
+/// Every move of a game, sent to a player who reconnects so they can rebuild it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub moves: Vec<(u32, u32)>,
+    pub elapsed: u32, // Seconds since the game started
+}
+
+/// The game clock of whoever keeps time, the server or the host, when a
+/// move was played. It stamps every move so both players show the same clock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockReading {
+    pub moves: u32, // Moves played since the game started, including this one
+    pub round_count: u32,
+    pub elapsed: u32, // Milliseconds the game had been played for
+}
+
+/// Rules a game is played by. Classic is the only rule set so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuleVariant {
+    #[default]
+    Classic,
+}
+
+/// Formatting constructor for RuleVariant, the name used in messages
+impl fmt::Display for RuleVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleVariant::Classic => write!(f, "classic"),
+        }
+    }
+}
+
+/// Settings of a game room, chosen by the player who creates it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomSettings {
+    pub target: u32, // Score that wins the game
+    pub timer: u32,  // Seconds on the game clock
+    pub variant: RuleVariant,
+}
+
+/// Default constructor for RoomSettings, also used for quick matches
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            target: 3,
+            timer: 10 * 60,
+            variant: RuleVariant::Classic,
+        }
+    }
+}
+
+/// Longest player name on a game server
+pub const MAX_NAME_LENGTH: usize = 20;
+
+/// Whether a player name can be used in messages: short, without spaces,
+/// and not "-" which stands for no player
+pub fn valid_player_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "-"
+        && name.chars().count() <= MAX_NAME_LENGTH
+        && name.chars().all(|character| character.is_ascii_graphic())
+}
+
+/// A correspondence game as listed to a player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorrespondenceInfo {
+    pub id: u32,
+    pub x: String,
+    pub o: Option<String>, // None while the challenge is open
+    pub turn: PlayerTurn,
+    pub winner: Option<PlayerTag>,
+    pub due: Option<u64>, // Unix time the next move is due by
+}
+
+/// A player's account on a game server, as shown on the leaderboard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerRating {
+    pub name: String,
+    pub rating: i32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+/// A rated game that has been played to the end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchResult {
+    pub time: u64, // Unix time the game ended
+    pub x: String,
+    pub o: String,
+    pub winner: Option<PlayerTag>, // None for a draw
+    pub change: i32, // Rating points the winner took from the loser, or X from O in a draw
+}
+
+/// Longest chat message, longer ones are cut
+pub const MAX_CHAT_LENGTH: usize = 120;
+
+/// Quick messages sent with a single button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emote {
+    GoodMove,
+    Oops,
+    GoodGame,
+}
+
+impl Emote {
+    /// Every emote, in the order of their buttons
+    pub const ALL: [Emote; 3] = [Emote::GoodMove, Emote::Oops, Emote::GoodGame];
+
+    /// Text shown for the emote
+    pub fn label(&self) -> &'static str {
+        match self {
+            Emote::GoodMove => "Good move!",
+            Emote::Oops => "Oops",
+            Emote::GoodGame => "GG",
+        }
+    }
+}
+
+/// Formatting constructor for Emote, the name used in messages
+impl fmt::Display for Emote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Emote::GoodMove => write!(f, "good_move"),
+            Emote::Oops => write!(f, "oops"),
+            Emote::GoodGame => write!(f, "gg"),
+        }
+    }
+}
+
+/// Chat text as it is sent: printable characters on one line, at most
+/// MAX_CHAT_LENGTH of them. None when nothing is left.
+pub fn clean_chat(text: &str) -> Option<String> {
+    let text: String = text
+        .chars()
+        .filter(|character| !character.is_control())
+        .take(MAX_CHAT_LENGTH)
+        .collect();
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// An open room or a game being played, as listed in the lobby
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomInfo {
+    pub id: u32,
+    pub settings: RoomSettings,
+}
+
+/// A message exchanged between two online players, or a player and the
+/// server. Each message is sent as one line of text, a keyword followed by
+/// its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Hello { version: u32 }, // First message from the joining player
+    // Host accepted the join and the game starts, or the server's lobby is entered
+    Welcome { version: u32 },
+    Reject { reason: String }, // Host refused the join, the connection closes
+    Move { cell: (u32, u32) },
+    Invalid { reason: String }, // Server only, the last message was refused
+    State(GameSnapshot),        // Server only
+    Restart,
+    Resign,
+    // Lobby messages, sent to and from the server only
+    List,                 // Ask for the open rooms
+    Rooms(Vec<RoomInfo>), // Answer to List
+    Create(RoomSettings), // Open a room and wait in it
+    Join { room: u32 },
+    Leave,                           // Close the room being waited in
+    QuickMatch,                      // Play the next player who asks for a quick match
+    Room { room: u32 },              // Waiting in this room for an opponent
+    Start(PlayerTurn, RoomSettings), // The game starts, played as the given player
+    // Reconnection messages, sent to and from the server only
+    Session { token: u64 }, // Secret that takes the seat back after a lost connection
+    Resume { token: u64 },  // Take a seat back, sent after HELLO
+    Sync(GameRecord),       // The game so far, after resuming
+    Away { seconds: u32 },  // The opponent lost their connection and has this long to return
+    Back,                   // The opponent has returned
+    // Spectator messages
+    Watch { version: u32, game: u32 }, // Watch a game, first message or from the lobby
+    Playing(Vec<RoomInfo>),            // Games that can be watched, sent after ROOMS
+    Spectate(RoomSettings),            // Watching a game with these settings
+    Spectators { count: u32 },         // How many are watching the game
+    Resigned(PlayerTurn),              // Sent to spectators when a player resigns or forfeits
+    // Clock messages, the server or the host keeps time
+    Clock(ClockReading),       // Sent after every move
+    TimeUp(Option<PlayerTag>), // The clock ran out and the game is won by the given player, or drawn
+    Ping { stamp: u64 },       // Sent by the other players to measure the delay, answered with PONG
+    Pong { stamp: u64 },       // The stamp of the PING being answered
+    // Chat between the two players
+    Chat { text: String },
+    Emote(Emote),
+    // Correspondence games, sent to and from the server only
+    Games { player: String }, // List the player's games and open challenges
+    GameList(Vec<CorrespondenceInfo>), // Answer to GAMES, CHALLENGE and ACCEPT
+    Challenge { player: String, days: u32 }, // Open a challenge, 0 days for no deadline
+    Accept { player: String, game: u32 }, // Take up an open challenge
+    Open { player: String, game: u32 }, // Play a game, answered like RESUME
+    // Accounts and ratings, sent to and from the server only
+    Name { player: String }, // Play lobby games as this player, creating the account
+    Leaders { player: String }, // Ask for the leaderboard and the player's history
+    Leaderboard(Vec<PlayerRating>), // Best players, then the one who asked if they have an account
+    History(Vec<MatchResult>), // Latest games of the player who asked, newest first
+}
+
+/// Error for a line that is not a valid message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    Empty,
+    UnknownMessage(String),
+    Malformed(String),
+}
+
+/// Formatting constructor for ProtocolError
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Empty => write!(f, "empty message"),
+            ProtocolError::UnknownMessage(line) => write!(f, "unknown message: {}", line),
+            ProtocolError::Malformed(line) => write!(f, "malformed message: {}", line),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl Message {
+    /// Text of the message, without the line ending
+    pub fn encode(&self) -> String {
+        match self {
+            Message::Hello { version } => format!("HELLO {}", version),
+            Message::Welcome { version } => format!("WELCOME {}", version),
+            Message::Reject { reason } => format!("REJECT {}", reason),
+            Message::Move { cell } => format!("MOVE {} {}", cell.0, cell.1),
+            Message::Invalid { reason } => format!("INVALID {}", reason),
+            Message::State(snapshot) => format!(
+                "STATE {} {} {} {} {:?}",
+                snapshot.moves,
+                snapshot.round_count,
+                snapshot.x_score,
+                snapshot.o_score,
+                snapshot.turn
+            ),
+            Message::Restart => "RESTART".to_string(),
+            Message::Resign => "RESIGN".to_string(),
+            Message::List => "LIST".to_string(),
+            Message::Rooms(rooms) => encode_rooms("ROOMS", rooms),
+            Message::Create(settings) => format!("CREATE {}", encode_settings(settings)),
+            Message::Join { room } => format!("JOIN {}", room),
+            Message::Leave => "LEAVE".to_string(),
+            Message::QuickMatch => "QUICK".to_string(),
+            Message::Room { room } => format!("ROOM {}", room),
+            Message::Start(player, settings) => {
+                format!("START {:?} {}", player, encode_settings(settings))
+            }
+            Message::Session { token } => format!("SESSION {:016x}", token),
+            Message::Resume { token } => format!("RESUME {:016x}", token),
+            Message::Sync(record) => {
+                let mut line = format!("SYNC {}", record.elapsed);
+                for cell in &record.moves {
+                    line.push_str(&format!(" {} {}", cell.0, cell.1));
+                }
+                line
+            }
+            Message::Away { seconds } => format!("AWAY {}", seconds),
+            Message::Back => "BACK".to_string(),
+            Message::Watch { version, game } => format!("WATCH {} {}", version, game),
+            Message::Playing(games) => encode_rooms("PLAYING", games),
+            Message::Spectate(settings) => format!("SPECTATE {}", encode_settings(settings)),
+            Message::Spectators { count } => format!("SPECTATORS {}", count),
+            Message::Resigned(player) => format!("RESIGNED {:?}", player),
+            Message::Clock(reading) => format!(
+                "CLOCK {} {} {}",
+                reading.moves, reading.round_count, reading.elapsed
+            ),
+            Message::TimeUp(winner) => match winner {
+                Some(winner) => format!("TIMEUP {}", winner),
+                None => "TIMEUP -".to_string(),
+            },
+            Message::Ping { stamp } => format!("PING {}", stamp),
+            Message::Pong { stamp } => format!("PONG {}", stamp),
+            Message::Chat { text } => format!("CHAT {}", text),
+            Message::Emote(emote) => format!("EMOTE {}", emote),
+            Message::Games { player } => format!("GAMES {}", player),
+            Message::GameList(games) => {
+                let mut line = "GAMELIST".to_string();
+                for game in games {
+                    line.push_str(&format!(
+                        " {} {} {} {:?} {} {}",
+                        game.id,
+                        game.x,
+                        game.o.as_deref().unwrap_or("-"),
+                        game.turn,
+                        game.winner
+                            .as_ref()
+                            .map_or("-".to_string(), |winner| winner.to_string()),
+                        game.due.unwrap_or(0)
+                    ));
+                }
+                line
+            }
+            Message::Challenge { player, days } => format!("CHALLENGE {} {}", player, days),
+            Message::Accept { player, game } => format!("ACCEPT {} {}", player, game),
+            Message::Open { player, game } => format!("OPEN {} {}", player, game),
+            Message::Name { player } => format!("NAME {}", player),
+            Message::Leaders { player } => format!("LEADERS {}", player),
+            Message::Leaderboard(players) => {
+                let mut line = "LEADERBOARD".to_string();
+                for player in players {
+                    line.push_str(&format!(
+                        " {} {} {} {} {}",
+                        player.name, player.rating, player.wins, player.draws, player.losses
+                    ));
+                }
+                line
+            }
+            Message::History(matches) => {
+                let mut line = "HISTORY".to_string();
+                for result in matches {
+                    line.push_str(&format!(
+                        " {} {} {} {} {}",
+                        result.time,
+                        result.x,
+                        result.o,
+                        result
+                            .winner
+                            .as_ref()
+                            .map_or("-".to_string(), |winner| winner.to_string()),
+                        result.change
+                    ));
+                }
+                line
+            }
+        }
+    }
+
+    /// Parse one line of text
+    pub fn decode(line: &str) -> Result<Message, ProtocolError> {
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let malformed = || ProtocolError::Malformed(line.to_string());
+        let number = |field: &str| field.parse::<u32>().map_err(|_| malformed());
+        let token = |field: &str| u64::from_str_radix(field, 16).map_err(|_| malformed());
+        let stamp = |field: &str| field.parse::<u64>().map_err(|_| malformed());
+        let player = |field: &str| match field {
+            "X" => Ok(PlayerTurn::X),
+            "O" => Ok(PlayerTurn::O),
+            _ => Err(malformed()),
+        };
+        let settings = |fields: &[&str]| decode_settings(fields).ok_or_else(malformed);
+        let name = |field: &str| {
+            if valid_player_name(field) {
+                Ok(field.to_string())
+            } else {
+                Err(malformed())
+            }
+        };
+        // Four fields per room: id, target, timer and variant
+        let rooms = |fields: &[&str]| {
+            let rooms = fields.chunks_exact(4);
+            if !rooms.remainder().is_empty() {
+                return Err(malformed());
+            }
+            rooms
+                .map(|room| {
+                    Ok(RoomInfo {
+                        id: number(room[0])?,
+                        settings: settings(&room[1..])?,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        match (keyword, fields.as_slice()) {
+            ("", _) => Err(ProtocolError::Empty),
+            ("HELLO", [version]) => Ok(Message::Hello {
+                version: number(version)?,
+            }),
+            ("WELCOME", [version]) => Ok(Message::Welcome {
+                version: number(version)?,
+            }),
+            ("REJECT", _) => Ok(Message::Reject {
+                reason: rest.to_string(),
+            }),
+            ("MOVE", [row, col]) => Ok(Message::Move {
+                cell: (number(row)?, number(col)?),
+            }),
+            ("INVALID", _) => Ok(Message::Invalid {
+                reason: rest.to_string(),
+            }),
+            ("STATE", [moves, round_count, x_score, o_score, turn]) => {
+                Ok(Message::State(GameSnapshot {
+                    moves: number(moves)?,
+                    round_count: number(round_count)?,
+                    x_score: number(x_score)?,
+                    o_score: number(o_score)?,
+                    turn: player(turn)?,
+                }))
+            }
+            ("RESTART", []) => Ok(Message::Restart),
+            ("RESIGN", []) => Ok(Message::Resign),
+            ("LIST", []) => Ok(Message::List),
+            ("ROOMS", fields) => Ok(Message::Rooms(rooms(fields)?)),
+            ("CREATE", fields) => Ok(Message::Create(settings(fields)?)),
+            ("JOIN", [room]) => Ok(Message::Join {
+                room: number(room)?,
+            }),
+            ("LEAVE", []) => Ok(Message::Leave),
+            ("QUICK", []) => Ok(Message::QuickMatch),
+            ("ROOM", [room]) => Ok(Message::Room {
+                room: number(room)?,
+            }),
+            ("START", [turn, fields @ ..]) => Ok(Message::Start(player(turn)?, settings(fields)?)),
+            ("SESSION", [field]) => Ok(Message::Session {
+                token: token(field)?,
+            }),
+            ("RESUME", [field]) => Ok(Message::Resume {
+                token: token(field)?,
+            }),
+            // The seconds, then two fields per move
+            ("SYNC", [elapsed, cells @ ..]) => {
+                let moves = cells.chunks_exact(2);
+                if !moves.remainder().is_empty() {
+                    return Err(malformed());
+                }
+                Ok(Message::Sync(GameRecord {
+                    moves: moves
+                        .map(|cell| Ok((number(cell[0])?, number(cell[1])?)))
+                        .collect::<Result<_, _>>()?,
+                    elapsed: number(elapsed)?,
+                }))
+            }
+            ("AWAY", [seconds]) => Ok(Message::Away {
+                seconds: number(seconds)?,
+            }),
+            ("BACK", []) => Ok(Message::Back),
+            ("WATCH", [version, game]) => Ok(Message::Watch {
+                version: number(version)?,
+                game: number(game)?,
+            }),
+            ("PLAYING", fields) => Ok(Message::Playing(rooms(fields)?)),
+            ("SPECTATE", fields) => Ok(Message::Spectate(settings(fields)?)),
+            ("SPECTATORS", [count]) => Ok(Message::Spectators {
+                count: number(count)?,
+            }),
+            ("RESIGNED", [turn]) => Ok(Message::Resigned(player(turn)?)),
+            ("CLOCK", [moves, round_count, elapsed]) => Ok(Message::Clock(ClockReading {
+                moves: number(moves)?,
+                round_count: number(round_count)?,
+                elapsed: number(elapsed)?,
+            })),
+            ("TIMEUP", ["X"]) => Ok(Message::TimeUp(Some(PlayerTag::X))),
+            ("TIMEUP", ["O"]) => Ok(Message::TimeUp(Some(PlayerTag::O))),
+            ("TIMEUP", ["-"]) => Ok(Message::TimeUp(None)),
+            ("PING", [field]) => Ok(Message::Ping {
+                stamp: stamp(field)?,
+            }),
+            ("PONG", [field]) => Ok(Message::Pong {
+                stamp: stamp(field)?,
+            }),
+            ("CHAT", _) => Ok(Message::Chat {
+                text: rest.to_string(),
+            }),
+            ("EMOTE", [emote]) => Emote::ALL
+                .into_iter()
+                .find(|known| known.to_string() == *emote)
+                .map(Message::Emote)
+                .ok_or_else(malformed),
+            ("GAMES", [player]) => Ok(Message::Games {
+                player: name(player)?,
+            }),
+            // Six fields per game: id, X, O or -, turn, winner or - and due time or 0
+            ("GAMELIST", _) => {
+                let games = fields.chunks_exact(6);
+                if !games.remainder().is_empty() {
+                    return Err(malformed());
+                }
+                games
+                    .map(|game| {
+                        Ok(CorrespondenceInfo {
+                            id: number(game[0])?,
+                            x: name(game[1])?,
+                            o: match game[2] {
+                                "-" => None,
+                                o => Some(name(o)?),
+                            },
+                            turn: player(game[3])?,
+                            winner: match game[4] {
+                                "-" => None,
+                                "X" => Some(PlayerTag::X),
+                                "O" => Some(PlayerTag::O),
+                                _ => return Err(malformed()),
+                            },
+                            due: match game[5].parse::<u64>().map_err(|_| malformed())? {
+                                0 => None,
+                                due => Some(due),
+                            },
+                        })
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(Message::GameList)
+            }
+            ("CHALLENGE", [player, days]) => Ok(Message::Challenge {
+                player: name(player)?,
+                days: number(days)?,
+            }),
+            ("ACCEPT", [player, game]) => Ok(Message::Accept {
+                player: name(player)?,
+                game: number(game)?,
+            }),
+            ("OPEN", [player, game]) => Ok(Message::Open {
+                player: name(player)?,
+                game: number(game)?,
+            }),
+            ("NAME", [player]) => Ok(Message::Name {
+                player: name(player)?,
+            }),
+            ("LEADERS", [player]) => Ok(Message::Leaders {
+                player: name(player)?,
+            }),
+            // Five fields per player: name, rating, wins, draws and losses
+            ("LEADERBOARD", _) => {
+                let players = fields.chunks_exact(5);
+                if !players.remainder().is_empty() {
+                    return Err(malformed());
+                }
+                players
+                    .map(|player| {
+                        Ok(PlayerRating {
+                            name: name(player[0])?,
+                            rating: player[1].parse().map_err(|_| malformed())?,
+                            wins: number(player[2])?,
+                            draws: number(player[3])?,
+                            losses: number(player[4])?,
+                        })
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(Message::Leaderboard)
+            }
+            // Five fields per game: time, X, O, winner and rating change
+            ("HISTORY", _) => {
+                let matches = fields.chunks_exact(5);
+                if !matches.remainder().is_empty() {
+                    return Err(malformed());
+                }
+                matches
+                    .map(|result| {
+                        Ok(MatchResult {
+                            time: result[0].parse().map_err(|_| malformed())?,
+                            x: name(result[1])?,
+                            o: name(result[2])?,
+                            winner: match result[3] {
+                                "-" => None,
+                                "X" => Some(PlayerTag::X),
+                                "O" => Some(PlayerTag::O),
+                                _ => return Err(malformed()),
+                            },
+                            change: result[4].parse().map_err(|_| malformed())?,
+                        })
+                    })
+                    .collect::<Result<_, _>>()
+                    .map(Message::History)
+            }
+            (
+                "HELLO" | "WELCOME" | "MOVE" | "STATE" | "RESTART" | "RESIGN" | "LIST" | "JOIN"
+                | "LEAVE" | "QUICK" | "ROOM" | "START" | "SESSION" | "RESUME" | "SYNC" | "AWAY"
+                | "BACK" | "WATCH" | "SPECTATORS" | "RESIGNED" | "EMOTE" | "GAMES" | "CHALLENGE"
+                | "ACCEPT" | "OPEN" | "NAME" | "LEADERS" | "CLOCK" | "TIMEUP" | "PING" | "PONG",
+                _,
+            ) => Err(malformed()),
+            _ => Err(ProtocolError::UnknownMessage(line.to_string())),
+        }
+    }
+}
+
+/// A list of rooms after its keyword
+fn encode_rooms(keyword: &str, rooms: &[RoomInfo]) -> String {
+    let mut line = keyword.to_string();
+    for room in rooms {
+        line.push_str(&format!(" {} {}", room.id, encode_settings(&room.settings)));
+    }
+    line
+}
+
+/// Fields of RoomSettings in a message
+pub(crate) fn encode_settings(settings: &RoomSettings) -> String {
+    format!(
+        "{} {} {}",
+        settings.target, settings.timer, settings.variant
+    )
+}
+
+/// RoomSettings from the fields written by encode_settings
+pub(crate) fn decode_settings(fields: &[&str]) -> Option<RoomSettings> {
+    match fields {
+        [target, timer, variant] => Some(RoomSettings {
+            target: target.parse().ok()?,
+            timer: timer.parse().ok()?,
+            variant: match *variant {
+                "classic" => RuleVariant::Classic,
+                _ => return None,
+            },
+        }),
+        _ => None,
+    }
+}
+
+/// A non-blocking connection to another player, polled once per frame
+pub struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>, // Bytes received after the last complete line
+    closed: bool,    // The other side has closed the connection
+}
+
+impl Connection {
+    /// Wrap a connected stream
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            buffer: Vec::new(),
+            closed: false,
+        })
+    }
+
+    /// Connect to `host:port`, or to `host` on DEFAULT_PORT
+    pub fn connect(address: &str, timeout: Duration) -> io::Result<Self> {
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        };
+        let resolved = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("unknown host {}", address))
+        })?;
+        Connection::new(TcpStream::connect_timeout(&resolved, timeout)?)
+    }
+
+    /// Send one message
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        let line = format!("{}\n", message.encode());
+        // Messages are short, so wait for the socket rather than queueing them
+        self.stream.set_nonblocking(false)?;
+        let result = self.stream.write_all(line.as_bytes());
+        self.stream.set_nonblocking(true)?;
+        result
+    }
+
+    /// Every complete message received since the last poll. Fails once the
+    /// other side has closed the connection and everything it sent before
+    /// has been returned, or when it sent something invalid.
+    pub fn poll(&mut self) -> io::Result<Vec<Message>> {
+        let mut chunk = [0; 1024];
+        while !self.closed {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => self.closed = true,
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        let mut messages = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            match Message::decode(&line) {
+                Ok(message) => messages.push(message),
+                Err(ProtocolError::Empty) => (),
+                Err(error) => return Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+            }
+        }
+        if self.closed && messages.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed",
+            ));
+        }
+        Ok(messages)
+    }
+}
+
+/// A connection being opened on a background thread, so the game keeps
+/// drawing frames while the other side is slow to answer
+pub struct PendingConnection(Option<JoinHandle<io::Result<Connection>>>);
+
+impl PendingConnection {
+    /// Connect to `address` and send it `greeting`, like Connection::connect
+    pub fn start(address: &str, timeout: Duration, greeting: Vec<Message>) -> Self {
+        let address = address.to_string();
+        PendingConnection(Some(thread::spawn(move || {
+            let mut connection = Connection::connect(&address, timeout)?;
+            for message in &greeting {
+                connection.send(message)?;
+            }
+            Ok(connection)
+        })))
+    }
+
+    /// The connection, or why it failed, once the attempt is over. Only
+    /// returned once, None after that.
+    pub fn poll(&mut self) -> Option<io::Result<Connection>> {
+        if !self.0.as_ref()?.is_finished() {
+            return None;
+        }
+        let handle = self.0.take()?;
+        Some(
+            handle
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("connecting failed"))),
+        )
+    }
+}
+
+/// Unit tests for the online protocol
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use test_case::test_case;
+
+    /// Every message survives being encoded and decoded
+    #[test_case(Message::Hello { version: PROTOCOL_VERSION })]
+    #[test_case(Message::Welcome { version: 7 })]
+    #[test_case(Message::Reject { reason: "version 2 is not supported".to_string() })]
+    #[test_case(Message::Move { cell: (12, 5) })]
+    #[test_case(Message::Invalid { reason: "cell (0,0) is taken".to_string() })]
+    #[test_case(Message::State(GameSnapshot { moves: 9, round_count: 1, x_score: 1, o_score: 0, turn: PlayerTurn::O }))]
+    #[test_case(Message::Restart)]
+    #[test_case(Message::Resign)]
+    #[test_case(Message::Rooms(Vec::new()))]
+    #[test_case(Message::Rooms(vec![
+        RoomInfo { id: 1, settings: RoomSettings::default() },
+        RoomInfo { id: 4, settings: RoomSettings { target: 5, timer: 120, variant: RuleVariant::Classic } },
+    ]))]
+    #[test_case(Message::Create(RoomSettings::default()))]
+    #[test_case(Message::Join { room: 4 })]
+    #[test_case(Message::QuickMatch)]
+    #[test_case(Message::Start(PlayerTurn::O, RoomSettings::default()))]
+    #[test_case(Message::Session { token: 0x00ab_cdef_0123_4567 })]
+    #[test_case(Message::Sync(GameRecord { moves: Vec::new(), elapsed: 0 }))]
+    #[test_case(Message::Sync(GameRecord { moves: vec![(0, 0), (4, 1)], elapsed: 75 }))]
+    #[test_case(Message::Away { seconds: 60 })]
+    #[test_case(Message::Watch { version: PROTOCOL_VERSION, game: 3 })]
+    #[test_case(Message::Playing(vec![RoomInfo { id: 2, settings: RoomSettings::default() }]))]
+    #[test_case(Message::Spectate(RoomSettings::default()))]
+    #[test_case(Message::Spectators { count: 2 })]
+    #[test_case(Message::Resigned(PlayerTurn::X))]
+    #[test_case(Message::Clock(ClockReading { moves: 12, round_count: 1, elapsed: 53_250 }))]
+    #[test_case(Message::TimeUp(Some(PlayerTag::O)))]
+    #[test_case(Message::TimeUp(None))]
+    #[test_case(Message::Ping { stamp: 4_000_000_001 })]
+    #[test_case(Message::Pong { stamp: 0 })]
+    #[test_case(Message::Chat { text: "nice one, again?".to_string() })]
+    #[test_case(Message::Emote(Emote::GoodGame))]
+    #[test_case(Message::Games { player: "alice".to_string() })]
+    #[test_case(Message::GameList(vec![
+        CorrespondenceInfo { id: 1, x: "alice".to_string(), o: None, turn: PlayerTurn::X, winner: None, due: None },
+        CorrespondenceInfo { id: 2, x: "bob".to_string(), o: Some("alice".to_string()), turn: PlayerTurn::O, winner: Some(PlayerTag::X), due: Some(1_700_000_000) },
+    ]))]
+    #[test_case(Message::Challenge { player: "alice".to_string(), days: 3 })]
+    #[test_case(Message::Accept { player: "bob".to_string(), game: 1 })]
+    #[test_case(Message::Open { player: "bob".to_string(), game: 1 })]
+    #[test_case(Message::Name { player: "bob".to_string() })]
+    #[test_case(Message::Leaders { player: "bob".to_string() })]
+    #[test_case(Message::Leaderboard(vec![
+        PlayerRating { name: "alice".to_string(), rating: 1516, wins: 1, draws: 0, losses: 0 },
+        PlayerRating { name: "bob".to_string(), rating: 1484, wins: 0, draws: 1, losses: 1 },
+    ]))]
+    #[test_case(Message::History(vec![
+        MatchResult { time: 1_700_000_000, x: "alice".to_string(), o: "bob".to_string(), winner: Some(PlayerTag::X), change: 16 },
+        MatchResult { time: 1_700_000_100, x: "bob".to_string(), o: "alice".to_string(), winner: None, change: 0 },
+    ]))]
+    fn test_round_trip(message: Message) {
+        assert_eq!(Message::decode(&message.encode()), Ok(message));
+    }
+
+    /// Lines that are not messages are rejected
+    #[test_case("", ProtocolError::Empty)]
+    #[test_case("MOVE 1", ProtocolError::Malformed("MOVE 1".to_string()))]
+    #[test_case("MOVE a b", ProtocolError::Malformed("MOVE a b".to_string()))]
+    #[test_case("START Z 3 600 classic", ProtocolError::Malformed("START Z 3 600 classic".to_string()))]
+    #[test_case("CREATE 3 600 misere", ProtocolError::Malformed("CREATE 3 600 misere".to_string()))]
+    #[test_case("ROOMS 1 3 600", ProtocolError::Malformed("ROOMS 1 3 600".to_string()))]
+    #[test_case("EMOTE wave", ProtocolError::Malformed("EMOTE wave".to_string()))]
+    #[test_case("OPEN - 1", ProtocolError::Malformed("OPEN - 1".to_string()))]
+    #[test_case("TIMEUP Z", ProtocolError::Malformed("TIMEUP Z".to_string()))]
+    #[test_case("JUMP 1 2", ProtocolError::UnknownMessage("JUMP 1 2".to_string()))]
+    fn test_invalid_lines(line: &str, expected: ProtocolError) {
+        assert_eq!(Message::decode(line), Err(expected));
+    }
+
+    /// Chat is cut to one short line of printable text
+    #[test_case("  hi  ", Some("hi"))]
+    #[test_case("two\nlines", Some("twolines"))]
+    #[test_case(" \t ", None)]
+    fn test_clean_chat(text: &str, expected: Option<&str>) {
+        assert_eq!(clean_chat(text).as_deref(), expected);
+    }
+
+    /// Long chat is cut at MAX_CHAT_LENGTH characters
+    #[test]
+    fn test_chat_length() {
+        let long = "é".repeat(MAX_CHAT_LENGTH + 5);
+        assert_eq!(clean_chat(&long).unwrap().chars().count(), MAX_CHAT_LENGTH);
+    }
+
+    /// Two connections exchange messages over loopback
+    #[test]
+    fn test_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut joiner = Connection::connect(&address, Duration::from_secs(1)).unwrap();
+        let mut host = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        joiner
+            .send(&Message::Hello {
+                version: PROTOCOL_VERSION,
+            })
+            .unwrap();
+        joiner.send(&Message::Move { cell: (0, 2) }).unwrap();
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            received.extend(host.poll().unwrap());
+        }
+        assert_eq!(
+            received,
+            vec![
+                Message::Hello {
+                    version: PROTOCOL_VERSION
+                },
+                Message::Move { cell: (0, 2) }
+            ]
+        );
+
+        drop(joiner);
+        let closed = loop {
+            match host.poll() {
+                Ok(messages) => assert!(messages.is_empty()),
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(closed.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
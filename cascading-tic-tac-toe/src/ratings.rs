@@ -0,0 +1,307 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::{MatchResult, PlayerRating, PlayerTag};
+
+/// First line of a ratings file
+const RATINGS_HEADER: &str = "cascading-tic-tac-toe ratings";
+
+/// Version of the ratings file format
+const RATINGS_VERSION: u32 = 1;
+
+/// Rating of a new account
+pub const INITIAL_RATING: i32 = 1500;
+
+/// Most rating points a single game can move
+const K_FACTOR: f64 = 32.0;
+
+/// Elo rating points a player takes from their opponent by scoring `score`:
+/// 1 for a win, 0.5 for a draw
+pub fn rating_change(player: i32, opponent: i32, score: f64) -> i32 {
+    let expected = 1.0 / (1.0 + 10f64.powf(f64::from(opponent - player) / 400.0));
+    (K_FACTOR * (score - expected)).round() as i32
+}
+
+/// Accounts, ratings and finished games of a game server, kept in one file
+/// that is rewritten after every change
+#[derive(Default)]
+pub struct Ratings {
+    path: Option<PathBuf>, // None keeps everything in memory
+    players: Vec<PlayerRating>,
+    matches: Vec<MatchResult>, // Oldest first
+}
+
+impl Ratings {
+    /// Read the ratings file, starting empty if there is none yet
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut ratings = match fs::read_to_string(&path) {
+            Ok(text) => Ratings::from_text(&text)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ratings::default(),
+            Err(error) => return Err(error),
+        };
+        ratings.path = Some(path);
+        Ok(ratings)
+    }
+
+    /// Write the ratings file, through a temporary file so it is never seen half written
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, self.to_text())?;
+        fs::rename(temporary, path)
+    }
+
+    /// Write the accounts, then the games in the order they ended
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nversion {}\n", RATINGS_HEADER, RATINGS_VERSION);
+        for player in &self.players {
+            text.push_str(&format!(
+                "player {} {} {} {} {}\n",
+                player.name, player.rating, player.wins, player.draws, player.losses
+            ));
+        }
+        for result in &self.matches {
+            text.push_str(&format!(
+                "match {} {} {} {} {}\n",
+                result.time,
+                result.x,
+                result.o,
+                result
+                    .winner
+                    .as_ref()
+                    .map_or("-".to_string(), |winner| winner.to_string()),
+                result.change
+            ));
+        }
+        text
+    }
+
+    /// Parse a ratings file, rejecting other formats and versions
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut lines = text.lines();
+
+        if lines.next() != Some(RATINGS_HEADER) {
+            return Err(invalid("not a ratings file".to_string()));
+        }
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix("version"))
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .ok_or_else(|| invalid("missing version".to_string()))?;
+        if version != RATINGS_VERSION {
+            return Err(invalid(format!(
+                "ratings version {} is not supported, expected {}",
+                version, RATINGS_VERSION
+            )));
+        }
+
+        let mut ratings = Ratings::default();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["player", player @ ..] => {
+                    parse_player(player).map(|player| ratings.players.push(player))
+                }
+                ["match", result @ ..] => {
+                    parse_match(result).map(|result| ratings.matches.push(result))
+                }
+                _ => None,
+            }
+            .ok_or_else(|| invalid(format!("bad ratings line: {}", line)))?;
+        }
+        Ok(ratings)
+    }
+
+    /// Create an account for `name` if it has none. Returns whether it was created.
+    pub fn register(&mut self, name: &str) -> bool {
+        if self.player(name).is_some() {
+            return false;
+        }
+        self.players.push(PlayerRating {
+            name: name.to_string(),
+            rating: INITIAL_RATING,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+        });
+        true
+    }
+
+    /// Account of `name`, if it has one
+    pub fn player(&self, name: &str) -> Option<&PlayerRating> {
+        self.players.iter().find(|player| player.name == name)
+    }
+
+    /// Rating of `name`, the initial rating without an account
+    pub fn rating(&self, name: &str) -> i32 {
+        self.player(name)
+            .map_or(INITIAL_RATING, |player| player.rating)
+    }
+
+    /// Rate a finished game between `x` and `o`, None for a draw, creating
+    /// their accounts if needed
+    pub fn record(
+        &mut self,
+        x: &str,
+        o: &str,
+        winner: Option<PlayerTag>,
+        time: u64,
+    ) -> MatchResult {
+        // A draw is rated as if X won, scoring half a game
+        let (first, second, score) = match winner {
+            Some(PlayerTag::X) => (x, o, 1.0),
+            Some(PlayerTag::O) => (o, x, 1.0),
+            None => (x, o, 0.5),
+        };
+        self.register(first);
+        self.register(second);
+        let change = rating_change(self.rating(first), self.rating(second), score);
+        for player in self.players.iter_mut() {
+            if player.name == first {
+                player.rating += change;
+            } else if player.name == second {
+                player.rating -= change;
+            } else {
+                continue;
+            }
+            match winner {
+                Some(_) if player.name == first => player.wins += 1,
+                Some(_) => player.losses += 1,
+                None => player.draws += 1,
+            }
+        }
+        let result = MatchResult {
+            time,
+            x: x.to_string(),
+            o: o.to_string(),
+            winner,
+            change,
+        };
+        self.matches.push(result.clone());
+        result
+    }
+
+    /// The `count` best players, highest rating first
+    pub fn leaders(&self, count: usize) -> Vec<PlayerRating> {
+        let mut players = self.players.clone();
+        players.sort_by(|a, b| b.rating.cmp(&a.rating).then_with(|| a.name.cmp(&b.name)));
+        players.truncate(count);
+        players
+    }
+
+    /// The `count` latest games of `name`, newest first
+    pub fn history(&self, name: &str, count: usize) -> Vec<MatchResult> {
+        self.matches
+            .iter()
+            .rev()
+            .filter(|result| result.x == name || result.o == name)
+            .take(count)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Account from the fields of a `player` line: name, rating, wins, draws and losses
+fn parse_player(fields: &[&str]) -> Option<PlayerRating> {
+    match fields {
+        [name, rating, wins, draws, losses] => Some(PlayerRating {
+            name: name.to_string(),
+            rating: rating.parse().ok()?,
+            wins: wins.parse().ok()?,
+            draws: draws.parse().ok()?,
+            losses: losses.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Game from the fields of a `match` line: time, X, O, winner and rating change
+fn parse_match(fields: &[&str]) -> Option<MatchResult> {
+    match fields {
+        [time, x, o, winner, change] => Some(MatchResult {
+            time: time.parse().ok()?,
+            x: x.to_string(),
+            o: o.to_string(),
+            winner: match *winner {
+                "X" => Some(PlayerTag::X),
+                "O" => Some(PlayerTag::O),
+                "-" => None,
+                _ => return None,
+            },
+            change: change.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Unit tests for ratings
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    /// Beating a stronger player is worth more than beating a weaker one,
+    /// and a draw takes points from the higher rated player
+    #[test_case(1500, 1500, 1.0, 16)]
+    #[test_case(1700, 1500, 1.0, 8)]
+    #[test_case(1500, 1700, 1.0, 24)]
+    #[test_case(1500, 1500, 0.5, 0)]
+    #[test_case(1700, 1500, 0.5, -8)]
+    fn test_rating_change(player: i32, opponent: i32, score: f64, change: i32) {
+        assert_eq!(rating_change(player, opponent, score), change);
+    }
+
+    /// Games move the ratings and are kept in the history through a save
+    #[test]
+    fn test_record() {
+        let mut ratings = Ratings::default();
+        assert!(ratings.register("carol"));
+        assert!(!ratings.register("carol"));
+        ratings.record("alice", "bob", Some(PlayerTag::X), 10);
+        ratings.record("bob", "alice", Some(PlayerTag::X), 20);
+
+        let ratings = Ratings::from_text(&ratings.to_text()).unwrap();
+        // Bob won back more than he lost, beating a higher rated player
+        let leaders: Vec<String> = ratings
+            .leaders(2)
+            .into_iter()
+            .map(|player| player.name)
+            .collect();
+        assert_eq!(leaders, ["bob", "carol"]);
+        assert_eq!(ratings.player("alice").unwrap().wins, 1);
+        assert_eq!(
+            ratings.rating("alice") + ratings.rating("bob"),
+            2 * INITIAL_RATING
+        );
+        let history = ratings.history("alice", 5);
+        assert_eq!(
+            history.iter().map(|result| result.time).collect::<Vec<_>>(),
+            [20, 10]
+        );
+    }
+
+    /// Drawn games are rated and kept in the history too
+    #[test]
+    fn test_record_draw() {
+        let mut ratings = Ratings::default();
+        ratings.record("alice", "bob", Some(PlayerTag::X), 10);
+        let result = ratings.record("alice", "bob", None, 20);
+        assert_eq!(result.winner, None);
+        assert!(result.change < 0);
+
+        let ratings = Ratings::from_text(&ratings.to_text()).unwrap();
+        let alice = ratings.player("alice").unwrap();
+        assert_eq!((alice.wins, alice.draws, alice.losses), (1, 1, 0));
+        assert_eq!(ratings.player("bob").unwrap().draws, 1);
+        assert!(alice.rating < INITIAL_RATING + 16);
+        assert_eq!(ratings.history("bob", 5)[0].winner, None);
+    }
+}
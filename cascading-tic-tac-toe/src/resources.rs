@@ -2,6 +2,8 @@
 
 use bevy::prelude::*;
 
+use crate::PlayerTurn;
+
 /// A resource that keeps track of player score, target score/time,
 /// the number of rounds played, and a vector holding arrays of tuples
 /// that signify the valid winning combinations on the board
@@ -27,3 +29,91 @@ impl RoundInit {
         }
     }
 }
+
+/// A resource listing the players whose moves are entered on this screen.
+/// Clicks are ignored on the turns of any other player, who is played by the computer.
+#[derive(Resource)]
+pub struct LocalPlayers(pub Vec<PlayerTurn>);
+
+/// Both players share the screen by default
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        LocalPlayers(vec![PlayerTurn::X, PlayerTurn::O])
+    }
+}
+
+impl LocalPlayers {
+    /// Whether clicks on the board are accepted on this turn
+    pub fn controls(&self, player_turn: &PlayerTurn) -> bool {
+        self.0.contains(player_turn)
+    }
+}
+
+/// How a game is decided, chosen on the new game menu. Also marks its
+/// button on that menu.
+#[derive(Resource, Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GameMode {
+    /// The first player to the target score wins
+    #[default]
+    Target,
+    /// The higher score wins when the clock runs out
+    Timed,
+    /// Rounds go on until the game is ended from the menu
+    Endless,
+}
+
+/// Modes offered on the new game menu, with their button labels
+pub const GAME_MODES: [(GameMode, &str); 3] = [
+    (GameMode::Target, "Target"),
+    (GameMode::Timed, "Timed"),
+    (GameMode::Endless, "Endless"),
+];
+
+/// Resource holding the score that wins a target score game, chosen on the
+/// target menu. Also marks its button on that menu.
+#[derive(Resource, Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TargetScore(pub u32);
+
+/// Three lines win by default
+impl Default for TargetScore {
+    fn default() -> Self {
+        TargetScore(3)
+    }
+}
+
+/// Target scores offered on the target menu
+pub const TARGET_SCORES: [u32; 4] = [1, 3, 5, 10];
+
+/// Resource holding the player who moves first in local games, chosen on
+/// the new game menu. Also marks its button on that menu.
+#[derive(Resource, Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FirstPlayer {
+    #[default]
+    X,
+    O,
+}
+
+impl FirstPlayer {
+    /// The turn a new game starts on
+    pub fn turn(&self) -> PlayerTurn {
+        match self {
+            FirstPlayer::X => PlayerTurn::X,
+            FirstPlayer::O => PlayerTurn::O,
+        }
+    }
+}
+
+/// Resource holding who plays against the player on this screen, chosen on
+/// the new game menu. Also marks its button on that menu.
+#[derive(Resource, Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Opponent {
+    /// Both players share the screen
+    #[default]
+    Human,
+    /// The computer plays its side, see Bot
+    Computer,
+}
+
+/// Resource holding whether the game being played has no target score or clock
+#[derive(Resource, Default)]
+pub struct EndlessMode(pub bool);
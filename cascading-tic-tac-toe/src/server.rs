@@ -0,0 +1,1996 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    clean_chat, unix_time, ClockReading, Connection, CorrespondenceStore, GameRecord, GameSnapshot,
+    Message, PlayerTag, PlayerTurn, Position, Ratings, RoomInfo, RoomSettings, INITIAL_RATING,
+    PROTOCOL_VERSION,
+};
+
+/// Pause between polls when nothing has arrived
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Players listed on the leaderboard
+const LEADERBOARD_SIZE: usize = 10;
+
+/// Latest games listed in a player's history
+const HISTORY_SIZE: usize = 10;
+
+/// Why the server refused a move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoveError {
+    NotYourTurn,
+    NotPlayable((u32, u32)), // Filled, invalid or not on the board
+    GameOver,
+    NoOpponent, // A correspondence challenge nobody has accepted yet
+}
+
+/// Formatting constructor for MoveError
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveError::NotYourTurn => write!(f, "it is not your turn"),
+            MoveError::NotPlayable(cell) => {
+                write!(f, "cell ({},{}) cannot be played", cell.0, cell.1)
+            }
+            MoveError::GameOver => write!(f, "the game is over"),
+            MoveError::NoOpponent => write!(f, "nobody has accepted the challenge yet"),
+        }
+    }
+}
+
+/// Winner of a game whose clock has run out, or an endless game ended from
+/// the menu: the higher score. None for a draw.
+pub fn winner_on_time(x_score: u32, o_score: u32) -> Option<PlayerTag> {
+    match x_score.cmp(&o_score) {
+        std::cmp::Ordering::Greater => Some(PlayerTag::X),
+        std::cmp::Ordering::Less => Some(PlayerTag::O),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// Authoritative state of one game. Every move is checked and scored here,
+/// so the scores clients see never depend on what another client claims.
+pub struct ServerGame {
+    position: Position,
+    target: u32,
+    moves: Vec<(u32, u32)>, // Every move since the game started
+    started: Instant,
+    clock: Option<Duration>, // How long the game may last, None for no clock
+    timed_out: bool,
+    resigned: Option<PlayerTurn>,
+}
+
+impl ServerGame {
+    /// New game played to `target`
+    pub fn new(target: u32) -> Self {
+        ServerGame {
+            position: Position::new(),
+            target,
+            moves: Vec::new(),
+            started: Instant::now(),
+            clock: None,
+            timed_out: false,
+            resigned: None,
+        }
+    }
+
+    /// New game played to `target`, ending when it lasts longer than `clock`
+    pub fn timed(target: u32, clock: Duration) -> Self {
+        ServerGame {
+            clock: Some(clock),
+            ..ServerGame::new(target)
+        }
+    }
+
+    /// Score that wins the game
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// Current position of the game
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    /// Winner of the game, by score, resignation or the clock
+    pub fn winner(&self) -> Option<PlayerTag> {
+        match self.resigned {
+            Some(PlayerTurn::X) => Some(PlayerTag::O),
+            Some(PlayerTurn::O) => Some(PlayerTag::X),
+            None if self.position.x_score() >= self.target => Some(PlayerTag::X),
+            None if self.position.o_score() >= self.target => Some(PlayerTag::O),
+            None if self.timed_out => {
+                winner_on_time(self.position.x_score(), self.position.o_score())
+            }
+            None => None,
+        }
+    }
+
+    /// Whether the game has ended, with a winner or drawn on time
+    pub fn is_over(&self) -> bool {
+        self.timed_out || self.winner().is_some()
+    }
+
+    /// End the game if it has lasted longer than the clock allows.
+    /// Returns true only for the check that ended it.
+    pub fn check_clock(&mut self) -> bool {
+        let Some(clock) = self.clock else {
+            return false;
+        };
+        if self.is_over() || self.started.elapsed() < clock {
+            return false;
+        }
+        self.timed_out = true;
+        true
+    }
+
+    /// The game clock now, sent after every move
+    pub fn clock_reading(&self) -> ClockReading {
+        ClockReading {
+            moves: self.moves.len() as u32,
+            round_count: self.position.round_count(),
+            elapsed: self.started.elapsed().as_millis() as u32,
+        }
+    }
+
+    /// Play a move for `player` if the rules allow it
+    pub fn play(&mut self, player: &PlayerTurn, cell: (u32, u32)) -> Result<(), MoveError> {
+        if self.is_over() {
+            return Err(MoveError::GameOver);
+        }
+        if player != self.position.turn() {
+            return Err(MoveError::NotYourTurn);
+        }
+        // Only empty cells on the board are listed, never Invalid or filled ones
+        if !self.position.moves().contains(&cell) {
+            return Err(MoveError::NotPlayable(cell));
+        }
+        self.position = self.position.play(cell);
+        self.moves.push(cell);
+        Ok(())
+    }
+
+    /// End the game in the opponent's favour
+    pub fn resign(&mut self, player: &PlayerTurn) {
+        if !self.is_over() {
+            self.resigned = Some(player.clone());
+        }
+    }
+
+    /// Start again from the empty board, with the same clock
+    pub fn restart(&mut self) {
+        *self = ServerGame {
+            clock: self.clock,
+            ..ServerGame::new(self.target)
+        };
+    }
+
+    /// Summary sent to both players
+    pub fn snapshot(&self) -> GameSnapshot {
+        GameSnapshot {
+            moves: self.moves.len() as u32,
+            round_count: self.position.round_count(),
+            x_score: self.position.x_score(),
+            o_score: self.position.o_score(),
+            turn: self.position.turn().clone(),
+        }
+    }
+
+    /// Everything a returning player needs to rebuild the game
+    pub fn record(&self) -> GameRecord {
+        GameRecord {
+            moves: self.moves.clone(),
+            elapsed: self.started.elapsed().as_secs() as u32,
+        }
+    }
+}
+
+/// Highest target score a room can be created with
+const MAX_TARGET: u32 = 20;
+
+/// Longest game clock a room can be created with, in seconds
+const MAX_TIMER: u32 = 2 * 60 * 60;
+
+/// Rating gap a quick match accepts straight away
+const RATING_GAP: i32 = 100;
+
+/// The accepted rating gap grows by RATING_GAP each time a player has waited this long
+const GAP_WIDENING: Duration = Duration::from_secs(10);
+
+/// Rating gap accepted by a player who has waited for `waited`
+fn accepted_gap(waited: Duration) -> i32 {
+    let widenings = waited.as_secs() / GAP_WIDENING.as_secs();
+    RATING_GAP.saturating_mul(1 + widenings.min(i32::MAX as u64) as i32)
+}
+
+/// Why the lobby refused a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyError {
+    InRoom, // Already waiting in a room
+    NoSuchRoom(u32),
+    BadSettings,
+}
+
+/// Formatting constructor for LobbyError
+impl fmt::Display for LobbyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LobbyError::InRoom => write!(f, "you are already waiting in a room"),
+            LobbyError::NoSuchRoom(room) => write!(f, "room {} is not open", room),
+            LobbyError::BadSettings => write!(
+                f,
+                "the target must be 1 to {} and the timer 1 to {} seconds",
+                MAX_TARGET, MAX_TIMER
+            ),
+        }
+    }
+}
+
+/// What a lobby request led to. Players are known by the id of their connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyOutcome {
+    Waiting(u32),                                 // In this room until someone joins
+    Paired { host: u32, settings: RoomSettings }, // The game starts, the host plays X
+}
+
+/// A room waiting for its second player
+struct Room {
+    id: u32,
+    host: u32,
+    settings: RoomSettings,
+    quick: Option<i32>, // Host's rating in a quick match room, which is paired rather than listed
+    opened: Instant,
+}
+
+/// Open rooms of the server, oldest first
+#[derive(Default)]
+pub struct Lobby {
+    rooms: Vec<Room>,
+    next_room: u32,
+}
+
+impl Lobby {
+    /// Rooms that can be joined from the list
+    pub fn rooms(&self) -> Vec<RoomInfo> {
+        self.rooms
+            .iter()
+            .filter(|room| room.quick.is_none())
+            .map(|room| RoomInfo {
+                id: room.id,
+                settings: room.settings.clone(),
+            })
+            .collect()
+    }
+
+    /// Open a room for `host` to wait in
+    pub fn create(
+        &mut self,
+        host: u32,
+        settings: RoomSettings,
+    ) -> Result<LobbyOutcome, LobbyError> {
+        if !(1..=MAX_TARGET).contains(&settings.target)
+            || !(1..=MAX_TIMER).contains(&settings.timer)
+        {
+            return Err(LobbyError::BadSettings);
+        }
+        self.open(host, settings, None)
+    }
+
+    /// Join an open room, which closes it
+    pub fn join(&mut self, player: u32, room: u32) -> Result<LobbyOutcome, LobbyError> {
+        if self.hosting(player) {
+            return Err(LobbyError::InRoom);
+        }
+        let index = self
+            .rooms
+            .iter()
+            .position(|open| open.id == room && open.quick.is_none())
+            .ok_or(LobbyError::NoSuchRoom(room))?;
+        let room = self.rooms.remove(index);
+        Ok(LobbyOutcome::Paired {
+            host: room.host,
+            settings: room.settings,
+        })
+    }
+
+    /// Play the waiting quick match player whose rating is closest to
+    /// `rating`, the one who has waited longest between equals, or wait for
+    /// the next one with `settings`. Players are only paired within a rating
+    /// gap that widens the longer they have waited.
+    pub fn quick_match(
+        &mut self,
+        player: u32,
+        settings: RoomSettings,
+        rating: i32,
+    ) -> Result<LobbyOutcome, LobbyError> {
+        if self.hosting(player) {
+            return Err(LobbyError::InRoom);
+        }
+        let closest = self
+            .rooms
+            .iter()
+            .enumerate()
+            .filter_map(|(index, room)| {
+                let gap = (room.quick? - rating).abs();
+                (gap <= accepted_gap(room.opened.elapsed())).then_some((index, gap))
+            })
+            .min_by_key(|(_, gap)| *gap)
+            .map(|(index, _)| index);
+        match closest {
+            Some(index) => {
+                let room = self.rooms.remove(index);
+                Ok(LobbyOutcome::Paired {
+                    host: room.host,
+                    settings: room.settings,
+                })
+            }
+            None => self.open(player, settings, Some(rating)),
+        }
+    }
+
+    /// Pair quick match players who have waited long enough for their
+    /// rating gap to be accepted. Returns the host, guest and settings of each pair.
+    pub fn pair_waiting(&mut self) -> Vec<(u32, u32, RoomSettings)> {
+        let mut pairs = Vec::new();
+        let mut index = 0;
+        while index < self.rooms.len() {
+            let room = &self.rooms[index];
+            let gap = accepted_gap(room.opened.elapsed());
+            let partner = match room.quick {
+                Some(rating) => self.rooms[index + 1..].iter().position(|other| {
+                    other
+                        .quick
+                        .is_some_and(|other| (other - rating).abs() <= gap)
+                }),
+                None => None,
+            };
+            match partner {
+                Some(offset) => {
+                    let guest = self.rooms.remove(index + 1 + offset);
+                    let host = self.rooms.remove(index);
+                    pairs.push((host.host, guest.host, host.settings));
+                }
+                None => index += 1,
+            }
+        }
+        pairs
+    }
+
+    /// Close the room `host` is waiting in, if any
+    pub fn leave(&mut self, host: u32) {
+        self.rooms.retain(|room| room.host != host);
+    }
+
+    /// Whether `player` is waiting in a room
+    fn hosting(&self, player: u32) -> bool {
+        self.rooms.iter().any(|room| room.host == player)
+    }
+
+    fn open(
+        &mut self,
+        host: u32,
+        settings: RoomSettings,
+        quick: Option<i32>,
+    ) -> Result<LobbyOutcome, LobbyError> {
+        if self.hosting(host) {
+            return Err(LobbyError::InRoom);
+        }
+        self.next_room += 1;
+        self.rooms.push(Room {
+            id: self.next_room,
+            host,
+            settings,
+            quick,
+            opened: Instant::now(),
+        });
+        Ok(LobbyOutcome::Waiting(self.next_room))
+    }
+}
+
+/// Settings of a server run
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub quick_settings: RoomSettings,     // Settings of quick matches
+    pub grace_period: Duration, // How long a game waits for a player who lost their connection
+    pub games_directory: Option<PathBuf>, // Where correspondence games are kept, None to refuse them
+    pub ratings_path: Option<PathBuf>, // Where accounts and ratings are kept, None for memory only
+}
+
+/// Default constructor for ServerConfig
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            quick_settings: RoomSettings::default(),
+            grace_period: Duration::from_secs(60),
+            games_directory: None,
+            ratings_path: None,
+        }
+    }
+}
+
+/// Someone arriving at a game in progress
+enum Arrival {
+    Resume(u64, Connection), // A player taking back the seat with this token
+    Watch(Connection),       // A spectator
+}
+
+impl Arrival {
+    /// Connection of whoever arrived
+    fn into_connection(self) -> Connection {
+        match self {
+            Arrival::Resume(_, connection) | Arrival::Watch(connection) => connection,
+        }
+    }
+}
+
+/// Games in progress, as players and spectators find them from the lobby
+#[derive(Default)]
+struct Games {
+    seats: HashMap<u64, Sender<Arrival>>, // By session token
+    watched: HashMap<u32, (RoomSettings, Sender<Arrival>)>, // By game id
+}
+
+impl Games {
+    /// Games that can be watched, oldest first
+    fn playing(&self) -> Vec<RoomInfo> {
+        let mut games: Vec<RoomInfo> = self
+            .watched
+            .iter()
+            .map(|(id, (settings, _))| RoomInfo {
+                id: *id,
+                settings: settings.clone(),
+            })
+            .collect();
+        games.sort_by_key(|game| game.id);
+        games
+    }
+}
+
+/// Games shared between the lobby and the game threads
+type SharedGames = Arc<Mutex<Games>>;
+
+/// Ratings shared between the lobby, which lists them, and the game threads, which update them
+type SharedRatings = Arc<Mutex<Ratings>>;
+
+/// A player connected to the server who is not playing a game
+struct Client {
+    id: u32,
+    connection: Connection,
+    greeted: bool,                        // HELLO received
+    name: Option<String>,                 // Account the player's lobby games are rated for
+    open_game: Option<(u32, PlayerTurn)>, // Correspondence game being played, and the seat
+}
+
+/// Where a player goes after a lobby message
+enum Handoff {
+    NewGame {
+        host: u32,
+        settings: RoomSettings,
+    }, // Joined the host's room
+    // Takes back the seat with the token, or watches without one
+    Arrive {
+        token: Option<u64>,
+        game: Sender<Arrival>,
+    },
+    // Stays in the lobby, the messages go to everyone else with the correspondence game open
+    Relay {
+        game: u32,
+        messages: Vec<Message>,
+    },
+}
+
+/// Accept players forever and keep them in the lobby until they are paired,
+/// take back their seat in a game or watch one. Each game runs on its own
+/// thread, so any number of games can be played at once.
+pub fn serve(listener: TcpListener, config: ServerConfig) -> io::Result<()> {
+    listener.set_nonblocking(true)?;
+    let mut clients: Vec<Client> = Vec::new();
+    let mut lobby = Lobby::default();
+    let shared = SharedGames::default();
+    let ratings = SharedRatings::new(Mutex::new(match &config.ratings_path {
+        Some(path) => Ratings::load(path)?,
+        None => Ratings::default(),
+    }));
+    let store = match &config.games_directory {
+        Some(directory) => Some(CorrespondenceStore::open(directory)?),
+        None => None,
+    };
+    let mut next_client = 0;
+    let mut next_game = 0;
+
+    loop {
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Ok(connection) = Connection::new(stream) {
+                        next_client += 1;
+                        clients.push(Client {
+                            id: next_client,
+                            connection,
+                            greeted: false,
+                            name: None,
+                            open_game: None,
+                        });
+                    }
+                }
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            }
+        }
+
+        let mut gone = Vec::new();
+        let mut games = Vec::new(); // Host, guest and settings of each new game
+        let mut arrivals = Vec::new(); // Client, token and game of each arrival
+        let mut relays = Vec::new(); // Sender, correspondence game and messages to pass on
+        for client in clients.iter_mut() {
+            let handled = client.connection.poll().and_then(|messages| {
+                for message in messages {
+                    let handoff = handle_lobby_message(
+                        client,
+                        &mut lobby,
+                        message,
+                        &config.quick_settings,
+                        &shared,
+                        &ratings,
+                        store.as_ref(),
+                    )?;
+                    match handoff {
+                        Some(Handoff::NewGame { host, settings }) => {
+                            games.push((host, client.id, settings))
+                        }
+                        Some(Handoff::Arrive { token, game }) => {
+                            arrivals.push((client.id, token, game))
+                        }
+                        Some(Handoff::Relay { game, messages }) => {
+                            relays.push((client.id, game, messages))
+                        }
+                        None => (),
+                    }
+                }
+                Ok(())
+            });
+            if handled.is_err() {
+                // A player who leaves before being paired frees the room
+                lobby.leave(client.id);
+                gone.push(client.id);
+            }
+        }
+        clients.retain(|client| !gone.contains(&client.id));
+
+        // Quick match players who waited long enough play a wider rating gap
+        games.extend(lobby.pair_waiting());
+
+        for (sender, game, messages) in relays {
+            for client in clients.iter_mut() {
+                let watching = matches!(client.open_game, Some((open, _)) if open == game);
+                if watching && client.id != sender {
+                    for message in &messages {
+                        // A lost connection is noticed by the next poll
+                        let _ = client.connection.send(message);
+                    }
+                }
+            }
+        }
+
+        for (host, guest, settings) in games {
+            match (
+                take_client(&mut clients, host),
+                take_client(&mut clients, guest),
+            ) {
+                (Some(x), Some(o)) => {
+                    next_game += 1;
+                    let mut table = Table::new(next_game, [x.connection, o.connection], settings);
+                    table.names = [x.name, o.name];
+                    let grace_period = config.grace_period;
+                    let shared = Arc::clone(&shared);
+                    let ratings = Arc::clone(&ratings);
+                    thread::spawn(move || run_game(table, grace_period, shared, ratings));
+                }
+                // The room closed as its host left, the other player stays in the lobby
+                (Some(mut client), None) | (None, Some(mut client)) => {
+                    let _ = client.connection.send(&Message::Invalid {
+                        reason: "the other player has left".to_string(),
+                    });
+                    clients.push(client);
+                }
+                (None, None) => (),
+            }
+        }
+
+        for (id, token, game) in arrivals {
+            let Some(client) = take_client(&mut clients, id) else {
+                continue;
+            };
+            let arrival = match token {
+                Some(token) => Arrival::Resume(token, client.connection),
+                None => Arrival::Watch(client.connection),
+            };
+            // The game may have ended since it was looked up
+            if let Err(mpsc::SendError(arrival)) = game.send(arrival) {
+                let _ = arrival.into_connection().send(&Message::Reject {
+                    reason: "the game is over".to_string(),
+                });
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Remove a client from the lobby
+fn take_client(clients: &mut Vec<Client>, id: u32) -> Option<Client> {
+    let index = clients.iter().position(|client| client.id == id)?;
+    Some(clients.swap_remove(index))
+}
+
+/// Answer one message from a player in the lobby. Returns where the player
+/// goes next, or an error to drop the player.
+fn handle_lobby_message(
+    client: &mut Client,
+    lobby: &mut Lobby,
+    message: Message,
+    quick_settings: &RoomSettings,
+    shared: &SharedGames,
+    ratings: &SharedRatings,
+    store: Option<&CorrespondenceStore>,
+) -> io::Result<Option<Handoff>> {
+    let reason = match message {
+        Message::Hello { version } | Message::Watch { version, .. }
+            if version != PROTOCOL_VERSION && !client.greeted =>
+        {
+            format!(
+                "protocol version {} is not supported, server uses {}",
+                version, PROTOCOL_VERSION
+            )
+        }
+        Message::Hello { .. } if !client.greeted => {
+            client.greeted = true;
+            client.connection.send(&Message::Welcome {
+                version: PROTOCOL_VERSION,
+            })?;
+            return Ok(None);
+        }
+        // Spectators may watch straight away, or after browsing the lobby
+        Message::Watch { game, .. } => match shared.lock().unwrap().watched.get(&game) {
+            Some((_, sender)) => {
+                return Ok(Some(Handoff::Arrive {
+                    token: None,
+                    game: sender.clone(),
+                }))
+            }
+            None if client.greeted => {
+                client.connection.send(&Message::Invalid {
+                    reason: format!("game {} is not being played", game),
+                })?;
+                return Ok(None);
+            }
+            None => format!("game {} is not being played", game),
+        },
+        _ if !client.greeted => "expected HELLO".to_string(),
+        Message::Resume { token } => match shared.lock().unwrap().seats.get(&token) {
+            Some(game) => {
+                return Ok(Some(Handoff::Arrive {
+                    token: Some(token),
+                    game: game.clone(),
+                }))
+            }
+            None => "there is no game to resume".to_string(),
+        },
+        // Also measures the delay of correspondence games, which have no clock
+        Message::Ping { stamp } => {
+            client.connection.send(&Message::Pong { stamp })?;
+            return Ok(None);
+        }
+        Message::List => {
+            client.connection.send(&Message::Rooms(lobby.rooms()))?;
+            let playing = shared.lock().unwrap().playing();
+            client.connection.send(&Message::Playing(playing))?;
+            return Ok(None);
+        }
+        message @ (Message::Games { .. }
+        | Message::Challenge { .. }
+        | Message::Accept { .. }
+        | Message::Open { .. }) => {
+            return answer_correspondence(client, store, message, quick_settings.target)
+        }
+        // Moves and the like from the lobby belong to the open correspondence game
+        message @ (Message::Move { .. }
+        | Message::Resign
+        | Message::Restart
+        | Message::Chat { .. }
+        | Message::Emote(_))
+            if client.open_game.is_some() =>
+        {
+            return answer_correspondence(client, store, message, quick_settings.target)
+        }
+        Message::Name { player } => {
+            let mut ratings = ratings.lock().unwrap();
+            if ratings.register(&player) {
+                if let Err(error) = ratings.save() {
+                    println!("Could not save the ratings: {}", error);
+                }
+            }
+            client.name = Some(player);
+            return Ok(None);
+        }
+        Message::Leaders { player } => {
+            let (leaders, history) = {
+                let ratings = ratings.lock().unwrap();
+                let mut leaders = ratings.leaders(LEADERBOARD_SIZE);
+                // The player's own account comes last, even when it is among the best
+                if let Some(account) = ratings.player(&player) {
+                    leaders.push(account.clone());
+                }
+                (leaders, ratings.history(&player, HISTORY_SIZE))
+            };
+            client.connection.send(&Message::Leaderboard(leaders))?;
+            client.connection.send(&Message::History(history))?;
+            return Ok(None);
+        }
+        Message::QuickMatch => {
+            let rating = match &client.name {
+                Some(name) => ratings.lock().unwrap().rating(name),
+                None => INITIAL_RATING,
+            };
+            let outcome = lobby.quick_match(client.id, quick_settings.clone(), rating);
+            return answer_lobby_outcome(client, outcome);
+        }
+        message => return answer_lobby_message(client, lobby, message),
+    };
+    // Best effort, the connection is closed either way
+    let _ = client.connection.send(&Message::Reject {
+        reason: reason.clone(),
+    });
+    Err(io::Error::new(io::ErrorKind::InvalidData, reason))
+}
+
+/// Answer a request about correspondence games from a greeted player. Games
+/// are loaded and saved for every request, so nothing is lost if the server stops.
+fn answer_correspondence(
+    client: &mut Client,
+    store: Option<&CorrespondenceStore>,
+    message: Message,
+    target: u32,
+) -> io::Result<Option<Handoff>> {
+    let Some(store) = store else {
+        client.connection.send(&Message::Invalid {
+            reason: "this server has no correspondence games".to_string(),
+        })?;
+        return Ok(None);
+    };
+    let now = unix_time();
+    let invalid = |reason: String| Message::Invalid { reason };
+    // Games of the player, then challenges they could accept
+    let game_list = |player: &str| -> io::Result<Message> {
+        let games = store.games(now)?;
+        let (mine, open): (Vec<_>, Vec<_>) = games
+            .iter()
+            .filter(|game| game.seat(player).is_some() || game.o.is_none())
+            .partition(|game| game.seat(player).is_some());
+        Ok(Message::GameList(
+            mine.into_iter()
+                .chain(open)
+                .map(|game| game.info())
+                .collect(),
+        ))
+    };
+
+    let reply = match message {
+        Message::Games { player } => game_list(&player)?,
+        Message::Challenge { player, days } => match store.challenge(&player, target, days, now) {
+            Ok(_) => game_list(&player)?,
+            Err(error) => invalid(error.to_string()),
+        },
+        Message::Accept { player, game } => match store.accept(game, &player, now) {
+            Ok(_) => game_list(&player)?,
+            Err(error) => invalid(error.to_string()),
+        },
+        Message::Open { player, game } => {
+            let opened = store
+                .load(game, now)
+                .ok()
+                .and_then(|opened| Some((opened.seat(&player)?, opened)));
+            let Some((seat, opened)) = opened else {
+                client
+                    .connection
+                    .send(&invalid(format!("you are not playing game {}", game)))?;
+                return Ok(None);
+            };
+            client.open_game = Some((game, seat.clone()));
+            client
+                .connection
+                .send(&Message::Start(seat, opened.settings()))?;
+            client.connection.send(&Message::Sync(opened.record(now)))?;
+            Message::State(opened.snapshot())
+        }
+        message => {
+            let Some((id, seat)) = client.open_game.clone() else {
+                return Ok(None);
+            };
+            let mut game = store.load(id, now)?;
+            let relayed = match message {
+                Message::Move { cell } => match game.play(&seat, cell, now) {
+                    Ok(()) => Message::Move { cell },
+                    Err(error) => {
+                        client.connection.send(&invalid(error.to_string()))?;
+                        return Ok(None);
+                    }
+                },
+                Message::Resign => {
+                    game.resign(&seat, now);
+                    Message::Resign
+                }
+                // Chat leaves the game as it was, so no state follows it
+                Message::Chat { text } => match clean_chat(&text) {
+                    Some(text) => {
+                        let messages = vec![Message::Chat { text }];
+                        return Ok(Some(Handoff::Relay { game: id, messages }));
+                    }
+                    None => invalid("empty chat message".to_string()),
+                },
+                Message::Emote(emote) => {
+                    let messages = vec![Message::Emote(emote)];
+                    return Ok(Some(Handoff::Relay { game: id, messages }));
+                }
+                // Correspondence games are never restarted, the board is sent back
+                _ => {
+                    client.connection.send(&invalid(
+                        "correspondence games cannot be restarted".to_string(),
+                    ))?;
+                    client.connection.send(&Message::Sync(game.record(now)))?;
+                    Message::State(game.snapshot())
+                }
+            };
+            if !matches!(relayed, Message::Move { .. } | Message::Resign) {
+                client.connection.send(&relayed)?;
+                return Ok(None);
+            }
+            store.save(&game)?;
+            let state = Message::State(game.snapshot());
+            client.connection.send(&state)?;
+            return Ok(Some(Handoff::Relay {
+                game: id,
+                messages: vec![relayed, state],
+            }));
+        }
+    };
+    client.connection.send(&reply)?;
+    Ok(None)
+}
+
+/// Answer a lobby request from a greeted player
+fn answer_lobby_message(
+    client: &mut Client,
+    lobby: &mut Lobby,
+    message: Message,
+) -> io::Result<Option<Handoff>> {
+    let outcome = match message {
+        Message::Leave => {
+            lobby.leave(client.id);
+            return Ok(None);
+        }
+        Message::Create(settings) => lobby.create(client.id, settings),
+        Message::Join { room } => lobby.join(client.id, room),
+        _ => {
+            client.connection.send(&Message::Invalid {
+                reason: format!("unexpected message {}", message.encode()),
+            })?;
+            return Ok(None);
+        }
+    };
+    answer_lobby_outcome(client, outcome)
+}
+
+/// Tell a player where a lobby request left them
+fn answer_lobby_outcome(
+    client: &mut Client,
+    outcome: Result<LobbyOutcome, LobbyError>,
+) -> io::Result<Option<Handoff>> {
+    match outcome {
+        Ok(LobbyOutcome::Waiting(room)) => client.connection.send(&Message::Room { room })?,
+        Ok(LobbyOutcome::Paired { host, settings }) => {
+            return Ok(Some(Handoff::NewGame { host, settings }))
+        }
+        Err(error) => client.connection.send(&Message::Invalid {
+            reason: error.to_string(),
+        })?,
+    }
+    Ok(None)
+}
+
+/// Hard to guess token for a seat
+fn new_token() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    hasher.write_u128(now.as_nanos());
+    hasher.finish()
+}
+
+/// A player's place in a game, kept while they reconnect
+struct Seat {
+    player: PlayerTurn,
+    connection: Option<Connection>, // None while the player is away
+    token: u64,
+    away_since: Option<Instant>, // When the opponent was told the player had gone
+}
+
+impl Seat {
+    /// Send a message if the player is connected, losing the connection on failure
+    fn send(&mut self, message: &Message) {
+        if let Some(connection) = self.connection.as_mut() {
+            if connection.send(message).is_err() {
+                self.connection = None;
+            }
+        }
+    }
+}
+
+/// Everyone at a game: both seats and the spectators
+struct Table {
+    id: u32,
+    settings: RoomSettings,
+    seats: [Seat; 2],
+    spectators: Vec<Connection>,
+    announced: usize,             // Spectator count the table was last told
+    names: [Option<String>; 2],   // Accounts of X and O, the game is rated when both are known
+    rated: bool,                  // The result of the current game has been rated
+    restart_asked: Option<usize>, // Seat that asked to restart, waiting for the other to agree
+}
+
+impl Table {
+    /// Seat X and O
+    fn new(id: u32, players: [Connection; 2], settings: RoomSettings) -> Self {
+        let [x, o] = players;
+        let seats = [(PlayerTurn::X, x), (PlayerTurn::O, o)].map(|(player, connection)| Seat {
+            player,
+            connection: Some(connection),
+            token: new_token(),
+            away_since: None,
+        });
+        Table {
+            id,
+            settings,
+            seats,
+            spectators: Vec::new(),
+            announced: 0,
+            names: [None, None],
+            rated: false,
+            restart_asked: None,
+        }
+    }
+
+    /// Whether results are rated, which needs both players to have different accounts
+    fn is_rated(&self) -> bool {
+        matches!(&self.names, [Some(x), Some(o)] if x != o)
+    }
+
+    /// Rate the game once it is over, won or drawn, if the table is rated
+    fn rate(&mut self, game: &ServerGame, ratings: &SharedRatings) {
+        let [Some(x), Some(o)] = &self.names else {
+            return;
+        };
+        if self.rated || !self.is_rated() || !game.is_over() {
+            return;
+        }
+        self.rated = true;
+        let mut ratings = ratings.lock().unwrap();
+        ratings.record(x, o, game.winner(), unix_time());
+        if let Err(error) = ratings.save() {
+            println!("Could not save the ratings: {}", error);
+        }
+    }
+
+    /// Send a message to the spectators, dropping those who have left
+    fn tell_spectators(&mut self, message: &Message) {
+        self.spectators
+            .retain_mut(|spectator| spectator.send(message).is_ok());
+    }
+
+    /// Send a message to both players and the spectators
+    fn tell_everyone(&mut self, message: &Message) {
+        for seat in self.seats.iter_mut() {
+            seat.send(message);
+        }
+        self.tell_spectators(message);
+    }
+
+    /// Answer the pings of spectators, refuse anything else they send and
+    /// drop those who have left
+    fn poll_spectators(&mut self) {
+        self.spectators
+            .retain_mut(|spectator| match spectator.poll() {
+                Ok(messages) => messages.into_iter().all(|message| {
+                    let answer = match message {
+                        Message::Ping { stamp } => Message::Pong { stamp },
+                        _ => Message::Invalid {
+                            reason: "spectators cannot play".to_string(),
+                        },
+                    };
+                    spectator.send(&answer).is_ok()
+                }),
+                Err(_) => false,
+            });
+    }
+
+    /// Tell everyone how many are watching, when that has changed
+    fn announce_spectators(&mut self) {
+        if self.spectators.len() != self.announced {
+            self.announced = self.spectators.len();
+            let count = self.announced as u32;
+            self.tell_everyone(&Message::Spectators { count });
+        }
+    }
+}
+
+/// Play one game between X and O. A player who loses their connection can
+/// take the seat back with its session token until `grace_period` has
+/// passed, after which they forfeit the game. Anyone can watch it while it
+/// is played.
+fn run_game(mut table: Table, grace_period: Duration, shared: SharedGames, ratings: SharedRatings) {
+    let (sender, arrivals) = mpsc::channel();
+    {
+        let mut games = shared.lock().unwrap();
+        for seat in &table.seats {
+            games.seats.insert(seat.token, sender.clone());
+        }
+        games
+            .watched
+            .insert(table.id, (table.settings.clone(), sender));
+    }
+
+    play_game(&mut table, grace_period, &arrivals, &ratings);
+
+    let mut games = shared.lock().unwrap();
+    for seat in &table.seats {
+        games.seats.remove(&seat.token);
+    }
+    games.watched.remove(&table.id);
+}
+
+/// Relay the messages of a game, checking each one against the ServerGame,
+/// until it is over and a player has left or a player's grace period runs
+/// out. Every result is rated once.
+fn play_game(
+    table: &mut Table,
+    grace_period: Duration,
+    arrivals: &Receiver<Arrival>,
+    ratings: &SharedRatings,
+) {
+    let clock = Duration::from_secs(table.settings.timer.into());
+    let mut game = ServerGame::timed(table.settings.target, clock);
+    let settings = table.settings.clone();
+    for seat in table.seats.iter_mut() {
+        seat.send(&Message::Start(seat.player.clone(), settings.clone()));
+        seat.send(&Message::Session { token: seat.token });
+        seat.send(&Message::State(game.snapshot()));
+        seat.send(&Message::Clock(game.clock_reading()));
+    }
+
+    loop {
+        while let Ok(arrival) = arrivals.try_recv() {
+            let (token, connection) = match arrival {
+                Arrival::Resume(token, connection) => (token, connection),
+                Arrival::Watch(mut connection) => {
+                    // Joins mid-game like a returning player
+                    let joined = connection
+                        .send(&Message::Spectate(settings.clone()))
+                        .and_then(|()| connection.send(&Message::Sync(game.record())))
+                        .and_then(|()| connection.send(&Message::State(game.snapshot())))
+                        .and_then(|()| connection.send(&Message::Clock(game.clock_reading())));
+                    if joined.is_ok() {
+                        table.spectators.push(connection);
+                    }
+                    continue;
+                }
+            };
+            let Some(index) = table.seats.iter().position(|seat| seat.token == token) else {
+                continue;
+            };
+            // Replaces a connection that has not been noticed to be lost yet
+            let seat = &mut table.seats[index];
+            seat.connection = Some(connection);
+            seat.send(&Message::Start(seat.player.clone(), settings.clone()));
+            seat.send(&Message::Session { token });
+            seat.send(&Message::Sync(game.record()));
+            seat.send(&Message::State(game.snapshot()));
+            seat.send(&Message::Clock(game.clock_reading()));
+            if table.announced > 0 {
+                let count = table.announced as u32;
+                seat.send(&Message::Spectators { count });
+            }
+            if seat.away_since.take().is_some() {
+                table.seats[1 - index].send(&Message::Back);
+                table.tell_spectators(&Message::Back);
+            }
+        }
+
+        for index in 0..2 {
+            let opponent = 1 - index;
+            let messages = match table.seats[index].connection.as_mut().map(Connection::poll) {
+                Some(Ok(messages)) => messages,
+                Some(Err(_)) => {
+                    table.seats[index].connection = None;
+                    continue;
+                }
+                None => continue,
+            };
+            let player = table.seats[index].player.clone();
+            for message in messages {
+                match message {
+                    Message::Move { cell } => match game.play(&player, cell) {
+                        Ok(()) => {
+                            // A restart asked for before the move no longer stands
+                            table.restart_asked = None;
+                            table.seats[opponent].send(&Message::Move { cell });
+                            table.tell_spectators(&Message::Move { cell });
+                        }
+                        Err(error) => {
+                            table.seats[index].send(&Message::Invalid {
+                                reason: error.to_string(),
+                            });
+                            // The refused move is already on the player's board,
+                            // the game so far takes it back off. A finished game
+                            // has been ended on their side too.
+                            if error != MoveError::GameOver {
+                                table.seats[index].send(&Message::Sync(game.record()));
+                                table.seats[index].send(&Message::State(game.snapshot()));
+                            }
+                            continue;
+                        }
+                    },
+                    // A rated game is resigned rather than restarted, so every
+                    // result is rated
+                    Message::Restart if table.is_rated() && !game.is_over() => {
+                        table.seats[index].send(&Message::Invalid {
+                            reason: "a rated game cannot be restarted before it ends".to_string(),
+                        });
+                        continue;
+                    }
+                    // Neither player can restart the game on their own, the
+                    // opponent is asked and the game restarts once they agree
+                    Message::Restart if table.restart_asked != Some(opponent) => {
+                        table.restart_asked = Some(index);
+                        table.seats[opponent].send(&Message::Restart);
+                        continue;
+                    }
+                    Message::Restart => {
+                        table.restart_asked = None;
+                        // The result is rated before the restart clears it
+                        table.rate(&game, ratings);
+                        game.restart();
+                        table.rated = false;
+                        table.tell_everyone(&Message::Restart);
+                    }
+                    Message::Resign => {
+                        game.resign(&player);
+                        table.seats[opponent].send(&Message::Resign);
+                        table.tell_spectators(&Message::Resigned(player.clone()));
+                    }
+                    // Chat leaves the game as it was, so no state follows it
+                    Message::Chat { text } => {
+                        match clean_chat(&text) {
+                            Some(text) => table.seats[opponent].send(&Message::Chat { text }),
+                            None => table.seats[index].send(&Message::Invalid {
+                                reason: "empty chat message".to_string(),
+                            }),
+                        }
+                        continue;
+                    }
+                    Message::Emote(emote) => {
+                        table.seats[opponent].send(&Message::Emote(emote));
+                        continue;
+                    }
+                    Message::Ping { stamp } => {
+                        table.seats[index].send(&Message::Pong { stamp });
+                        continue;
+                    }
+                    _ => {
+                        table.seats[index].send(&Message::Invalid {
+                            reason: format!("unexpected message {}", message.encode()),
+                        });
+                        continue;
+                    }
+                }
+                // Only sent after a change, a refused message leaves the game as it was
+                table.tell_everyone(&Message::State(game.snapshot()));
+                table.tell_everyone(&Message::Clock(game.clock_reading()));
+            }
+        }
+        // Moves that arrived in time were played above, later ones are refused
+        if game.check_clock() {
+            table.tell_everyone(&Message::TimeUp(game.winner()));
+            table.tell_everyone(&Message::State(game.snapshot()));
+        }
+        table.rate(&game, ratings);
+        table.poll_spectators();
+        table.announce_spectators();
+
+        for index in 0..2 {
+            if table.seats[index].connection.is_some() {
+                continue;
+            }
+            match table.seats[index].away_since {
+                // Nothing to wait for once the game is over
+                None if game.is_over() => return,
+                None => {
+                    table.seats[index].away_since = Some(Instant::now());
+                    let away = Message::Away {
+                        seconds: grace_period.as_secs() as u32,
+                    };
+                    table.seats[1 - index].send(&away);
+                    table.tell_spectators(&away);
+                }
+                Some(since) if since.elapsed() >= grace_period => {
+                    let player = table.seats[index].player.clone();
+                    game.resign(&player);
+                    table.seats[1 - index].send(&Message::Resign);
+                    table.tell_spectators(&Message::Resigned(player));
+                    table.rate(&game, ratings);
+                    return;
+                }
+                Some(_) => (),
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Unit tests for the game server
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emote;
+    use std::time::Instant;
+
+    /// Add messages received on a connection to `messages` until it holds `count`
+    fn receive(connection: &mut Connection, messages: &mut Vec<Message>, count: usize) {
+        let start = Instant::now();
+        while messages.len() < count {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "got {:?}",
+                messages
+            );
+            messages.extend(connection.poll().unwrap());
+        }
+    }
+
+    /// Add messages received on a connection to `messages` until one is `found`
+    fn receive_until(
+        connection: &mut Connection,
+        messages: &mut Vec<Message>,
+        found: impl Fn(&Message) -> bool,
+    ) {
+        while !messages.iter().any(&found) {
+            let count = messages.len() + 1;
+            receive(connection, messages, count);
+        }
+    }
+
+    /// Connect to a server and say hello
+    fn greet(address: &str) -> Connection {
+        let mut connection = Connection::connect(address, Duration::from_secs(1)).unwrap();
+        connection
+            .send(&Message::Hello {
+                version: PROTOCOL_VERSION,
+            })
+            .unwrap();
+        let mut messages = Vec::new();
+        receive(&mut connection, &mut messages, 1);
+        assert_eq!(
+            messages[0],
+            Message::Welcome {
+                version: PROTOCOL_VERSION
+            }
+        );
+        connection
+    }
+
+    /// Start a server and a quick match on it. Returns the server address,
+    /// X, O and O's session token.
+    fn start_game(config: ServerConfig) -> (String, Connection, Connection, u64) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, config));
+
+        let mut x = greet(&address);
+        x.send(&Message::QuickMatch).unwrap();
+        let mut x_messages = Vec::new();
+        receive(&mut x, &mut x_messages, 1);
+        let mut o = greet(&address);
+        o.send(&Message::QuickMatch).unwrap();
+        receive(&mut x, &mut x_messages, 5);
+        let mut o_messages = Vec::new();
+        receive(&mut o, &mut o_messages, 4);
+        let Message::Session { token } = o_messages[1] else {
+            panic!("got {:?}", o_messages);
+        };
+        (address, x, o, token)
+    }
+
+    /// Moves out of turn, on filled cells or off the board are refused
+    #[test]
+    fn test_move_validation() {
+        let mut game = ServerGame::new(1);
+        assert_eq!(
+            game.play(&PlayerTurn::O, (0, 0)),
+            Err(MoveError::NotYourTurn)
+        );
+        assert_eq!(game.play(&PlayerTurn::X, (0, 0)), Ok(()));
+        assert_eq!(
+            game.play(&PlayerTurn::O, (0, 0)),
+            Err(MoveError::NotPlayable((0, 0)))
+        );
+        assert_eq!(
+            game.play(&PlayerTurn::O, (4, 0)),
+            Err(MoveError::NotPlayable((4, 0)))
+        );
+
+        for (player, cell) in [
+            (PlayerTurn::O, (1, 0)),
+            (PlayerTurn::X, (0, 1)),
+            (PlayerTurn::O, (1, 1)),
+            (PlayerTurn::X, (0, 2)),
+        ] {
+            game.play(&player, cell).unwrap();
+        }
+        let snapshot = game.snapshot();
+        assert_eq!(
+            (snapshot.x_score, snapshot.round_count, snapshot.moves),
+            (1, 1, 5)
+        );
+        assert_eq!(game.winner(), Some(PlayerTag::X));
+        assert_eq!(game.play(&PlayerTurn::O, (3, 1)), Err(MoveError::GameOver));
+    }
+
+    /// A game whose clock has run out goes to the higher score, equal scores draw
+    #[test]
+    fn test_clock() {
+        assert_eq!(winner_on_time(2, 1), Some(PlayerTag::X));
+        assert_eq!(winner_on_time(0, 0), None);
+
+        let mut game = ServerGame::timed(3, Duration::ZERO);
+        game.play(&PlayerTurn::X, (0, 0)).unwrap();
+        assert!(game.check_clock());
+        assert!(!game.check_clock());
+        assert!(game.is_over());
+        assert_eq!(game.winner(), None);
+        assert_eq!(game.play(&PlayerTurn::O, (1, 0)), Err(MoveError::GameOver));
+        game.restart();
+        assert_eq!(game.winner(), None);
+        assert!(!ServerGame::new(3).check_clock());
+    }
+
+    /// Rooms are opened, listed, joined and closed, quick matches pair in order
+    #[test]
+    fn test_lobby() {
+        let mut lobby = Lobby::default();
+        let settings = RoomSettings {
+            target: 5,
+            ..RoomSettings::default()
+        };
+        assert_eq!(
+            lobby.create(1, settings.clone()),
+            Ok(LobbyOutcome::Waiting(1))
+        );
+        assert_eq!(lobby.create(1, settings.clone()), Err(LobbyError::InRoom));
+        let no_target = RoomSettings {
+            target: 0,
+            ..RoomSettings::default()
+        };
+        assert_eq!(lobby.create(2, no_target), Err(LobbyError::BadSettings));
+
+        // Quick match rooms are not listed or joined from the list
+        assert_eq!(
+            lobby.quick_match(2, RoomSettings::default(), INITIAL_RATING),
+            Ok(LobbyOutcome::Waiting(2))
+        );
+        assert_eq!(
+            lobby.rooms(),
+            vec![RoomInfo {
+                id: 1,
+                settings: settings.clone()
+            }]
+        );
+        assert_eq!(lobby.join(3, 2), Err(LobbyError::NoSuchRoom(2)));
+        assert_eq!(
+            lobby.quick_match(3, RoomSettings::default(), INITIAL_RATING),
+            Ok(LobbyOutcome::Paired {
+                host: 2,
+                settings: RoomSettings::default()
+            })
+        );
+
+        assert_eq!(
+            lobby.join(4, 1),
+            Ok(LobbyOutcome::Paired { host: 1, settings })
+        );
+        assert_eq!(
+            lobby.create(5, RoomSettings::default()),
+            Ok(LobbyOutcome::Waiting(3))
+        );
+        lobby.leave(5);
+        assert_eq!(lobby.join(6, 3), Err(LobbyError::NoSuchRoom(3)));
+        assert!(lobby.rooms().is_empty());
+    }
+
+    /// Quick matches pair close ratings, the gap widening with the wait
+    #[test]
+    fn test_quick_match_by_rating() {
+        let mut lobby = Lobby::default();
+        let settings = RoomSettings::default();
+        let paired = |host| LobbyOutcome::Paired {
+            host,
+            settings: settings.clone(),
+        };
+        for (player, rating) in [(1, 1800), (2, 1450)] {
+            assert_eq!(
+                lobby.quick_match(player, settings.clone(), rating),
+                Ok(LobbyOutcome::Waiting(player))
+            );
+        }
+        assert_eq!(lobby.quick_match(3, settings.clone(), 1550), Ok(paired(2)));
+        assert_eq!(lobby.quick_match(4, settings.clone(), 1700), Ok(paired(1)));
+        assert_eq!(accepted_gap(Duration::from_secs(25)), 3 * RATING_GAP);
+
+        for (player, rating) in [(5, 1500), (6, 1700)] {
+            lobby.quick_match(player, settings.clone(), rating).unwrap();
+        }
+        assert!(lobby.pair_waiting().is_empty());
+        lobby.rooms[0].opened -= GAP_WIDENING;
+        assert_eq!(lobby.pair_waiting(), [(5, 6, settings.clone())]);
+    }
+
+    /// A game between named players moves their ratings, which the
+    /// leaderboard and history then show
+    #[test]
+    fn test_rated_game() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, ServerConfig::default()));
+
+        let mut players = Vec::new();
+        for name in ["alice", "bob"] {
+            let mut player = greet(&address);
+            player
+                .send(&Message::Name {
+                    player: name.to_string(),
+                })
+                .unwrap();
+            player.send(&Message::QuickMatch).unwrap();
+            let mut messages = Vec::new();
+            receive(&mut player, &mut messages, 1);
+            players.push(player);
+        }
+        players[1].send(&Message::Resign).unwrap();
+        let mut messages = Vec::new();
+        receive(&mut players[0], &mut messages, 5);
+
+        let mut viewer = greet(&address);
+        let start = Instant::now();
+        let leaders = loop {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            viewer
+                .send(&Message::Leaders {
+                    player: "bob".to_string(),
+                })
+                .unwrap();
+            let mut answers = Vec::new();
+            receive(&mut viewer, &mut answers, 2);
+            match &answers[..] {
+                [Message::Leaderboard(leaders), Message::History(history)]
+                    if !history.is_empty() =>
+                {
+                    assert_eq!(history[0].winner, Some(PlayerTag::X));
+                    break leaders.clone();
+                }
+                _ => thread::sleep(POLL_INTERVAL),
+            }
+        };
+        let ratings: Vec<(&str, i32)> = leaders
+            .iter()
+            .map(|player| (player.name.as_str(), player.rating))
+            .collect();
+        // Bob's own account comes last
+        assert_eq!(ratings, [("alice", 1516), ("bob", 1484), ("bob", 1484)]);
+    }
+
+    /// A rated game drawn on time is rated and kept in the history too
+    #[test]
+    fn test_rated_draw() {
+        let config = ServerConfig {
+            quick_settings: RoomSettings {
+                timer: 1,
+                ..RoomSettings::default()
+            },
+            ..ServerConfig::default()
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, config));
+
+        let mut players = Vec::new();
+        for name in ["erin", "frank"] {
+            let mut player = greet(&address);
+            player
+                .send(&Message::Name {
+                    player: name.to_string(),
+                })
+                .unwrap();
+            player.send(&Message::QuickMatch).unwrap();
+            let mut messages = Vec::new();
+            receive(&mut player, &mut messages, 1);
+            players.push(player);
+        }
+        players[0].send(&Message::Move { cell: (0, 0) }).unwrap();
+        let mut messages = Vec::new();
+        receive_until(&mut players[0], &mut messages, |message| {
+            *message == Message::TimeUp(None)
+        });
+
+        let mut viewer = greet(&address);
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            viewer
+                .send(&Message::Leaders {
+                    player: "frank".to_string(),
+                })
+                .unwrap();
+            let mut answers = Vec::new();
+            receive(&mut viewer, &mut answers, 2);
+            match &answers[..] {
+                [Message::Leaderboard(leaders), Message::History(history)]
+                    if !history.is_empty() =>
+                {
+                    assert_eq!(history[0].winner, None);
+                    let frank = leaders.last().unwrap();
+                    assert_eq!((frank.rating, frank.draws), (1500, 1));
+                    break;
+                }
+                _ => thread::sleep(POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// A rated game cannot be restarted before it ends, and its result is
+    /// rated before an agreed restart clears it
+    #[test]
+    fn test_rated_restart() {
+        let config = ServerConfig {
+            quick_settings: RoomSettings {
+                target: 1,
+                ..RoomSettings::default()
+            },
+            ..ServerConfig::default()
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, config));
+
+        let mut players = Vec::new();
+        let mut received = [Vec::new(), Vec::new()];
+        for name in ["carol", "dave"] {
+            let mut player = greet(&address);
+            player
+                .send(&Message::Name {
+                    player: name.to_string(),
+                })
+                .unwrap();
+            player.send(&Message::QuickMatch).unwrap();
+            players.push(player);
+        }
+        for (player, messages) in players.iter_mut().zip(received.iter_mut()) {
+            receive_until(player, messages, |message| {
+                matches!(message, Message::Start(..))
+            });
+        }
+
+        // Dave cannot get out of the game by restarting it
+        players[1].send(&Message::Restart).unwrap();
+        receive_until(&mut players[1], &mut received[1], |message| {
+            matches!(message, Message::Invalid { .. })
+        });
+
+        // Carol wins, then both restart at once
+        for (index, cell) in [
+            (0, (0, 0)),
+            (1, (1, 0)),
+            (0, (0, 1)),
+            (1, (1, 1)),
+            (0, (0, 2)),
+        ] {
+            players[index].send(&Message::Move { cell }).unwrap();
+            receive_until(
+                &mut players[1 - index],
+                &mut received[1 - index],
+                |message| *message == Message::Move { cell },
+            );
+        }
+        assert!(!received[0].contains(&Message::Restart));
+        players[1].send(&Message::Restart).unwrap();
+        players[0].send(&Message::Restart).unwrap();
+        for (player, messages) in players.iter_mut().zip(received.iter_mut()) {
+            messages.clear();
+            receive_until(
+                player,
+                messages,
+                |message| matches!(message, Message::State(snapshot) if snapshot.moves == 0),
+            );
+        }
+
+        let mut viewer = greet(&address);
+        viewer
+            .send(&Message::Leaders {
+                player: "dave".to_string(),
+            })
+            .unwrap();
+        let mut answers = Vec::new();
+        receive(&mut viewer, &mut answers, 2);
+        let Message::History(history) = &answers[1] else {
+            panic!("got {:?}", answers);
+        };
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].winner, Some(PlayerTag::X));
+    }
+
+    /// Two quick matches run side by side, each relaying only its own moves
+    #[test]
+    fn test_concurrent_games() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, ServerConfig::default()));
+
+        let mut players = Vec::new();
+        let mut received = Vec::new();
+        for index in 0..4 {
+            let mut connection = greet(&address);
+            connection.send(&Message::QuickMatch).unwrap();
+            let mut messages = Vec::new();
+            // Wait for the room, so players are paired in this order
+            if index % 2 == 0 {
+                receive(&mut connection, &mut messages, 1);
+                assert_eq!(
+                    messages[0],
+                    Message::Room {
+                        room: index / 2 + 1
+                    }
+                );
+            }
+            players.push(connection);
+            received.push(messages);
+        }
+        for (index, (connection, messages)) in
+            players.iter_mut().zip(received.iter_mut()).enumerate()
+        {
+            let player = if index % 2 == 0 {
+                PlayerTurn::X
+            } else {
+                PlayerTurn::O
+            };
+            let start = messages.len();
+            // Start, session, state and clock
+            receive(connection, messages, start + 4);
+            assert_eq!(
+                messages[start],
+                Message::Start(player, RoomSettings::default())
+            );
+        }
+
+        // X of the second game, then O of the first game out of turn
+        players[2].send(&Message::Move { cell: (1, 1) }).unwrap();
+        players[1].send(&Message::Move { cell: (0, 0) }).unwrap();
+
+        let mut second_o = Vec::new();
+        receive(&mut players[3], &mut second_o, 2);
+        assert_eq!(second_o[0], Message::Move { cell: (1, 1) });
+        assert!(matches!(&second_o[1], Message::State(snapshot) if snapshot.moves == 1));
+
+        // The refused player is sent the game so far to take the move back
+        let mut first_o = Vec::new();
+        receive(&mut players[1], &mut first_o, 3);
+        assert_eq!(
+            first_o[..2],
+            [
+                Message::Invalid {
+                    reason: MoveError::NotYourTurn.to_string()
+                },
+                Message::Sync(GameRecord {
+                    moves: Vec::new(),
+                    elapsed: 0
+                }),
+            ]
+        );
+        assert!(matches!(&first_o[2], Message::State(snapshot) if snapshot.moves == 0));
+        assert!(players[0].poll().unwrap().is_empty());
+    }
+
+    /// A restart asked for by one player only reaches the opponent, the game
+    /// restarts for everyone once they agree
+    #[test]
+    fn test_restart_agreed() {
+        let (_, mut x, mut o, _) = start_game(ServerConfig::default());
+        x.send(&Message::Move { cell: (0, 0) }).unwrap();
+        x.send(&Message::Restart).unwrap();
+        let mut o_messages = Vec::new();
+        receive(&mut o, &mut o_messages, 4);
+        assert_eq!(o_messages[3], Message::Restart);
+
+        o.send(&Message::Restart).unwrap();
+        receive(&mut o, &mut o_messages, 6);
+        assert_eq!(o_messages[4], Message::Restart);
+        assert!(matches!(&o_messages[5], Message::State(snapshot) if snapshot.moves == 0));
+
+        // The state and clock of the move, then the agreed restart
+        let mut x_messages = Vec::new();
+        receive(&mut x, &mut x_messages, 4);
+        assert_eq!(x_messages[2], Message::Restart);
+        assert!(matches!(&x_messages[3], Message::State(snapshot) if snapshot.moves == 0));
+    }
+
+    /// A player who drops out takes their seat back and is sent the game so far
+    #[test]
+    fn test_reconnect() {
+        let (address, mut x, o, token) = start_game(ServerConfig::default());
+        x.send(&Message::Move { cell: (0, 0) }).unwrap();
+        drop(o);
+
+        // The state and clock of the move come first
+        let mut x_messages = Vec::new();
+        receive(&mut x, &mut x_messages, 3);
+        assert_eq!(x_messages[2], Message::Away { seconds: 60 });
+
+        let mut o = greet(&address);
+        o.send(&Message::Resume { token }).unwrap();
+        let mut o_messages = Vec::new();
+        receive(&mut o, &mut o_messages, 5);
+        assert_eq!(
+            o_messages[..3],
+            [
+                Message::Start(PlayerTurn::O, RoomSettings::default()),
+                Message::Session { token },
+                Message::Sync(GameRecord {
+                    moves: vec![(0, 0)],
+                    elapsed: 0
+                }),
+            ]
+        );
+        assert!(matches!(&o_messages[4], Message::Clock(reading) if reading.moves == 1));
+        receive(&mut x, &mut x_messages, 4);
+        assert_eq!(x_messages[3], Message::Back);
+
+        o.send(&Message::Move { cell: (1, 0) }).unwrap();
+        receive(&mut x, &mut x_messages, 5);
+        assert_eq!(x_messages[4], Message::Move { cell: (1, 0) });
+    }
+
+    /// A player who does not come back in time forfeits and their token is forgotten
+    #[test]
+    fn test_forfeit() {
+        let config = ServerConfig {
+            grace_period: Duration::from_millis(50),
+            ..ServerConfig::default()
+        };
+        let (address, mut x, o, token) = start_game(config);
+        drop(o);
+
+        let mut x_messages = Vec::new();
+        receive(&mut x, &mut x_messages, 2);
+        assert_eq!(x_messages, [Message::Away { seconds: 0 }, Message::Resign]);
+
+        let mut o = greet(&address);
+        o.send(&Message::Resume { token }).unwrap();
+        let mut o_messages = Vec::new();
+        receive(&mut o, &mut o_messages, 1);
+        assert!(matches!(o_messages[0], Message::Reject { .. }));
+    }
+
+    /// A spectator joins mid-game, follows the moves and cannot play
+    #[test]
+    fn test_spectator() {
+        let (address, mut x, mut o, _) = start_game(ServerConfig::default());
+        x.send(&Message::Move { cell: (0, 0) }).unwrap();
+        let mut o_messages = Vec::new();
+        receive(&mut o, &mut o_messages, 3);
+
+        let mut lobby = greet(&address);
+        lobby.send(&Message::List).unwrap();
+        let mut listed = Vec::new();
+        receive(&mut lobby, &mut listed, 2);
+        assert_eq!(
+            listed[1],
+            Message::Playing(vec![RoomInfo {
+                id: 1,
+                settings: RoomSettings::default()
+            }])
+        );
+
+        let mut spectator = Connection::connect(&address, Duration::from_secs(1)).unwrap();
+        spectator
+            .send(&Message::Watch {
+                version: PROTOCOL_VERSION,
+                game: 1,
+            })
+            .unwrap();
+        let mut watched = Vec::new();
+        receive(&mut spectator, &mut watched, 5);
+        assert_eq!(
+            watched[..2],
+            [
+                Message::Spectate(RoomSettings::default()),
+                Message::Sync(GameRecord {
+                    moves: vec![(0, 0)],
+                    elapsed: 0
+                }),
+            ]
+        );
+        assert!(matches!(&watched[3], Message::Clock(reading) if reading.moves == 1));
+        assert_eq!(watched[4], Message::Spectators { count: 1 });
+        receive(&mut o, &mut o_messages, 4);
+        assert_eq!(o_messages[3], Message::Spectators { count: 1 });
+
+        spectator.send(&Message::Move { cell: (1, 0) }).unwrap();
+        spectator.send(&Message::Ping { stamp: 7 }).unwrap();
+        receive(&mut spectator, &mut watched, 7);
+        assert!(matches!(watched[5], Message::Invalid { .. }));
+        assert_eq!(watched[6], Message::Pong { stamp: 7 });
+
+        o.send(&Message::Move { cell: (1, 0) }).unwrap();
+        receive(&mut spectator, &mut watched, 10);
+        assert_eq!(watched[7], Message::Move { cell: (1, 0) });
+        assert!(matches!(&watched[8], Message::State(snapshot) if snapshot.moves == 2));
+        assert!(matches!(&watched[9], Message::Clock(reading) if reading.moves == 2));
+    }
+
+    /// The server ends a game whose clock has run out for both players
+    #[test]
+    fn test_time_up() {
+        let config = ServerConfig {
+            quick_settings: RoomSettings {
+                timer: 1,
+                ..RoomSettings::default()
+            },
+            ..ServerConfig::default()
+        };
+        let (_, mut x, mut o, _) = start_game(config);
+        x.send(&Message::Move { cell: (0, 0) }).unwrap();
+        for connection in [&mut x, &mut o] {
+            let mut messages = Vec::new();
+            while !messages.contains(&Message::TimeUp(None)) {
+                let count = messages.len() + 1;
+                receive(connection, &mut messages, count);
+            }
+        }
+        // Too late, the game is over
+        o.send(&Message::Move { cell: (1, 0) }).unwrap();
+        let mut o_messages = Vec::new();
+        while !matches!(o_messages.last(), Some(Message::Invalid { .. })) {
+            let count = o_messages.len() + 1;
+            receive(&mut o, &mut o_messages, count);
+        }
+        assert_eq!(
+            o_messages.last(),
+            Some(&Message::Invalid {
+                reason: MoveError::GameOver.to_string()
+            })
+        );
+    }
+
+    /// Chat is cleaned and relayed to the opponent only, without a new state
+    #[test]
+    fn test_chat_relay() {
+        let (_, mut x, mut o, _) = start_game(ServerConfig::default());
+        x.send(&Message::Chat {
+            text: "  good luck ".to_string(),
+        })
+        .unwrap();
+        x.send(&Message::Emote(Emote::GoodGame)).unwrap();
+        x.send(&Message::Chat {
+            text: String::new(),
+        })
+        .unwrap();
+
+        let mut o_messages = Vec::new();
+        receive(&mut o, &mut o_messages, 2);
+        assert_eq!(
+            o_messages,
+            [
+                Message::Chat {
+                    text: "good luck".to_string()
+                },
+                Message::Emote(Emote::GoodGame)
+            ]
+        );
+        let mut x_messages = Vec::new();
+        receive(&mut x, &mut x_messages, 1);
+        assert!(matches!(x_messages[0], Message::Invalid { .. }));
+    }
+
+    /// Correspondence games are challenged, accepted and opened from the
+    /// lobby, and moves reach the opponent while both have the game open
+    #[test]
+    fn test_correspondence() {
+        let directory = std::env::temp_dir().join(format!("server_games_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let config = ServerConfig {
+            games_directory: Some(directory),
+            ..ServerConfig::default()
+        };
+        thread::spawn(move || serve(listener, config));
+        let player = |name: &str| name.to_string();
+
+        let mut alice = greet(&address);
+        alice
+            .send(&Message::Challenge {
+                player: player("alice"),
+                days: 3,
+            })
+            .unwrap();
+        let mut alice_messages = Vec::new();
+        receive(&mut alice, &mut alice_messages, 1);
+        let mut bob = greet(&address);
+        bob.send(&Message::Accept {
+            player: player("bob"),
+            game: 1,
+        })
+        .unwrap();
+        let mut bob_messages = Vec::new();
+        receive(&mut bob, &mut bob_messages, 1);
+        let Message::GameList(games) = &bob_messages[0] else {
+            panic!("got {:?}", bob_messages);
+        };
+        assert_eq!(games[0].o, Some(player("bob")));
+        assert!(games[0].due.is_some());
+
+        alice
+            .send(&Message::Open {
+                player: player("alice"),
+                game: 1,
+            })
+            .unwrap();
+        receive(&mut alice, &mut alice_messages, 4);
+        assert!(matches!(
+            alice_messages[1],
+            Message::Start(PlayerTurn::X, _)
+        ));
+        bob.send(&Message::Open {
+            player: player("bob"),
+            game: 1,
+        })
+        .unwrap();
+        receive(&mut bob, &mut bob_messages, 4);
+        assert!(matches!(bob_messages[1], Message::Start(PlayerTurn::O, _)));
+
+        alice.send(&Message::Move { cell: (0, 0) }).unwrap();
+        receive(&mut bob, &mut bob_messages, 6);
+        assert_eq!(bob_messages[4], Message::Move { cell: (0, 0) });
+        bob.send(&Message::Move { cell: (0, 0) }).unwrap();
+        receive(&mut bob, &mut bob_messages, 7);
+        assert!(matches!(bob_messages[6], Message::Invalid { .. }));
+
+        // Opening the game again rebuilds it from the saved moves
+        let mut carol = greet(&address);
+        carol
+            .send(&Message::Open {
+                player: player("bob"),
+                game: 1,
+            })
+            .unwrap();
+        let mut carol_messages = Vec::new();
+        receive(&mut carol, &mut carol_messages, 2);
+        assert!(matches!(&carol_messages[1], Message::Sync(record) if record.moves == [(0, 0)]));
+    }
+}
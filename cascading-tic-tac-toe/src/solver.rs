@@ -0,0 +1,429 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::{canonical_position, Bound, PlayerTurn, Position, TableEntry, TranspositionTable};
+
+/// Search algorithm used by the solver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    Exhaustive,  // Alpha-beta search of the full game tree
+    ProofNumber, // Proof-number search, proving a win and then a draw
+}
+
+/// Settings for a single solver run
+#[derive(Debug, Clone)]
+pub struct SolverConfig {
+    pub target: u32,     // Score that ends the game, as in RoundInit
+    pub max_rounds: u32, // Last round searched, games still open after it are adjudicated on score
+    pub mode: SearchMode,
+    pub node_limit: usize, // Maximum tree size for proof-number search
+}
+
+/// A constructor for SolverConfig
+impl SolverConfig {
+    pub fn new(target: u32, max_rounds: u32, mode: SearchMode) -> Self {
+        SolverConfig {
+            target,
+            max_rounds,
+            mode,
+            node_limit: 5_000_000,
+        }
+    }
+}
+
+/// Game-theoretic value of a position for the first player (X)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameValue {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// Conversion from the solver's internal score, seen from X
+impl From<i8> for GameValue {
+    fn from(score: i8) -> Self {
+        match score {
+            1 => GameValue::Win,
+            0 => GameValue::Draw,
+            _ => GameValue::Loss,
+        }
+    }
+}
+
+/// Formatting constructor for GameValue
+impl fmt::Display for GameValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameValue::Win => write!(f, "first player win"),
+            GameValue::Draw => write!(f, "draw"),
+            GameValue::Loss => write!(f, "first player loss"),
+        }
+    }
+}
+
+/// Result of a solver run, with the value of every distinct move from the solved position
+#[derive(Debug, Clone)]
+pub struct SolveReport {
+    pub config: SolverConfig,
+    pub value: Option<GameValue>, // None if the node limit was reached
+    pub first_moves: Vec<((u32, u32), Option<GameValue>)>,
+    pub nodes: u64,
+}
+
+/// Formatting constructor for SolveReport, used by the headless solver binary
+impl fmt::Display for SolveReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |value: &Option<GameValue>| match value {
+            Some(value) => value.to_string(),
+            None => "unresolved".to_string(),
+        };
+        writeln!(
+            f,
+            "target {}, rounds 0..={}, {:?} search",
+            self.config.target, self.config.max_rounds, self.config.mode
+        )?;
+        writeln!(f, "value: {}", value(&self.value))?;
+        writeln!(f, "nodes: {}", self.nodes)?;
+        writeln!(f, "first moves:")?;
+        for (cell, move_value) in self.first_moves.iter() {
+            let optimal = if self.value.is_some() && *move_value == self.value {
+                " (optimal)"
+            } else {
+                ""
+            };
+            writeln!(
+                f,
+                "  ({},{}) {}{}",
+                cell.0,
+                cell.1,
+                value(move_value),
+                optimal
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Node of the proof-number search tree
+struct ProofNode {
+    position: Position,
+    proof: u64,
+    disproof: u64,
+    children: Vec<usize>,
+    expanded: bool,
+}
+
+const INFINITY: u64 = u64::MAX / 2;
+
+/// Search state shared by both solver modes
+struct Solver {
+    config: SolverConfig,
+    table: TranspositionTable,
+    proven: HashMap<u64, bool>,
+    nodes: u64,
+}
+
+impl Solver {
+    /// Score of a finished game, from X's point of view
+    fn terminal(&self, position: &Position) -> Option<i8> {
+        if position.o_score() >= self.config.target {
+            Some(-1)
+        } else if position.x_score() >= self.config.target {
+            Some(1)
+        } else if position.round_count() > self.config.max_rounds {
+            Some((position.x_score() as i64 - position.o_score() as i64).signum() as i8)
+        } else {
+            None
+        }
+    }
+
+    /// Canonical hash of a position. Symmetries of the current layout do not
+    /// survive the next sub-board being added, so positions are only folded
+    /// in the last round the search looks at.
+    fn key(&self, position: &Position) -> u64 {
+        if position.round_count() < self.config.max_rounds {
+            return position.hash();
+        }
+        canonical_position(position).0.hash()
+    }
+
+    /// Successor positions, skipping moves that are symmetric to an earlier one
+    fn children(&self, position: &Position) -> Vec<((u32, u32), Position)> {
+        let mut seen = HashSet::new();
+        let mut children = Vec::new();
+        for cell in position.moves() {
+            let child = position.play(cell);
+            if position.round_count() < self.config.max_rounds || seen.insert(self.key(&child)) {
+                children.push((cell, child));
+            }
+        }
+        children
+    }
+
+    /// Alpha-beta negamax, scores are from the point of view of the player to move
+    fn negamax(&mut self, position: &Position, mut alpha: i8, mut beta: i8) -> i8 {
+        self.nodes += 1;
+        let sign = if *position.turn() == PlayerTurn::X {
+            1
+        } else {
+            -1
+        };
+        if let Some(score) = self.terminal(position) {
+            return sign * score;
+        }
+
+        let key = self.key(position);
+        let alpha_before = alpha;
+        if let Some(entry) = self.table.probe(key) {
+            let score = entry.score as i8;
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower => alpha = alpha.max(score),
+                Bound::Upper => beta = beta.min(score),
+            }
+            if alpha >= beta {
+                return score;
+            }
+        }
+
+        let mut best = -1;
+        let mut best_move = None;
+        for (cell, child) in self.children(position) {
+            let score = -self.negamax(&child, -beta, -alpha);
+            if best_move.is_none() || score > best {
+                best = score;
+                best_move = Some(cell);
+            }
+            alpha = alpha.max(best);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best <= alpha_before {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.store(TableEntry::new(
+            key,
+            u32::MAX,
+            best as i32,
+            bound,
+            best_move,
+        ));
+        best
+    }
+
+    /// Whether X can force a score of at least `goal` from the position.
+    /// Returns None when the tree grows past the node limit.
+    fn prove(&mut self, position: &Position, goal: i8) -> Option<bool> {
+        let mut tree = vec![self.proof_node(position.clone(), goal)];
+
+        while tree[0].proof != 0 && tree[0].disproof != 0 {
+            if tree.len() > self.config.node_limit {
+                return None;
+            }
+
+            // Walk down to the most-proving node
+            let mut path = vec![0];
+            let mut current = 0;
+            while tree[current].expanded {
+                let or_node = *tree[current].position.turn() == PlayerTurn::X;
+                current = *tree[current]
+                    .children
+                    .iter()
+                    .min_by_key(|&&child| {
+                        if or_node {
+                            tree[child].proof
+                        } else {
+                            tree[child].disproof
+                        }
+                    })
+                    .expect("expanded nodes are never terminal");
+                path.push(current);
+            }
+
+            // Expand it
+            for (_, child) in self.children(&tree[current].position) {
+                let node = self.proof_node(child, goal);
+                tree.push(node);
+                let index = tree.len() - 1;
+                tree[current].children.push(index);
+            }
+            tree[current].expanded = true;
+
+            // Back the numbers up to the root
+            for &index in path.iter().rev() {
+                let or_node = *tree[index].position.turn() == PlayerTurn::X;
+                let mut proofs = tree[index].children.iter().map(|&child| tree[child].proof);
+                let mut disproofs = tree[index]
+                    .children
+                    .iter()
+                    .map(|&child| tree[child].disproof);
+                let sum = |numbers: &mut dyn Iterator<Item = u64>| {
+                    numbers
+                        .fold(0, |sum: u64, number| sum.saturating_add(number))
+                        .min(INFINITY)
+                };
+                let (proof, disproof) = if or_node {
+                    (proofs.min().unwrap_or(INFINITY), sum(&mut disproofs))
+                } else {
+                    (sum(&mut proofs), disproofs.min().unwrap_or(INFINITY))
+                };
+                tree[index].proof = proof;
+                tree[index].disproof = disproof;
+                if proof == 0 || disproof == 0 {
+                    let key = self.key(&tree[index].position);
+                    self.proven.insert(key, proof == 0);
+                }
+            }
+        }
+        Some(tree[0].proof == 0)
+    }
+
+    /// Leaf of the proof-number tree, solved straight away if it is terminal or already proven
+    fn proof_node(&mut self, position: Position, goal: i8) -> ProofNode {
+        self.nodes += 1;
+        let solved = match self.terminal(&position) {
+            Some(score) => Some(score >= goal),
+            None => self.proven.get(&self.key(&position)).copied(),
+        };
+        let (proof, disproof) = match solved {
+            Some(true) => (0, INFINITY),
+            Some(false) => (INFINITY, 0),
+            None => (1, 1),
+        };
+        ProofNode {
+            position,
+            proof,
+            disproof,
+            children: Vec::new(),
+            expanded: false,
+        }
+    }
+
+    /// Value of a position for X using the configured search mode
+    fn value(&mut self, position: &Position) -> Option<GameValue> {
+        match self.config.mode {
+            SearchMode::Exhaustive => {
+                let sign = if *position.turn() == PlayerTurn::X {
+                    1
+                } else {
+                    -1
+                };
+                Some(GameValue::from(sign * self.negamax(position, -1, 1)))
+            }
+            SearchMode::ProofNumber => {
+                // Proven results depend on the goal, so each proof starts afresh
+                self.proven.clear();
+                if self.prove(position, 1)? {
+                    return Some(GameValue::Win);
+                }
+                self.proven.clear();
+                if self.prove(position, 0)? {
+                    Some(GameValue::Draw)
+                } else {
+                    Some(GameValue::Loss)
+                }
+            }
+        }
+    }
+}
+
+/// Solve the opening of a cascading game: the value for the first player
+/// under perfect play, and the value of each distinct first move
+pub fn solve(config: SolverConfig) -> SolveReport {
+    solve_position(&Position::new(), config)
+}
+
+/// Solve a position: its value for X when the player to move plays
+/// perfectly, and the value of each distinct move from it
+pub fn solve_position(position: &Position, config: SolverConfig) -> SolveReport {
+    let mut solver = Solver {
+        config: config.clone(),
+        table: TranspositionTable::default(),
+        proven: HashMap::new(),
+        nodes: 0,
+    };
+
+    let mut first_moves = Vec::new();
+    for (cell, child) in solver.children(position) {
+        first_moves.push((cell, solver.value(&child)));
+    }
+
+    // X prefers a win, O prefers a loss for X
+    let preference = |value: &GameValue| match (value, position.turn()) {
+        (GameValue::Draw, _) => 1,
+        (GameValue::Win, PlayerTurn::X) | (GameValue::Loss, PlayerTurn::O) => 0,
+        _ => 2,
+    };
+    let value = if first_moves.iter().any(|(_, value)| value.is_none()) {
+        None
+    } else {
+        first_moves
+            .iter()
+            .filter_map(|(_, value)| *value)
+            .min_by_key(preference)
+    };
+
+    SolveReport {
+        config,
+        value,
+        first_moves,
+        nodes: solver.nodes,
+    }
+}
+
+/// Unit tests for the solver
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    /// Round 0 with target 1 is plain tic-tac-toe, which is a draw
+    #[test_case(SearchMode::Exhaustive)]
+    #[test_case(SearchMode::ProofNumber)]
+    fn test_round_zero_is_draw(mode: SearchMode) {
+        let report = solve(SolverConfig::new(1, 0, mode));
+        assert_eq!(report.value, Some(GameValue::Draw));
+        // Corner, edge and centre are the only distinct openings
+        assert_eq!(report.first_moves.len(), 3);
+    }
+
+    /// Both search modes agree on every first move
+    #[test]
+    fn test_modes_agree() {
+        let exhaustive = solve(SolverConfig::new(1, 0, SearchMode::Exhaustive));
+        let proof_number = solve(SolverConfig::new(1, 0, SearchMode::ProofNumber));
+        assert_eq!(exhaustive.first_moves, proof_number.first_moves);
+    }
+
+    /// Past round 0 both search modes still agree on the value and on which
+    /// first moves are optimal
+    #[test]
+    fn test_modes_agree_in_round_one() {
+        // O completed the top row in round 0, so the board has grown and X moves
+        let position = [(2, 0), (0, 0), (2, 2), (0, 1), (1, 1), (0, 2)]
+            .iter()
+            .fold(Position::new(), |position, &cell| position.play(cell));
+        assert_eq!(position.round_count(), 1);
+
+        let config = |mode| SolverConfig::new(2, 1, mode);
+        let exhaustive = solve_position(&position, config(SearchMode::Exhaustive));
+        let proof_number = solve_position(&position, config(SearchMode::ProofNumber));
+        assert_eq!(exhaustive.value, Some(GameValue::Win));
+        assert_eq!(exhaustive.value, proof_number.value);
+        let optimal = |report: &SolveReport| -> Vec<(u32, u32)> {
+            report
+                .first_moves
+                .iter()
+                .filter(|(_, value)| *value == report.value)
+                .map(|(cell, _)| *cell)
+                .collect()
+        };
+        assert_eq!(optimal(&exhaustive), optimal(&proof_number));
+    }
+}
@@ -42,6 +42,8 @@ pub enum GameState {
     GameOngoing,  
     Updating,  
     Won(PlayerTag),
+    Draw, // Timed games with equal scores when the clock runs out, or endless games ended level
+    Paused, // Local games only, the clocks and board wait for the pause menu
 }
 
 /// State to keep track of the inner round state, which is either updating or not updating
@@ -53,15 +55,14 @@ pub enum RoundState {
 }
 
 /// State to keep track of gameplay arena
-/// Online and VsComputer are not implemented
 #[derive(Debug, Clone, Eq, PartialEq, Hash, States, Reflect, Default)]
 pub enum PlayingState {
     #[default]
     NotPlaying,
     Loading,
     Local,
-    Online, // TODO
-    VsComputer, // TODO
+    Online,
+    VsComputer,
 }
 
 /// State used for the start menu screen
@@ -72,8 +73,13 @@ pub enum MenuState {
     Round,
     RoundTarget,
     RoundTimer,
+    Paused,
     Settings,
     SettingsDisplay,
     SettingsSound,
+    Online,
+    Lobby,
+    Correspondence,
+    Leaderboard,
     Disabled,
 }
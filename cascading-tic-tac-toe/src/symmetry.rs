@@ -0,0 +1,199 @@
+use crate::Position;
+
+/// A map of the board onto itself that sends scoring combinations to scoring
+/// combinations, so positions related by it score identically this round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    Identity,
+    // Symmetries of sub-board 0, valid only before the board cascades
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    MirrorRows,
+    MirrorColumns,
+    MirrorDiagonal,
+    MirrorAntiDiagonal,
+    // Half-turn about the middle of the cascade diagonal, sending sub-board
+    // k onto sub-board n - k
+    CascadeHalfTurn,
+}
+
+/// Symmetries of sub-board 0
+const SUB_BOARD_SYMMETRIES: [Symmetry; 8] = [
+    Symmetry::Identity,
+    Symmetry::Rotate90,
+    Symmetry::Rotate180,
+    Symmetry::Rotate270,
+    Symmetry::MirrorRows,
+    Symmetry::MirrorColumns,
+    Symmetry::MirrorDiagonal,
+    Symmetry::MirrorAntiDiagonal,
+];
+
+/// Symmetries left once the board has cascaded
+const CASCADE_SYMMETRIES: [Symmetry; 2] = [Symmetry::Identity, Symmetry::CascadeHalfTurn];
+
+/// Symmetries of the layout after n rounds
+pub fn symmetries(n: u32) -> &'static [Symmetry] {
+    if n == 0 {
+        &SUB_BOARD_SYMMETRIES
+    } else {
+        &CASCADE_SYMMETRIES
+    }
+}
+
+impl Symmetry {
+    /// Image of a cell on the layout after n rounds
+    pub fn apply(&self, cell: (u32, u32), n: u32) -> (u32, u32) {
+        let (row, col) = cell;
+        match self {
+            Symmetry::Identity => (row, col),
+            Symmetry::Rotate90 => (col, 2 - row),
+            Symmetry::Rotate180 => (2 - row, 2 - col),
+            Symmetry::Rotate270 => (2 - col, row),
+            Symmetry::MirrorRows => (2 - row, col),
+            Symmetry::MirrorColumns => (row, 2 - col),
+            Symmetry::MirrorDiagonal => (col, row),
+            Symmetry::MirrorAntiDiagonal => (2 - col, 2 - row),
+            Symmetry::CascadeHalfTurn => (2 * n + 2 - row, n + 2 - col),
+        }
+    }
+
+    /// The symmetry that undoes this one
+    pub fn inverse(&self) -> Symmetry {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            symmetry => *symmetry,
+        }
+    }
+
+    /// Image of a whole position
+    pub fn transform(&self, position: &Position) -> Position {
+        let n = position.round_count();
+        position.transformed(|cell| self.apply(cell, n))
+    }
+}
+
+/// Canonical representative of a position under the symmetries of its current
+/// layout: the image with the smallest hash. Also returns the symmetry that
+/// maps the position onto it, so moves found for the representative can be
+/// mapped back with Symmetry::inverse.
+///
+/// The next sub-board is only added on one side of the cascade, so two
+/// positions folded together here score the same for the rest of the current
+/// round, but not necessarily after the board grows.
+pub fn canonical_position(position: &Position) -> (Position, Symmetry) {
+    symmetries(position.round_count())
+        .iter()
+        .map(|symmetry| (symmetry.transform(position), *symmetry))
+        .min_by_key(|(image, _)| image.hash())
+        .expect("symmetries always include the identity")
+}
+
+/// Unit tests for board symmetries
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate_winning_combinations;
+    use test_case::test_case;
+
+    /// Position reached by playing pseudo-random moves from the empty board
+    fn random_position(seed: u64, moves: usize) -> Position {
+        let mut state = seed;
+        let mut position = Position::new();
+        for _ in 0..moves {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let legal = position.moves();
+            position = position.play(legal[(state >> 33) as usize % legal.len()]);
+        }
+        position
+    }
+
+    /// Every symmetry sends the scoring combinations of its layout onto themselves
+    #[test_case(0)]
+    #[test_case(1)]
+    #[test_case(4)]
+    fn test_combinations_preserved(n: u32) {
+        let mut combinations = Vec::new();
+        generate_winning_combinations(n, &mut combinations);
+        let normalise = |combination: [(u32, u32); 3]| {
+            let mut combination = combination;
+            combination.sort();
+            combination
+        };
+        let mut expected: Vec<_> = combinations.iter().cloned().map(normalise).collect();
+        expected.sort();
+        for symmetry in symmetries(n) {
+            let mut mapped: Vec<_> = combinations
+                .iter()
+                .map(|combination| normalise(combination.map(|cell| symmetry.apply(cell, n))))
+                .collect();
+            mapped.sort();
+            assert_eq!(mapped, expected, "{:?}", symmetry);
+            for cell in combinations.iter().flatten() {
+                let image = symmetry.apply(*cell, n);
+                assert_eq!(symmetry.inverse().apply(image, n), *cell);
+            }
+        }
+    }
+
+    /// Playing a move and then applying a symmetry scores the same as
+    /// playing the mapped move on the mapped position
+    #[test_case(1, 4)]
+    #[test_case(2, 7)]
+    #[test_case(3, 15)]
+    #[test_case(4, 24)]
+    #[test_case(5, 40)]
+    fn test_scoring_invariant(seed: u64, moves: usize) {
+        let position = random_position(seed, moves);
+        let n = position.round_count();
+        for symmetry in symmetries(n) {
+            let image = symmetry.transform(&position);
+            for cell in position.moves() {
+                let played = position.play(cell);
+                let image_played = image.play(symmetry.apply(cell, n));
+                assert_eq!(played.x_score(), image_played.x_score(), "{:?}", symmetry);
+                assert_eq!(played.o_score(), image_played.o_score(), "{:?}", symmetry);
+                assert_eq!(
+                    played.round_count(),
+                    image_played.round_count(),
+                    "{:?}",
+                    symmetry
+                );
+                if played.round_count() == n {
+                    assert_eq!(symmetry.transform(&played).hash(), image_played.hash());
+                }
+            }
+        }
+    }
+
+    /// Symmetric positions share a canonical representative
+    #[test_case(6, 5)]
+    #[test_case(7, 20)]
+    #[test_case(8, 33)]
+    fn test_canonical_position(seed: u64, moves: usize) {
+        let position = random_position(seed, moves);
+        let (canonical, symmetry) = canonical_position(&position);
+        assert_eq!(symmetry.transform(&position).hash(), canonical.hash());
+        for symmetry in symmetries(position.round_count()) {
+            let (image_canonical, _) = canonical_position(&symmetry.transform(&position));
+            assert_eq!(image_canonical.hash(), canonical.hash());
+        }
+    }
+
+    /// The 9 openings of sub-board 0 fold into corner, edge and centre
+    #[test]
+    fn test_opening_classes() {
+        let mut classes: Vec<u64> = Position::new()
+            .moves()
+            .into_iter()
+            .map(|cell| canonical_position(&Position::new().play(cell)).0.hash())
+            .collect();
+        classes.sort();
+        classes.dedup();
+        assert_eq!(classes.len(), 3);
+    }
+}
@@ -4,45 +4,133 @@ use bevy::prelude::*;
 pub const TIME: u16 = 10*60;
 pub const TEXT_COLOR: Color = Color::WHITE;
 
-/// Component that stores dynamic countdown timer
-#[derive(Component)]
+/// Resource holding the seconds on the game clock, chosen on the timed game
+/// menu or set by an online room. Also marks its button on that menu.
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct TimeLimit(pub u32);
+
+/// Resource holding whether the game ends when the clock runs out, rather than at the target score
+#[derive(Resource, Default)]
+pub struct TimedMode(pub bool);
+
+/// Default constructor for TimeLimit
+impl Default for TimeLimit {
+    fn default() -> Self {
+        TimeLimit(TIME.into())
+    }
+}
+
+/// Seconds of extra time played in overtime
+pub const OVERTIME: u32 = 60;
 
-pub struct Counter{
-    pub seconds: Timer,
-    pub unit: Timer,
+/// What a timed game that ends with equal scores goes on to. Chosen on the
+/// timed game menu, where it also marks the tie rule buttons.
+#[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Default)]
+pub enum TieRule {
+    #[default]
+    Draw,
+    /// The next scored line wins
+    SuddenDeath,
+    /// OVERTIME more seconds are played, then equal scores draw
+    Overtime,
+    /// One fresh sub-board is played, a line on it wins
+    TiebreakRound,
 }
 
-/// A constructor for the Counter component
-impl Counter{
-    pub fn new() -> Counter {
+/// Tie rules offered on the timed game menu, with their button labels
+pub const TIE_RULES: [(TieRule, &str); 4] = [
+    (TieRule::Draw, "Draw"),
+    (TieRule::SuddenDeath, "Sudden"),
+    (TieRule::Overtime, "Overtime"),
+    (TieRule::TiebreakRound, "Tiebreak"),
+];
+
+/// Resource holding the tie rule being played, once a timed game has run out level
+#[derive(Resource, Default)]
+pub struct TieBreak(pub Option<TieRule>);
+
+/// Resource holding the game clock. It runs through every round of a game,
+/// so only starting a new game or restarting winds it back.
+#[derive(Resource)]
+pub struct GameClock{
+    seconds: Timer,
+}
+
+/// Default constructor for GameClock
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock::new(TIME.into())
+    }
+}
+
+impl GameClock{
+    pub fn new(seconds: u32) -> GameClock {
         Self {
-            seconds: Timer::from_seconds(TIME.into(), TimerMode::Repeating),
-            unit: Timer::from_seconds(1.,TimerMode::Repeating)
+            seconds: Timer::from_seconds(seconds as f32, TimerMode::Once),
         }
     }
     pub fn pause(&mut self){
         self.seconds.pause();
-        self.unit.pause();
     }
     pub fn paused(&self) -> bool{
-        self.seconds.paused() && self.unit.paused()
+        self.seconds.paused()
+    }
+    pub fn unpause(&mut self){
+        self.seconds.unpause();
+    }
+    /// Wind the clock to `elapsed`, as read from the clock that keeps time online
+    pub fn set_elapsed(&mut self, elapsed: Duration){
+        self.seconds.set_elapsed(elapsed.min(self.duration()));
     }
     pub fn tick(&mut self, duration: Duration) {
         self.seconds.tick(duration);
-        self.unit.tick(duration);
     }
-
-
-    pub fn unit_just_finished(&self) -> bool{
-        self.unit.just_finished()
-
+    pub fn just_finished(&self) -> bool{
+        self.seconds.just_finished()
     }
     pub fn duration(&self) -> Duration{
         self.seconds.duration()
     }
+    pub fn elapsed(&self) -> Duration{
+        self.seconds.elapsed()
+    }
     pub fn elapsed_secs_round(&self) -> f32{
         self.seconds.elapsed_secs().round()
     }
+    pub fn remaining(&self) -> Duration{
+        self.duration().saturating_sub(Duration::from_secs_f32(self.elapsed_secs_round()))
+    }
+    /// Add `extra` time to a clock, which runs again if it had run out
+    pub fn extend(&mut self, extra: Duration){
+        let elapsed = self.elapsed();
+        self.seconds = Timer::new(self.duration() + extra, TimerMode::Once);
+        self.seconds.set_elapsed(elapsed);
+    }
+}
+
+/// Tag component for the text that shows the game clock
+#[derive(Component)]
+pub struct ClockText;
+
+/// System that winds the game clock back to the time limit when a game
+/// is started or restarted, before any tie has to be broken
+pub fn reset_clock(
+    mut clock: ResMut<GameClock>,
+    time_limit: Res<TimeLimit>,
+    mut tie_break: ResMut<TieBreak>,
+) {
+    *clock = GameClock::new(time_limit.0);
+    tie_break.0 = None;
+}
+
+/// System that shows the game clock on the game screen
+pub fn update_clock_text(clock: Res<GameClock>, mut query: Query<&mut Text, With<ClockText>>) {
+    let label = time(clock.remaining());
+    for mut text in &mut query {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.clone();
+        }
+    }
 }
 
 /// System to dynamically update the time displayed to screen
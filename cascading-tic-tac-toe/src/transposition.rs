@@ -0,0 +1,124 @@
+use bevy::prelude::Resource;
+
+/// How a stored score relates to the true value of the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower, // the search failed high, the value is at least the score
+    Upper, // the search failed low, the value is at most the score
+}
+
+/// Result of searching one position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableEntry {
+    pub hash: u64,  // Full Zobrist hash, to tell apart positions sharing a slot
+    pub depth: u32, // Plies searched below the position, u32::MAX if solved to the end
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<(u32, u32)>,
+    age: u8,
+}
+
+/// A constructor for TableEntry
+impl TableEntry {
+    pub fn new(
+        hash: u64,
+        depth: u32,
+        score: i32,
+        bound: Bound,
+        best_move: Option<(u32, u32)>,
+    ) -> Self {
+        TableEntry {
+            hash,
+            depth,
+            score,
+            bound,
+            best_move,
+            age: 0,
+        }
+    }
+}
+
+/// A resource holding a fixed-size transposition table keyed by Position::hash,
+/// so every search run during a game shares what earlier searches found
+#[derive(Resource)]
+pub struct TranspositionTable {
+    entries: Vec<Option<TableEntry>>,
+    age: u8,
+}
+
+impl TranspositionTable {
+    /// Table with room for `capacity` entries, rounded up to a power of two
+    pub fn new(capacity: usize) -> Self {
+        TranspositionTable {
+            entries: vec![None; capacity.max(1).next_power_of_two()],
+            age: 0,
+        }
+    }
+
+    /// Mark the start of a new search, entries from older searches are replaced first
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    /// Entry stored for a position, if it has not been overwritten
+    pub fn probe(&self, hash: u64) -> Option<&TableEntry> {
+        self.entries[self.slot(hash)]
+            .as_ref()
+            .filter(|entry| entry.hash == hash)
+    }
+
+    /// Store a search result. A slot keeps the deeper of two results for the
+    /// current search, but anything left from an older search is replaced.
+    pub fn store(&mut self, mut entry: TableEntry) {
+        entry.age = self.age;
+        let slot = self.slot(entry.hash);
+        let replace = match &self.entries[slot] {
+            None => true,
+            Some(stored) => {
+                stored.hash == entry.hash || stored.age != self.age || entry.depth >= stored.depth
+            }
+        };
+        if replace {
+            self.entries[slot] = Some(entry);
+        }
+    }
+
+    /// Remove every entry, used when a new game starts
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+
+    /// Slot of a hash, the table size is a power of two
+    fn slot(&self, hash: u64) -> usize {
+        (hash as usize) & (self.entries.len() - 1)
+    }
+}
+
+/// Default constructor for TranspositionTable, about 32 MB
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(1 << 20)
+    }
+}
+
+/// Unit tests for the transposition table
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Colliding hashes keep the deeper entry within one search only
+    #[test]
+    fn test_replacement() {
+        let mut table = TranspositionTable::new(4);
+        table.store(TableEntry::new(1, 5, 10, Bound::Exact, None));
+        table.store(TableEntry::new(5, 2, 20, Bound::Exact, None));
+        assert_eq!(table.probe(1).map(|entry| entry.score), Some(10));
+        assert!(table.probe(5).is_none());
+
+        table.new_search();
+        table.store(TableEntry::new(5, 2, 20, Bound::Exact, None));
+        assert!(table.probe(1).is_none());
+        assert_eq!(table.probe(5).map(|entry| entry.score), Some(20));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{rank_moves, EvalWeights, Position, TranspositionTable, FEATURE_COUNT};
+
+/// Index of the score margin weight, which stays fixed to set the scale
+const MARGIN: usize = FEATURE_COUNT - 1;
+
+/// Settings of a tuning run
+#[derive(Debug, Clone)]
+pub struct TuningConfig {
+    pub generations: u32, // Candidates tried
+    pub games: u32,       // Games per candidate, half with each colour
+    pub depth: u32,       // Search depth of both players
+    pub node_limit: u64,  // Search nodes per move
+    pub target: u32,      // Score that wins a game
+    pub max_plies: u32,   // Games still going after this many moves are decided on score
+    pub step: i32,        // Largest change to a weight in one generation
+    pub seed: u64,        // Seed for the openings and the mutations
+}
+
+/// Default constructor for TuningConfig
+impl Default for TuningConfig {
+    fn default() -> Self {
+        TuningConfig {
+            generations: 100,
+            games: 32,
+            depth: 3,
+            node_limit: 20_000,
+            target: 3,
+            max_plies: 60,
+            step: 40,
+            seed: 1,
+        }
+    }
+}
+
+/// Linear congruential generator, so a run depends only on its seed
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    /// Uniform integer in -bound..=bound
+    fn symmetric(&mut self, bound: i32) -> i32 {
+        (self.next() % (2 * bound as u64 + 1)) as i32 - bound
+    }
+}
+
+/// Play one game from `opening`, 1 if X wins, -1 if O wins and 0 for a draw
+pub fn play_game(
+    x: &EvalWeights,
+    o: &EvalWeights,
+    opening: &[(u32, u32)],
+    config: &TuningConfig,
+) -> i32 {
+    // Scores stored in a table depend on the weights, so each side keeps its own
+    let mut tables = [
+        TranspositionTable::new(1 << 16),
+        TranspositionTable::new(1 << 16),
+    ];
+    let mut position = opening
+        .iter()
+        .fold(Position::new(), |position, &cell| position.play(cell));
+
+    for ply in opening.len() as u32..config.max_plies {
+        if position.x_score() >= config.target || position.o_score() >= config.target {
+            break;
+        }
+        let (weights, table) = if ply % 2 == 0 {
+            (x, &mut tables[0])
+        } else {
+            (o, &mut tables[1])
+        };
+        let ranked = rank_moves(
+            &position,
+            config.target,
+            config.depth,
+            config.node_limit,
+            weights,
+            table,
+        );
+        position = position.play(ranked[0].0);
+    }
+    (position.x_score() as i32 - position.o_score() as i32).signum()
+}
+
+/// Net games won by `candidate` against `champion`. Every opening is played
+/// twice with the colours swapped, so neither side gains from moving first.
+pub fn play_match(
+    candidate: &EvalWeights,
+    champion: &EvalWeights,
+    config: &TuningConfig,
+    seed: u64,
+) -> i32 {
+    let mut rng = Lcg(seed);
+    let mut result = 0;
+    for _ in 0..config.games.div_ceil(2) {
+        // Two random moves, so the deterministic players meet in different games
+        let mut opening = Vec::new();
+        let mut position = Position::new();
+        for _ in 0..2 {
+            let moves = position.moves();
+            let cell = moves[rng.next() as usize % moves.len()];
+            position = position.play(cell);
+            opening.push(cell);
+        }
+        result += play_game(candidate, champion, &opening, config);
+        result -= play_game(champion, candidate, &opening, config);
+    }
+    result
+}
+
+/// Tune evaluation weights by self-play with a (1+1) evolution strategy: each
+/// generation mutates the current weights and keeps the mutation only if it
+/// wins a match against them. The margin weight is never changed, since
+/// scaling every weight together gives the same bot.
+/// `report` is handed each generation, its candidate, the candidate's match
+/// result and the weights it played against.
+pub fn tune(
+    start: &EvalWeights,
+    config: &TuningConfig,
+    mut report: impl FnMut(u32, &EvalWeights, i32, &EvalWeights),
+) -> EvalWeights {
+    let mut rng = Lcg(config.seed);
+    let mut champion = start.clone();
+    for generation in 0..config.generations {
+        let mut candidate = champion.clone();
+        for (index, weight) in candidate.0.iter_mut().enumerate() {
+            if index != MARGIN {
+                *weight += rng.symmetric(config.step);
+            }
+        }
+        let result = play_match(&candidate, &champion, config, rng.next());
+        report(generation, &candidate, result, &champion);
+        if result > 0 {
+            champion = candidate;
+        }
+    }
+    champion
+}
+
+/// Unit tests for weight tuning
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The same seed always tunes to the same weights
+    #[test]
+    fn test_tuning_is_deterministic() {
+        let config = TuningConfig {
+            generations: 3,
+            games: 2,
+            depth: 1,
+            max_plies: 20,
+            ..TuningConfig::default()
+        };
+        let start = EvalWeights::default();
+        let mut generations = 0;
+        let tuned = tune(&start, &config, |_, _, _, _| generations += 1);
+        assert_eq!(generations, config.generations);
+        assert_eq!(tune(&start, &config, |_, _, _, _| ()), tuned);
+        assert_eq!(tuned.0[MARGIN], start.0[MARGIN]);
+    }
+}
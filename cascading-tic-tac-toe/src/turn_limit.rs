@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+
+use crate::{
+    choose_move, on_cell_clicked, update_player_turn, BoardGrid, Bot, CellClickedEvent,
+    EvalWeights, GameScreenTag, GameState, OpeningBook, PlayerTag, PlayerTurn, PlayingState,
+    Position, RoundInit, RoundState, StateWrapper, TranspositionTable, TEXT_COLOR,
+};
+
+/// Seconds each move may take, 0 for no limit. Chosen on the new game
+/// menu, where it also marks the limit buttons.
+#[derive(Resource, Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TurnLimit(pub u32);
+
+/// What happens to a player who runs out of time for a move. Chosen on the
+/// new game menu, where it also marks the penalty buttons.
+#[derive(Resource, Component, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeoutPenalty {
+    SkipTurn,
+    #[default]
+    RandomMove,
+    BotMove,
+    Forfeit,
+}
+
+/// Move limits offered on the new game menu, in seconds
+pub const TURN_LIMITS: [u32; 4] = [0, 5, 10, 20];
+
+/// Penalties offered on the new game menu, with their button labels
+pub const TIMEOUT_PENALTIES: [(TimeoutPenalty, &str); 4] = [
+    (TimeoutPenalty::SkipTurn, "Skip"),
+    (TimeoutPenalty::RandomMove, "Random"),
+    (TimeoutPenalty::BotMove, "Computer"),
+    (TimeoutPenalty::Forfeit, "Forfeit"),
+];
+
+/// Resource holding the time left for the current move, when moves have a limit
+#[derive(Resource, Default)]
+pub struct TurnTimer(Option<Timer>);
+
+/// Tag component for the bar that shrinks as the time for a move runs out
+#[derive(Component)]
+pub struct TurnBar;
+
+/// Struct for the per-move time limit
+pub struct TurnLimitPlugin;
+
+/// Plugin that times every move and plays the penalty when one takes too long
+impl Plugin for TurnLimitPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TurnLimit>()
+            .init_resource::<TimeoutPenalty>()
+            .init_resource::<TurnTimer>()
+            .add_systems(OnEnter(GameState::LoadingNewGame), reset_turn_timer)
+            .add_systems(
+                Update,
+                (run_turn_timer.before(on_cell_clicked), update_turn_bar)
+                    .chain()
+                    .run_if(in_state(GameState::GameOngoing)),
+            );
+    }
+}
+
+/// System that sets up the move timer when a game is started or restarted.
+/// Online games are timed by their room instead.
+fn reset_turn_timer(
+    mut turn_timer: ResMut<TurnTimer>,
+    limit: Res<TurnLimit>,
+    playing_state: Res<State<PlayingState>>,
+) {
+    let online = *playing_state.get() == PlayingState::Online;
+    turn_timer.0 =
+        (!online && limit.0 > 0).then(|| Timer::from_seconds(limit.0 as f32, TimerMode::Once));
+}
+
+/// System that runs the timer of the move being played, restarting it on
+/// every turn, and plays the penalty when it runs out
+#[allow(clippy::too_many_arguments)]
+fn run_turn_timer(
+    mut turn_timer: ResMut<TurnTimer>,
+    penalty: Res<TimeoutPenalty>,
+    time: Res<Time>,
+    player_turn: Res<State<PlayerTurn>>,
+    next_player_turn: ResMut<NextState<PlayerTurn>>,
+    mut next_game_state: ResMut<NextState<GameState>>,
+    mut next_round_state: ResMut<NextState<RoundState>>,
+    grid: Res<BoardGrid>,
+    mut send_cell_clicked: EventWriter<CellClickedEvent>,
+    round_init: Res<RoundInit>,
+    bot: Res<Bot>,
+    book: Res<OpeningBook>,
+    weights: Res<EvalWeights>,
+    mut table: ResMut<TranspositionTable>,
+) {
+    let Some(timer) = turn_timer.0.as_mut() else {
+        return;
+    };
+    if player_turn.is_changed() {
+        timer.reset();
+    }
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    // The next turn starts the timer again
+    timer.reset();
+
+    let cell = match *penalty {
+        TimeoutPenalty::SkipTurn | TimeoutPenalty::Forfeit => None,
+        TimeoutPenalty::RandomMove => {
+            let valid: Vec<(u32, u32)> = grid.valid_cells().collect();
+            // Close enough to random for picking a move
+            let pick = time.elapsed().subsec_nanos() as usize;
+            valid.get(pick % valid.len().max(1)).copied()
+        }
+        TimeoutPenalty::BotMove => {
+            let position = Position::from_board(&grid, &round_init, player_turn.get());
+            choose_move(
+                &position,
+                round_init.target,
+                &bot,
+                &book,
+                &weights,
+                &mut table,
+            )
+            .map(|chosen| chosen.cell)
+        }
+    };
+
+    if let Some(cell) = cell {
+        send_cell_clicked.send(CellClickedEvent {
+            cell,
+            book_move: false,
+        });
+    } else if *penalty == TimeoutPenalty::Forfeit {
+        next_round_state.set(RoundState::NotUpdating);
+        next_game_state.set(GameState::Won(match player_turn.get() {
+            PlayerTurn::X => PlayerTag::O,
+            PlayerTurn::O => PlayerTag::X,
+        }));
+    } else {
+        // Skipped, or no move was found to play
+        update_player_turn(&mut StateWrapper {
+            current: player_turn.get().clone(),
+            next: next_player_turn,
+        });
+    }
+}
+
+/// System that shows the time left for the move as a bar across the top of
+/// the game screen, which is rebuilt every round
+fn update_turn_bar(
+    mut commands: Commands,
+    turn_timer: Res<TurnTimer>,
+    mut bars: Query<&mut Style, With<TurnBar>>,
+) {
+    let Some(timer) = &turn_timer.0 else {
+        return;
+    };
+    let width = Val::Percent(100.0 * timer.fraction_remaining());
+    if bars.is_empty() {
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    width,
+                    height: Val::Px(8.0),
+                    ..default()
+                },
+                background_color: TEXT_COLOR.into(),
+                ..default()
+            },
+            TurnBar,
+            GameScreenTag,
+        ));
+        return;
+    }
+    for mut style in bars.iter_mut() {
+        style.width = width;
+    }
+}
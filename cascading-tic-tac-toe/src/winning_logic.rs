@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{CellState, GameState, GridCell, PlayerTag, RoundInit, RoundState};
+use crate::{BoardGrid, CellState, EndlessMode, GameState, MoveLog, PlayerTag, RoundInit, RoundState, TimedMode};
 
 /// Struct for handling winning logic in tic-tac-toe game
 pub struct WinningLogicPlugin;
@@ -19,12 +19,18 @@ impl Plugin for WinningLogicPlugin {
 }
 
 /// System that checks whether the entire game is over 
-/// Valid for target score mode only
+/// Valid for target score mode only, timed games end with the clock
+/// and endless games from the menu
 fn is_game_over(
     round: Res<RoundInit>,
+    timed: Res<TimedMode>,
+    endless: Res<EndlessMode>,
     mut next_game_state: ResMut<NextState<GameState>>,
     mut next_round_state: ResMut<NextState<RoundState>>,
 ) {
+    if timed.0 || endless.0 {
+        return;
+    }
     if round.x_score >= round.target {
         next_round_state.set(RoundState::NotUpdating);
         next_game_state.set(GameState::Won(PlayerTag::X));
@@ -36,30 +42,38 @@ fn is_game_over(
 }
 
 /// System for checking if a round is over by scanning for winning combination
-/// or a draw (all cells are filled, no remaining valid moves)
-fn is_round_over(
-    cells_query: Query<&GridCell>,
+/// or a draw (all cells are filled, no remaining valid moves).
+/// Only combinations through moves played since the last check can have been
+/// completed, so long games do not rescan the whole board every frame.
+pub(crate) fn is_round_over(
+    grid: Res<BoardGrid>,
+    move_log: Res<MoveLog>,
+    mut checked_moves: Local<usize>,
     mut update_game: ResMut<NextState<GameState>>,
     mut update_round: ResMut<NextState<RoundState>>,
     mut round_init: ResMut<RoundInit>,
 ) {
-    // Collect the states of all cells into a vector
+    // The log is cleared when the game restarts
+    if move_log.moves.len() < *checked_moves {
+        *checked_moves = 0;
+    }
+    if move_log.moves.len() == *checked_moves {
+        return;
+    }
     let n = round_init.round_count;
-    let grid_size = (2 * n + 3) * (n + 3);
-
-    let mut cells = vec![CellState::Valid; grid_size as usize];
-    for cell in cells_query.iter() {
-        let cell_id: usize = ((n + 3) * cell.cell_coord.0 + cell.cell_coord.1)
-            .try_into()
-            .unwrap();
-        cells[cell_id] = cell.state.clone();
+    let mut candidates = Vec::new();
+    for logged in &move_log.moves[*checked_moves..] {
+        generate_cell_combinations(logged.cell, n, &mut candidates);
     }
+    candidates.sort();
+    candidates.dedup();
+    *checked_moves = move_log.moves.len();
 
     let x_before = round_init.x_score;
     let o_before = round_init.o_score;
 
     // Check if player X has won
-    while is_winner(&cells, n, PlayerTag::X, &mut round_init.game_combinations) {
+    while is_winner(&grid, PlayerTag::X, &candidates, &mut round_init.game_combinations) {
         update_game.set(GameState::Updating);
         round_init.x_score += 1;
         round_init.round_count += 1;
@@ -67,7 +81,7 @@ fn is_round_over(
     }
     
     // Check if player O has won
-    while is_winner(&cells, n, PlayerTag::O, &mut round_init.game_combinations) {
+    while is_winner(&grid, PlayerTag::O, &candidates, &mut round_init.game_combinations) {
         update_game.set(GameState::Updating);
         round_init.o_score += 1;
         round_init.round_count += 1;
@@ -79,14 +93,14 @@ fn is_round_over(
     
     // TODO: REFACTOR
     // TODO: Check if the game is a draw, optimize to exclude cells that cannot be combos
-    if is_draw(&cells) && x_before == x_after && o_before == o_after {
+    if grid.is_full() && x_before == x_after && o_before == o_after {
         round_init.round_count += 1;
         update_round.set(RoundState::UpdatingRound)
     }
 }
 
 /// Algorithm to ensure that 4+ in a row is not scored as a valid combination
-fn has_two_tuples(
+pub(crate) fn has_two_tuples(
     game_combinations: &mut Vec<[(u32, u32); 3]>,
     winning_combination: &[(u32, u32); 3],
 ) -> bool {
@@ -124,19 +138,17 @@ fn has_two_tuples(
 //     false
 // }
 
-/// Check if a player has scored a winning combination
-fn is_winner(
-    cells: &Vec<CellState>,
-    n: u32,
+/// Check if a player has scored one of the candidate winning combinations
+pub(crate) fn is_winner(
+    grid: &BoardGrid,
     player: PlayerTag,
+    candidates: &[[(u32, u32); 3]],
     game_combinations: &mut Vec<[(u32, u32); 3]>,
 ) -> bool {
     let state = CellState::Filled(player);
 
-    let mut winning_combinations: Vec<[(u32, u32); 3]> = Vec::new();
-    generate_winning_combinations(n, &mut winning_combinations);
-    // Iterate over all winning combinations
-    for winning_combination in winning_combinations {
+    // Iterate over the candidate combinations
+    for &winning_combination in candidates {
         let mut all_match = true;
 
         if game_combinations.contains(&winning_combination)
@@ -145,10 +157,8 @@ fn is_winner(
             continue; // Skip to the next combination
         }
 
-        for cell in winning_combination.iter() {
-            let index = get_index(cell.0, cell.1, n + 3);
-
-            if cells[index] != state {
+        for &cell in winning_combination.iter() {
+            if grid.get(cell) != state {
                 all_match = false;
                 break;
             }
@@ -164,54 +174,67 @@ fn is_winner(
 }
 
 /// Algorithm to generate all valid winning combinations based on the number of rounds played, n
-fn generate_winning_combinations(round_init: u32, winners: &mut Vec<[(u32, u32); 3]>) {
+pub(crate) fn generate_winning_combinations(round_init: u32, winners: &mut Vec<[(u32, u32); 3]>) {
     for n in 0..=round_init {
-        // horizontal
-        winners.push([(2 * n, n), (2 * n, n + 1), (2 * n, n + 2)]);
-        winners.push([(2 * n + 1, n), (2 * n + 1, n + 1), (2 * n + 1, n + 2)]);
-        winners.push([(2 * n + 2, n), (2 * n + 2, n + 1), (2 * n + 2, n + 2)]);
-        // vertical
-        winners.push([(2 * n, n), (2 * n + 1, n), (2 * n + 2, n)]);
-        winners.push([(2 * n, n + 1), (2 * n + 1, n + 1), (2 * n + 2, n + 1)]);
-        winners.push([(2 * n, n + 2), (2 * n + 1, n + 2), (2 * n + 2, n + 2)]);
-        // diagonals
-        winners.push([(2 * n, n), (2 * n + 1, n + 1), (2 * n + 2, n + 2)]);
-        winners.push([(2 * n, n + 2), (2 * n + 1, n + 1), (2 * n + 2, n)]);
-        if n > 0 {
-            // reach-back
-            winners.push([(2 * n - 2, n), (2 * n - 1, n + 1), (2 * n, n + 2)]);
-            winners.push([(2 * n - 1, n), (2 * n, n + 1), (2 * n + 1, n + 2)]);
-            winners.push([(2 * n - 1, n - 1), (2 * n, n), (2 * n + 1, n + 1)]);
-            winners.push([(2 * n, n - 1), (2 * n + 1, n), (2 * n + 2, n + 1)]);
-            winners.push([(2 * n - 1, n), (2 * n, n), (2 * n + 1, n)]);
-            winners.push([(2 * n - 1, n + 1), (2 * n, n + 1), (2 * n + 1, n + 1)]);
-        }
+        generate_sub_board_combinations(n, winners);
     }
 }
 
-/// System to convert (x,y) coordinate to integer id based on the number of columns in the full map
-fn get_index(x: u32, y: u32, num_cols: u32) -> usize {
-    let index = (x * num_cols) + y;
-    index as usize // Cast to usize if needed
+/// Algorithm to generate the winning combinations added by sub-board n,
+/// including the reach-back combinations shared with sub-board n - 1
+pub(crate) fn generate_sub_board_combinations(n: u32, winners: &mut Vec<[(u32, u32); 3]>) {
+    // horizontal
+    winners.push([(2 * n, n), (2 * n, n + 1), (2 * n, n + 2)]);
+    winners.push([(2 * n + 1, n), (2 * n + 1, n + 1), (2 * n + 1, n + 2)]);
+    winners.push([(2 * n + 2, n), (2 * n + 2, n + 1), (2 * n + 2, n + 2)]);
+    // vertical
+    winners.push([(2 * n, n), (2 * n + 1, n), (2 * n + 2, n)]);
+    winners.push([(2 * n, n + 1), (2 * n + 1, n + 1), (2 * n + 2, n + 1)]);
+    winners.push([(2 * n, n + 2), (2 * n + 1, n + 2), (2 * n + 2, n + 2)]);
+    // diagonals
+    winners.push([(2 * n, n), (2 * n + 1, n + 1), (2 * n + 2, n + 2)]);
+    winners.push([(2 * n, n + 2), (2 * n + 1, n + 1), (2 * n + 2, n)]);
+    if n > 0 {
+        // reach-back
+        winners.push([(2 * n - 2, n), (2 * n - 1, n + 1), (2 * n, n + 2)]);
+        winners.push([(2 * n - 1, n), (2 * n, n + 1), (2 * n + 1, n + 2)]);
+        winners.push([(2 * n - 1, n - 1), (2 * n, n), (2 * n + 1, n + 1)]);
+        winners.push([(2 * n, n - 1), (2 * n + 1, n), (2 * n + 2, n + 1)]);
+        winners.push([(2 * n - 1, n), (2 * n, n), (2 * n + 1, n)]);
+        winners.push([(2 * n - 1, n + 1), (2 * n, n + 1), (2 * n + 1, n + 1)]);
+    }
 }
 
-/// Check if the game is a draw (no remaining valid moves)
-fn is_draw(cells: &Vec<CellState>) -> bool {
-    // If there are no Valid cells left, the game is a draw
-    !cells.iter().any(|element| *element == CellState::Valid)
+/// Algorithm to generate the winning combinations through a cell of the
+/// board after n rounds, from the few sub-boards that can reach it
+pub(crate) fn generate_cell_combinations(
+    cell: (u32, u32),
+    n: u32,
+    winners: &mut Vec<[(u32, u32); 3]>,
+) {
+    let mut combinations = Vec::new();
+    for sub_board in cell.0.saturating_sub(1) / 2..=(cell.0 / 2 + 1).min(n) {
+        generate_sub_board_combinations(sub_board, &mut combinations);
+    }
+    winners.extend(combinations.into_iter().filter(|combination| combination.contains(&cell)));
 }
 
 /// Unit tests for the winning logic functions
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cell_coord;
     use test_case::test_case;
 
-    /// Test cases for the `is_draw` function
-    #[test_case(vec![CellState::Filled(PlayerTag::X), CellState::Filled(PlayerTag::O)], true)]
-    #[test_case(vec![CellState::Filled(PlayerTag::X), CellState::Valid], false)]
-    fn test_is_draw(input: Vec<CellState>, expected: bool) {
-        assert_eq!(is_draw(&input), expected);
+    /// Test cases for a draw, the board is full once no valid cell is left
+    #[test_case(9, true)]
+    #[test_case(8, false)]
+    fn test_is_draw(filled: usize, expected: bool) {
+        let mut grid = BoardGrid::default();
+        for id in 0..filled {
+            grid.fill(cell_coord(id), PlayerTag::X);
+        }
+        assert_eq!(grid.is_full(), expected);
     }
 
     fn set_winners() -> Vec<[(u32, u32); 3]> {
@@ -227,7 +250,13 @@ mod tests {
     #[test_case(vec![CellState::Filled(PlayerTag::X), CellState::Valid, CellState::Valid, CellState::Filled(PlayerTag::X), CellState::Valid, CellState::Valid, CellState::Filled(PlayerTag::X), CellState::Valid, CellState::Valid], PlayerTag::X, true)]
     #[test_case(vec![CellState::Filled(PlayerTag::X), CellState::Filled(PlayerTag::O), CellState::Filled(PlayerTag::X), CellState::Valid, CellState::Valid, CellState::Valid, CellState::Valid, CellState::Valid, CellState::Valid], PlayerTag::X, false)]
     fn test_is_winner(input: Vec<CellState>, player: PlayerTag, expected: bool) {
-        let mut winners = set_winners();
-        assert_eq!(is_winner(&input, 0, player, &mut winners), expected);
+        let winners = set_winners();
+        let mut grid = BoardGrid::default();
+        for (id, state) in input.iter().enumerate() {
+            if let CellState::Filled(tag) = state {
+                grid.fill(cell_coord(id), tag.clone());
+            }
+        }
+        assert_eq!(is_winner(&grid, player, &winners, &mut Vec::new()), expected);
     }
 }